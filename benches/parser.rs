@@ -0,0 +1,54 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use yotc::lexer::tokens::Token;
+use yotc::lexer::Lexer;
+use yotc::parser::Parser;
+
+/// Generate a program with many functions full of binary expressions.
+///
+/// # Arguments
+/// * `functions` - The number of functions to generate.
+fn synthetic_program(functions: usize) -> String {
+    let mut source = String::new();
+    for i in 0..functions {
+        source.push_str(&format!(
+            "@f{}[a, b] {{
+                @x = a * 2 + b / 3 - (a - b) * 4;
+                @y = x == a + b * 5 - 6;
+                ?[x < y + 1 * 2] -> x + y * a - b;
+                -> (x + 1) * (y - 2) / (a + b + 3) + f{}(x, y);
+            }}\n",
+            i,
+            i.saturating_sub(1)
+        ));
+    }
+    source.push_str("@main[] -> f0(1, 2);\n");
+    source
+}
+
+/// Lex a program into its tokens.
+///
+/// # Arguments
+/// * `source` - The raw program.
+fn lex(source: &str) -> Vec<Token> {
+    Lexer::from_text(source)
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap()
+}
+
+fn parse_benchmark(c: &mut Criterion) {
+    let source = synthetic_program(1000);
+    c.bench_function("parse 1000 functions", |b| {
+        b.iter_batched(
+            || lex(&source),
+            |tokens| {
+                Parser::new(tokens.into_iter().peekable())
+                    .parse_program()
+                    .unwrap()
+            },
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+criterion_group!(benches, parse_benchmark);
+criterion_main!(benches);
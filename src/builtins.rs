@@ -0,0 +1,17 @@
+/// The functions built into the compiler and their signatures.
+pub const BUILTIN_FUNCTIONS: &[(&str, &str)] = &[
+    ("print", "print[values...]"),
+    ("len", "len[string]"),
+    ("min", "min[a, b]"),
+    ("max", "max[a, b]"),
+    ("abs", "abs[a]"),
+    ("assert", "assert[condition, (message)]"),
+    ("puts", "puts[string]"),
+    ("putchar", "putchar[c]"),
+    ("getchar", "getchar[]"),
+    ("read_int", "read_int[]"),
+];
+
+/// The built-in functions that call the libc function with the same name, which an external
+/// declaration of that function doesn't shadow.
+pub const LIBC_BUILTIN_FUNCTIONS: &[&str] = &["puts", "putchar", "getchar"];
@@ -0,0 +1,247 @@
+use crate::lexer::tokens::Span;
+use std::fmt;
+
+/// How serious a diagnostic is.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// An error or warning about a program, which can be written as JSON for editors.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    /// The path of the program file, `-` for stdin.
+    pub file: String,
+    /// The position the diagnostic is about, `None` if it's unknown.
+    pub span: Option<Span>,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Diagnostic {
+    /// Write the diagnostic as a JSON object on a single line.
+    ///
+    /// The object has the fields `file`, `line`, `col`, `severity`, and `message`. The line and
+    /// column are `null` if the position is unknown.
+    pub fn to_json(&self) -> String {
+        let (line, col) = match self.span {
+            Some(span) => (span.line.to_string(), span.column.to_string()),
+            None => ("null".to_string(), "null".to_string()),
+        };
+        format!(
+            "{{\"file\":{},\"line\":{},\"col\":{},\"severity\":{},\"message\":{}}}",
+            json_string(&self.file),
+            line,
+            col,
+            json_string(&self.severity.to_string()),
+            json_string(&self.message)
+        )
+    }
+}
+
+/// ANSI escape codes for the parts of a rendered diagnostic.
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+const RESET: &str = "\x1b[0m";
+
+impl Diagnostic {
+    /// Render the diagnostic for a person, with the line of source it's about and a caret under
+    /// its column.
+    ///
+    /// ```text
+    /// error: Parsing: Expected `;` after variable declaration
+    ///  --> main.yot:2:9
+    ///   |
+    /// 2 | @a = 1 +
+    ///   |         ^
+    /// ```
+    ///
+    /// Only the message is rendered if the position is unknown, and the source line is left out if
+    /// it isn't in `source`.
+    ///
+    /// # Arguments
+    /// * `source` - The source of the program, `None` if it can't be read again.
+    /// * `tab_width` - The number of columns between tab stops that the column was counted with.
+    /// * `color` - Whether or not to color the output with ANSI escape codes.
+    pub fn render(&self, source: Option<&str>, tab_width: usize, color: bool) -> String {
+        let paint = |code: &'static str| if color { code } else { "" };
+        let severity_color = match self.severity {
+            Severity::Error => paint(RED),
+            Severity::Warning => paint(YELLOW),
+        };
+        let mut rendered = format!(
+            "{}{}{}: {}{}{}",
+            severity_color,
+            self.severity,
+            paint(RESET),
+            paint(BOLD),
+            self.message,
+            paint(RESET)
+        );
+
+        let span = match self.span {
+            Some(span) => span,
+            None => return rendered,
+        };
+        let gutter = " ".repeat(span.line.to_string().len());
+        rendered.push_str(&format!(
+            "\n{}{}-->{} {}:{}:{}",
+            gutter,
+            paint(BLUE),
+            paint(RESET),
+            self.file,
+            span.line,
+            span.column
+        ));
+
+        let line = match source.and_then(|s| s.lines().nth(span.line - 1)) {
+            Some(line) => line,
+            None => return rendered,
+        };
+        rendered.push_str(&format!(
+            "\n{} {}|{}\n{}{} |{} {}\n{} {}|{} {}{}^{}",
+            gutter,
+            paint(BLUE),
+            paint(RESET),
+            paint(BLUE),
+            span.line,
+            paint(RESET),
+            line,
+            gutter,
+            paint(BLUE),
+            paint(RESET),
+            caret_indent(line, span.column, tab_width),
+            severity_color,
+            paint(RESET)
+        ));
+        rendered
+    }
+}
+
+/// Get the whitespace that lines up a caret under a column of a line.
+///
+/// Tabs in the line are kept, so the caret lines up however wide the terminal shows tabs.
+///
+/// # Arguments
+/// * `line` - The line of source.
+/// * `column` - The column to point at, starting at 1.
+/// * `tab_width` - The number of columns between tab stops that the column was counted with.
+fn caret_indent(line: &str, column: usize, tab_width: usize) -> String {
+    let tab_width = tab_width.max(1);
+    let mut indent = String::new();
+    let mut current = 1;
+    let mut chars = line.chars();
+    while current < column {
+        match chars.next() {
+            Some('\t') => {
+                indent.push('\t');
+                current = (current - 1) / tab_width * tab_width + tab_width + 1;
+            }
+            // A position past the end of the line, such as a missing `;`, is padded with spaces
+            _ => {
+                indent.push(' ');
+                current += 1;
+            }
+        }
+    }
+    indent
+}
+
+/// Quote and escape a string as a JSON string.
+///
+/// # Arguments
+/// * `s` - The string.
+fn json_string(s: &str) -> String {
+    let mut json = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{Diagnostic, Severity};
+    use crate::lexer::tokens::Span;
+
+    #[test]
+    fn to_json() {
+        let diagnostic = Diagnostic {
+            file: "dir\\main.yot".to_string(),
+            span: Some(Span { line: 3, column: 7 }),
+            severity: Severity::Error,
+            message: "Unknown token `$`\n\"here\"".to_string(),
+        };
+        assert_eq!(
+            diagnostic.to_json(),
+            r#"{"file":"dir\\main.yot","line":3,"col":7,"severity":"error","message":"Unknown token `$`\n\"here\""}"#
+        );
+
+        let diagnostic = Diagnostic {
+            span: None,
+            ..diagnostic
+        };
+        assert!(diagnostic.to_json().contains(r#""line":null,"col":null"#));
+    }
+
+    #[test]
+    fn render() {
+        let diagnostic = Diagnostic {
+            file: "main.yot".to_string(),
+            span: Some(Span { line: 2, column: 8 }),
+            severity: Severity::Error,
+            message: "Parsing: Expected an expression".to_string(),
+        };
+        assert_eq!(
+            diagnostic.render(Some("@main[] {\n    @a = ;\n}"), 1, false),
+            "error: Parsing: Expected an expression
+ --> main.yot:2:8
+  |
+2 |     @a = ;
+  |        ^"
+        );
+
+        // Tabs are kept in front of the caret, however many columns they were counted as
+        let diagnostic = Diagnostic {
+            span: Some(Span { line: 1, column: 9 }),
+            ..diagnostic
+        };
+        assert!(diagnostic
+            .render(Some("\t@a = ;"), 4, false)
+            .ends_with("\n  | \t    ^"));
+
+        let colored = diagnostic.render(Some("\t@a = ;"), 4, true);
+        assert!(colored.starts_with("\x1b[1;31merror\x1b[0m"));
+
+        let diagnostic = Diagnostic {
+            span: None,
+            ..diagnostic
+        };
+        assert_eq!(
+            diagnostic.render(None, 1, false),
+            "error: Parsing: Expected an expression"
+        );
+    }
+}
@@ -0,0 +1,490 @@
+use crate::builtins::BUILTIN_FUNCTIONS;
+use crate::c_str;
+use crate::generator::Generator;
+use crate::parser::expression::Expression;
+use crate::Result;
+use llvm_sys::core;
+use llvm_sys::prelude::LLVMValueRef;
+use llvm_sys::{LLVMIntPredicate, LLVMRealPredicate, LLVMTypeKind};
+use log::trace;
+
+/// The built-in functions that don't call libc, which are the only ones available to
+/// freestanding programs.
+const FREESTANDING_BUILTIN_FUNCTIONS: &[&str] = &["min", "max", "abs"];
+
+impl Generator {
+    /// Generate a call to a built-in function.
+    ///
+    /// `None` is returned if the function isn't a built-in, built-ins are disabled, or the
+    /// program defines its own function with the same name.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the called function.
+    /// * `args` - The arguments of the call.
+    pub unsafe fn gen_builtin_call(
+        &self,
+        name: &str,
+        args: &[Expression],
+    ) -> Result<Option<LLVMValueRef>> {
+        if !self.is_builtin(name) {
+            return Ok(None);
+        }
+        if self.options.freestanding && !FREESTANDING_BUILTIN_FUNCTIONS.contains(&name) {
+            return Err(format!(
+                "Built-in `{}` calls libc, which freestanding programs don't have",
+                name
+            ));
+        }
+
+        match name {
+            "print" => self.gen_print_call(args).map(Some),
+            "len" => self.gen_len_call(args).map(Some),
+            "min" | "max" => self.gen_min_max_call(name, args).map(Some),
+            "abs" => self.gen_abs_call(args).map(Some),
+            "assert" => self.gen_assert_call(args).map(Some),
+            "puts" => self.gen_puts_call(args).map(Some),
+            "putchar" => self.gen_putchar_call(args).map(Some),
+            "getchar" => self.gen_getchar_call(args).map(Some),
+            "read_int" => self.gen_read_int_call(args).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// Check if a call to a function calls a built-in, rather than a function of the program.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the called function.
+    pub unsafe fn is_builtin(&self, name: &str) -> bool {
+        // Every function of the program is declared before any body is generated, but overloads
+        // are declared with mangled names
+        self.options.builtins
+            && BUILTIN_FUNCTIONS.iter().any(|(b, _)| *b == name)
+            && core::LLVMGetNamedFunction(self.module, c_str!(name)).is_null()
+            && !self.program.is_overloaded(name)
+    }
+
+    /// Generate a call to `printf` that prints integers separated by spaces followed by a newline.
+    ///
+    /// # Arguments
+    /// * `args` - The arguments of the call.
+    unsafe fn gen_print_call(&self, args: &[Expression]) -> Result<LLVMValueRef> {
+        trace!("Generating print call");
+        if args.is_empty() {
+            return Err("Built-in `print` expects at least 1 argument, got 0".to_string());
+        }
+
+        let mut placeholders = Vec::new();
+        let mut values = Vec::new();
+        for arg in args {
+            let mut value = self.gen_expression(arg)?;
+            let value_type = core::LLVMTypeOf(value);
+            if core::LLVMGetTypeKind(value_type) != LLVMTypeKind::LLVMIntegerTypeKind {
+                return Err("Built-in `print` expects integers".to_string());
+            }
+
+            if core::LLVMGetIntTypeWidth(value_type) > 32 {
+                placeholders.push("%ld");
+            } else if self.is_unsigned(arg) {
+                placeholders.push("%u");
+            } else {
+                // Variadic arguments smaller than an int are promoted to an int in C
+                value = core::LLVMBuildSExt(self.builder, value, self.i32_type(), c_str!(""));
+                placeholders.push("%d");
+            }
+            values.push(value);
+        }
+
+        let format = self.gen_pooled_string(&format!("{}\n", placeholders.join(" ")));
+        let printf = self.get_or_declare_function(
+            "printf",
+            self.i32_type(),
+            &mut [self.i8_ptr_type()],
+            true,
+        );
+        let mut llvm_args = vec![format];
+        llvm_args.extend(values);
+        Ok(core::LLVMBuildCall(
+            self.builder,
+            printf,
+            llvm_args.as_mut_ptr(),
+            llvm_args.len() as u32,
+            c_str!(""),
+        ))
+    }
+
+    /// Generate a compare and select that picks the smaller or larger of two numbers.
+    ///
+    /// # Arguments
+    /// * `name` - `min` or `max`.
+    /// * `args` - The arguments of the call.
+    unsafe fn gen_min_max_call(&self, name: &str, args: &[Expression]) -> Result<LLVMValueRef> {
+        trace!("Generating {} call", name);
+        if args.len() != 2 {
+            return Err(format!(
+                "Built-in `{}` expects 2 arguments, got {}",
+                name,
+                args.len()
+            ));
+        }
+
+        let mut a = self.gen_expression(&args[0])?;
+        let mut b = self.gen_expression(&args[1])?;
+        let (a_type, b_type) = (core::LLVMTypeOf(a), core::LLVMTypeOf(b));
+        let is_unsigned = self.is_unsigned(&args[0]) || self.is_unsigned(&args[1]);
+        if self.int_width(a_type) < self.int_width(b_type) {
+            a = self.gen_int_conversion(a, b_type, self.is_unsigned(&args[0]));
+        } else if self.int_width(b_type) < self.int_width(a_type) {
+            b = self.gen_int_conversion(b, a_type, self.is_unsigned(&args[1]));
+        }
+
+        let is_less = match core::LLVMGetTypeKind(core::LLVMTypeOf(a)) {
+            LLVMTypeKind::LLVMIntegerTypeKind
+                if core::LLVMGetTypeKind(core::LLVMTypeOf(b))
+                    == LLVMTypeKind::LLVMIntegerTypeKind =>
+            {
+                let predicate = if is_unsigned {
+                    LLVMIntPredicate::LLVMIntULT
+                } else {
+                    LLVMIntPredicate::LLVMIntSLT
+                };
+                core::LLVMBuildICmp(self.builder, predicate, a, b, c_str!(""))
+            }
+            LLVMTypeKind::LLVMDoubleTypeKind if core::LLVMTypeOf(b) == core::LLVMTypeOf(a) => {
+                core::LLVMBuildFCmp(
+                    self.builder,
+                    LLVMRealPredicate::LLVMRealOLT,
+                    a,
+                    b,
+                    c_str!(""),
+                )
+            }
+            _ => {
+                return Err(format!(
+                    "Built-in `{}` expects two integers or two floats",
+                    name
+                ))
+            }
+        };
+        let (if_less, otherwise) = if name == "min" { (a, b) } else { (b, a) };
+        Ok(core::LLVMBuildSelect(
+            self.builder,
+            is_less,
+            if_less,
+            otherwise,
+            c_str!(""),
+        ))
+    }
+
+    /// Generate a compare and select that gets the absolute value of a number.
+    ///
+    /// # Arguments
+    /// * `args` - The arguments of the call.
+    unsafe fn gen_abs_call(&self, args: &[Expression]) -> Result<LLVMValueRef> {
+        trace!("Generating abs call");
+        if args.len() != 1 {
+            return Err(format!(
+                "Built-in `abs` expects 1 argument, got {}",
+                args.len()
+            ));
+        }
+
+        let value = self.gen_expression(&args[0])?;
+        let value_type = core::LLVMTypeOf(value);
+        let (is_negative, negated) = match core::LLVMGetTypeKind(value_type) {
+            // Unsigned values are never negative
+            LLVMTypeKind::LLVMIntegerTypeKind if self.is_unsigned(&args[0]) => return Ok(value),
+            LLVMTypeKind::LLVMIntegerTypeKind => (
+                core::LLVMBuildICmp(
+                    self.builder,
+                    LLVMIntPredicate::LLVMIntSLT,
+                    value,
+                    core::LLVMConstNull(value_type),
+                    c_str!(""),
+                ),
+                core::LLVMBuildNeg(self.builder, value, c_str!("")),
+            ),
+            LLVMTypeKind::LLVMDoubleTypeKind => (
+                core::LLVMBuildFCmp(
+                    self.builder,
+                    LLVMRealPredicate::LLVMRealOLT,
+                    value,
+                    core::LLVMConstNull(value_type),
+                    c_str!(""),
+                ),
+                core::LLVMBuildFNeg(self.builder, value, c_str!("")),
+            ),
+            _ => return Err("Built-in `abs` expects a number".to_string()),
+        };
+        Ok(core::LLVMBuildSelect(
+            self.builder,
+            is_negative,
+            negated,
+            value,
+            c_str!(""),
+        ))
+    }
+
+    /// Generate a check that aborts the program if a condition is zero, after printing an error
+    /// with the optional message to stderr.
+    ///
+    /// Like a call to a void function, the value of the call can't be used.
+    ///
+    /// # Arguments
+    /// * `args` - The arguments of the call.
+    unsafe fn gen_assert_call(&self, args: &[Expression]) -> Result<LLVMValueRef> {
+        trace!("Generating assert call");
+        if args.is_empty() || args.len() > 2 {
+            return Err(format!(
+                "Built-in `assert` expects 1 or 2 arguments, got {}",
+                args.len()
+            ));
+        }
+
+        let condition = self.gen_expression(&args[0])?;
+        let condition_type = core::LLVMTypeOf(condition);
+        if core::LLVMGetTypeKind(condition_type) != LLVMTypeKind::LLVMIntegerTypeKind {
+            return Err("Built-in `assert` expects an integer condition".to_string());
+        }
+        let message = match args.get(1) {
+            Some(message) => {
+                let message = self.gen_expression(message)?;
+                if core::LLVMTypeOf(message) != self.i8_ptr_type() {
+                    return Err("Built-in `assert` expects a string message".to_string());
+                }
+                Some(message)
+            }
+            None => None,
+        };
+        let condition = core::LLVMBuildICmp(
+            self.builder,
+            LLVMIntPredicate::LLVMIntNE,
+            condition,
+            core::LLVMConstNull(condition_type),
+            c_str!(""),
+        );
+
+        let function = core::LLVMGetBasicBlockParent(core::LLVMGetInsertBlock(self.builder));
+        let fail_block =
+            core::LLVMAppendBasicBlockInContext(self.context, function, c_str!("assert_fail"));
+        let pass_block =
+            core::LLVMAppendBasicBlockInContext(self.context, function, c_str!("assert_pass"));
+        core::LLVMBuildCondBr(self.builder, condition, pass_block, fail_block);
+
+        core::LLVMPositionBuilderAtEnd(self.builder, fail_block);
+        let dprintf = self.get_or_declare_function(
+            "dprintf",
+            self.i32_type(),
+            &mut [self.i32_type(), self.i8_ptr_type()],
+            true,
+        );
+        let mut dprintf_args = match message {
+            Some(message) => vec![
+                self.gen_integer_literal(2, None)?,
+                self.gen_pooled_string("Assertion failed: %s\n"),
+                message,
+            ],
+            None => vec![
+                self.gen_integer_literal(2, None)?,
+                self.gen_pooled_string("Assertion failed\n"),
+            ],
+        };
+        core::LLVMBuildCall(
+            self.builder,
+            dprintf,
+            dprintf_args.as_mut_ptr(),
+            dprintf_args.len() as u32,
+            c_str!(""),
+        );
+        // Aborting doesn't flush stdout, which would lose what the program printed before
+        let fflush = self.get_or_declare_function(
+            "fflush",
+            self.i32_type(),
+            &mut [self.i8_ptr_type()],
+            false,
+        );
+        let mut fflush_args = [core::LLVMConstNull(self.i8_ptr_type())];
+        core::LLVMBuildCall(
+            self.builder,
+            fflush,
+            fflush_args.as_mut_ptr(),
+            fflush_args.len() as u32,
+            c_str!(""),
+        );
+        let abort = self.get_or_declare_function(
+            "abort",
+            core::LLVMVoidTypeInContext(self.context),
+            &mut [],
+            false,
+        );
+        let abort_call = core::LLVMBuildCall(self.builder, abort, [].as_mut_ptr(), 0, c_str!(""));
+        core::LLVMBuildUnreachable(self.builder);
+
+        core::LLVMPositionBuilderAtEnd(self.builder, pass_block);
+        Ok(abort_call)
+    }
+
+    /// Generate a call to `puts` that writes a string followed by a newline to stdout.
+    ///
+    /// # Arguments
+    /// * `args` - The arguments of the call.
+    unsafe fn gen_puts_call(&self, args: &[Expression]) -> Result<LLVMValueRef> {
+        trace!("Generating puts call");
+        if args.len() != 1 {
+            return Err(format!(
+                "Built-in `puts` expects 1 argument, got {}",
+                args.len()
+            ));
+        }
+
+        let string = self.gen_expression(&args[0])?;
+        if core::LLVMTypeOf(string) != self.i8_ptr_type() {
+            return Err("Built-in `puts` expects a string".to_string());
+        }
+
+        let puts =
+            self.get_or_declare_function("puts", self.i32_type(), &mut [self.i8_ptr_type()], false);
+        let mut llvm_args = [string];
+        Ok(core::LLVMBuildCall(
+            self.builder,
+            puts,
+            llvm_args.as_mut_ptr(),
+            llvm_args.len() as u32,
+            c_str!(""),
+        ))
+    }
+
+    /// Generate a call to `putchar` that writes a character to stdout.
+    ///
+    /// # Arguments
+    /// * `args` - The arguments of the call.
+    unsafe fn gen_putchar_call(&self, args: &[Expression]) -> Result<LLVMValueRef> {
+        trace!("Generating putchar call");
+        if args.len() != 1 {
+            return Err(format!(
+                "Built-in `putchar` expects 1 argument, got {}",
+                args.len()
+            ));
+        }
+
+        let c = self.gen_expression_of_type(&args[0], Some(self.i32_type()))?;
+        if core::LLVMTypeOf(c) != self.i32_type() {
+            return Err("Built-in `putchar` expects an integer".to_string());
+        }
+
+        let putchar =
+            self.get_or_declare_function("putchar", self.i32_type(), &mut [self.i32_type()], false);
+        let mut llvm_args = [c];
+        Ok(core::LLVMBuildCall(
+            self.builder,
+            putchar,
+            llvm_args.as_mut_ptr(),
+            llvm_args.len() as u32,
+            c_str!(""),
+        ))
+    }
+
+    /// Generate a call to `getchar` that reads a character from stdin, or -1 at the end of input.
+    ///
+    /// # Arguments
+    /// * `args` - The arguments of the call.
+    unsafe fn gen_getchar_call(&self, args: &[Expression]) -> Result<LLVMValueRef> {
+        trace!("Generating getchar call");
+        if !args.is_empty() {
+            return Err(format!(
+                "Built-in `getchar` expects 0 arguments, got {}",
+                args.len()
+            ));
+        }
+
+        let getchar = self.get_or_declare_function("getchar", self.i32_type(), &mut [], false);
+        Ok(core::LLVMBuildCall(
+            self.builder,
+            getchar,
+            [].as_mut_ptr(),
+            0,
+            c_str!(""),
+        ))
+    }
+
+    /// Generate a call to `scanf` that reads an integer from stdin as an i32.
+    ///
+    /// The result is 0 if no integer could be read.
+    ///
+    /// # Arguments
+    /// * `args` - The arguments of the call.
+    unsafe fn gen_read_int_call(&self, args: &[Expression]) -> Result<LLVMValueRef> {
+        trace!("Generating read_int call");
+        if !args.is_empty() {
+            return Err(format!(
+                "Built-in `read_int` expects 0 arguments, got {}",
+                args.len()
+            ));
+        }
+
+        // scanf leaves the value untouched when it fails, so it starts out as 0
+        let value = self.gen_local_variable("_", self.i32_type(), false, false);
+        core::LLVMBuildStore(
+            self.builder,
+            core::LLVMConstInt(self.i32_type(), 0, 0),
+            value,
+        );
+
+        let format = self.gen_pooled_string("%d");
+        let scanf =
+            self.get_or_declare_function("scanf", self.i32_type(), &mut [self.i8_ptr_type()], true);
+        let mut llvm_args = [format, value];
+        core::LLVMBuildCall(
+            self.builder,
+            scanf,
+            llvm_args.as_mut_ptr(),
+            llvm_args.len() as u32,
+            c_str!(""),
+        );
+        Ok(core::LLVMBuildLoad2(
+            self.builder,
+            self.i32_type(),
+            value,
+            c_str!(""),
+        ))
+    }
+
+    /// Generate a call to `strlen` that gets the length of a string as an i32.
+    ///
+    /// # Arguments
+    /// * `args` - The arguments of the call.
+    unsafe fn gen_len_call(&self, args: &[Expression]) -> Result<LLVMValueRef> {
+        trace!("Generating len call");
+        if args.len() != 1 {
+            return Err(format!(
+                "Built-in `len` expects 1 argument, got {}",
+                args.len()
+            ));
+        }
+
+        let string = self.gen_expression(&args[0])?;
+        if core::LLVMTypeOf(string) != self.i8_ptr_type() {
+            return Err("Built-in `len` expects a string".to_string());
+        }
+
+        let strlen = self.get_or_declare_function(
+            "strlen",
+            self.int_type(64),
+            &mut [self.i8_ptr_type()],
+            false,
+        );
+        let mut llvm_args = [string];
+        let len = core::LLVMBuildCall(
+            self.builder,
+            strlen,
+            llvm_args.as_mut_ptr(),
+            llvm_args.len() as u32,
+            c_str!(""),
+        );
+        Ok(core::LLVMBuildTrunc(
+            self.builder,
+            len,
+            self.i32_type(),
+            c_str!(""),
+        ))
+    }
+}
@@ -0,0 +1,176 @@
+use crate::c_str;
+use crate::generator::Generator;
+use llvm_sys::debuginfo::{
+    LLVMDIFlags, LLVMDWARFEmissionKind, LLVMDWARFSourceLanguage, LLVMDebugMetadataVersion,
+};
+use llvm_sys::prelude::{LLVMBool, LLVMDIBuilderRef, LLVMMetadataRef, LLVMValueRef};
+use llvm_sys::{core, debuginfo, LLVMModuleFlagBehavior};
+use log::trace;
+use std::path::Path;
+use std::ptr;
+
+extern "C" {
+    // LLVM 11 added `SysRoot` and `SDK` parameters to the end of this function, so it's declared
+    // with them here. Older versions of LLVM ignore the extra arguments.
+    fn LLVMDIBuilderCreateCompileUnit(
+        Builder: LLVMDIBuilderRef,
+        Lang: LLVMDWARFSourceLanguage,
+        FileRef: LLVMMetadataRef,
+        Producer: *const libc::c_char,
+        ProducerLen: libc::size_t,
+        isOptimized: LLVMBool,
+        Flags: *const libc::c_char,
+        FlagsLen: libc::size_t,
+        RuntimeVer: libc::c_uint,
+        SplitName: *const libc::c_char,
+        SplitNameLen: libc::size_t,
+        Kind: LLVMDWARFEmissionKind,
+        DWOId: libc::c_uint,
+        SplitDebugInlining: LLVMBool,
+        DebugInfoForProfiling: LLVMBool,
+        SysRoot: *const libc::c_char,
+        SysRootLen: libc::size_t,
+        SDK: *const libc::c_char,
+        SDKLen: libc::size_t,
+    ) -> LLVMMetadataRef;
+}
+
+/// The DWARF debug info builder of a module.
+pub struct DebugInfo {
+    /// LLVM debug info builder.
+    builder: LLVMDIBuilderRef,
+    /// The source file of the module.
+    file: LLVMMetadataRef,
+}
+
+impl DebugInfo {
+    /// Create a debug info builder with a compile unit for a source file.
+    ///
+    /// # Arguments
+    /// * `generator` - The generator of the module.
+    /// * `source_path` - The path to the source file.
+    pub unsafe fn new(generator: &Generator, source_path: &str) -> Self {
+        trace!("Creating debug info for `{}`", source_path);
+        let path = Path::new(source_path);
+        let file_name = path.file_name().unwrap().to_str().unwrap();
+        let directory = path.parent().and_then(Path::to_str).unwrap_or("");
+
+        let builder = debuginfo::LLVMCreateDIBuilder(generator.module);
+        let file = debuginfo::LLVMDIBuilderCreateFile(
+            builder,
+            c_str!(file_name),
+            file_name.len(),
+            c_str!(directory),
+            directory.len(),
+        );
+        let producer = concat!("yotc ", env!("CARGO_PKG_VERSION"));
+        LLVMDIBuilderCreateCompileUnit(
+            builder,
+            LLVMDWARFSourceLanguage::LLVMDWARFSourceLanguageC,
+            file,
+            c_str!(producer),
+            producer.len(),
+            false as i32,
+            c_str!(""),
+            0,
+            0,
+            c_str!(""),
+            0,
+            LLVMDWARFEmissionKind::LLVMDWARFEmissionKindFull,
+            0,
+            false as i32,
+            false as i32,
+            c_str!(""),
+            0,
+            c_str!(""),
+            0,
+        );
+
+        let version = core::LLVMValueAsMetadata(core::LLVMConstInt(
+            generator.i32_type(),
+            LLVMDebugMetadataVersion() as u64,
+            false as i32,
+        ));
+        let flag = "Debug Info Version";
+        core::LLVMAddModuleFlag(
+            generator.module,
+            LLVMModuleFlagBehavior::LLVMModuleFlagBehaviorWarning,
+            c_str!(flag),
+            flag.len(),
+            version,
+        );
+
+        DebugInfo { builder, file }
+    }
+}
+
+impl Generator {
+    /// Attach a subprogram to a function and point the locations of its instructions at the line
+    /// it's declared on.
+    ///
+    /// # Arguments
+    /// * `function` - The LLVM function.
+    /// * `name` - The name of the function.
+    /// * `line` - The line the function is declared on.
+    pub unsafe fn gen_function_debug_info(&self, function: LLVMValueRef, name: &str, line: usize) {
+        let debug_info = match &self.debug_info {
+            Some(debug_info) => debug_info,
+            None => return,
+        };
+
+        trace!("Generating debug info for `{}`", name);
+        let function_type = debuginfo::LLVMDIBuilderCreateSubroutineType(
+            debug_info.builder,
+            debug_info.file,
+            ptr::null_mut(),
+            0,
+            LLVMDIFlags::LLVMDIFlagZero,
+        );
+        let subprogram = debuginfo::LLVMDIBuilderCreateFunction(
+            debug_info.builder,
+            debug_info.file,
+            c_str!(name),
+            name.len(),
+            c_str!(name),
+            name.len(),
+            debug_info.file,
+            line as u32,
+            function_type,
+            false as i32,
+            true as i32,
+            line as u32,
+            LLVMDIFlags::LLVMDIFlagZero,
+            false as i32,
+        );
+        debuginfo::LLVMSetSubprogram(function, subprogram);
+
+        let location = debuginfo::LLVMDIBuilderCreateDebugLocation(
+            self.context,
+            line as u32,
+            0,
+            subprogram,
+            ptr::null_mut(),
+        );
+        core::LLVMSetCurrentDebugLocation2(self.builder, location);
+    }
+
+    /// Stop attaching debug locations to instructions, such as after the end of a function.
+    pub unsafe fn clear_debug_location(&self) {
+        if self.debug_info.is_some() {
+            core::LLVMSetCurrentDebugLocation2(self.builder, ptr::null_mut());
+        }
+    }
+
+    /// Finish the debug info of the module, which must be done before it's verified or emitted.
+    pub unsafe fn finalize_debug_info(&self) {
+        if let Some(debug_info) = &self.debug_info {
+            debuginfo::LLVMDIBuilderFinalize(debug_info.builder);
+        }
+    }
+}
+
+impl Drop for DebugInfo {
+    fn drop(&mut self) {
+        unsafe { debuginfo::LLVMDisposeDIBuilder(self.builder) }
+    }
+}
@@ -2,111 +2,286 @@ use crate::c_str;
 use crate::generator::Generator;
 use crate::lexer::tokens::Literal;
 use crate::parser::expression::Expression;
+use crate::parser::optimize::constant_value;
 use crate::Result;
 use llvm_sys::core;
-use llvm_sys::prelude::LLVMValueRef;
-use llvm_sys::LLVMIntPredicate;
+use llvm_sys::prelude::{LLVMTypeRef, LLVMValueRef};
+use llvm_sys::{LLVMAttributeFunctionIndex, LLVMIntPredicate, LLVMRealPredicate, LLVMTypeKind};
 use log::trace;
+use std::ptr;
 
 impl Generator {
     pub unsafe fn gen_expression(&self, expression: &Expression) -> Result<LLVMValueRef> {
+        self.gen_expression_of_type(expression, None)
+    }
+
+    /// Generate an expression, materializing integer literals as the expected type if given.
+    ///
+    /// Integers of another width are converted to the expected type, sign extended unless they
+    /// are unsigned, or truncated.
+    ///
+    /// # Arguments
+    /// * `expression` - The expression to generate.
+    /// * `expected_type` - The type the expression's value is expected to have.
+    pub unsafe fn gen_expression_of_type(
+        &self,
+        expression: &Expression,
+        expected_type: Option<LLVMTypeRef>,
+    ) -> Result<LLVMValueRef> {
+        let value = self.gen_unconverted_expression(expression, expected_type)?;
+        Ok(match expected_type {
+            Some(expected_type) => {
+                self.gen_int_conversion(value, expected_type, self.is_unsigned(expression))
+            }
+            None => value,
+        })
+    }
+
+    /// Generate an expression, whose value may not have the expected type.
+    ///
+    /// # Arguments
+    /// * `expression` - The expression to generate.
+    /// * `expected_type` - The type the expression's value is expected to have.
+    unsafe fn gen_unconverted_expression(
+        &self,
+        expression: &Expression,
+        expected_type: Option<LLVMTypeRef>,
+    ) -> Result<LLVMValueRef> {
         trace!("Generating expression");
         match expression {
-            Expression::LiteralExpression { value } => {
+            Expression::LiteralExpression { value, .. } => {
                 trace!("Generating literal expression: {:?}", value);
                 match value {
                     Literal::Integer(i) => {
                         trace!("Integer literal: {}", i);
-                        Ok(core::LLVMConstInt(self.i32_type(), *i as u64, false as i32))
+                        self.gen_integer_literal(*i, expected_type)
+                    }
+                    Literal::Char(c) => {
+                        trace!("Char literal: {:?}", c);
+                        self.gen_integer_literal(*c as i32, expected_type)
+                    }
+                    Literal::SizedInteger { value, bits } => {
+                        trace!("Sized integer literal: {}i{}", value, bits);
+                        Ok(core::LLVMConstInt(
+                            self.int_type(*bits),
+                            *value as u64,
+                            true as i32,
+                        ))
                     }
                     Literal::Str(s) => {
                         trace!("Str literal: {}", s);
-                        Ok(core::LLVMConstString(
-                            c_str!(s),
-                            s.len() as u32,
-                            false as i32,
-                        ))
+                        Ok(self.gen_pooled_string(s))
                     }
                 }
             }
 
-            Expression::ParenExpression { expression } => {
+            Expression::ParenExpression { expression, .. } => {
                 trace!("Generating paren expression");
-                self.gen_expression(expression)
+                self.gen_expression_of_type(expression, expected_type)
             }
 
-            Expression::VariableReferenceExpression { name } => {
+            Expression::VariableReferenceExpression { name, .. } => {
                 trace!("Generating variable reference expression: {}", name);
-                if let Some(var) = self.local_vars.borrow().get(name) {
-                    trace!("Local variable: {}", name);
+                if let Some((var, var_type)) = self.variable(name) {
+                    trace!("Variable: {}", name);
                     Ok(core::LLVMBuildLoad2(
                         self.builder,
-                        self.i32_type(),
-                        *var,
+                        var_type,
+                        var,
                         c_str!(""),
                     ))
                 } else {
-                    Err(format!("Unresolved variable reference `{}`", name))
+                    let function = core::LLVMGetNamedFunction(self.module, c_str!(name));
+                    if function.is_null() {
+                        Err(format!("Unresolved variable reference `{}`", name))
+                    } else {
+                        trace!("Function reference: {}", name);
+                        Ok(function)
+                    }
                 }
             }
 
-            Expression::FunctionCallExpression { name, args } => {
-                trace!("Generating function call expression: {}", name);
-                let mut llvm_args: Vec<LLVMValueRef> = Vec::new();
-                for arg in args {
-                    llvm_args.push(self.gen_expression(arg)?);
+            Expression::TupleExpression { elements, .. } => {
+                trace!("Generating tuple expression");
+                // Elements are materialized as the element types of an expected tuple
+                let mut expected_types = vec![None; elements.len()];
+                if let Some(t) = expected_type {
+                    if core::LLVMGetTypeKind(t) == LLVMTypeKind::LLVMStructTypeKind
+                        && core::LLVMCountStructElementTypes(t) as usize == elements.len()
+                    {
+                        let mut types = vec![ptr::null_mut(); elements.len()];
+                        core::LLVMGetStructElementTypes(t, types.as_mut_ptr());
+                        expected_types = types.into_iter().map(Some).collect();
+                    }
                 }
 
-                let function = core::LLVMGetNamedFunction(self.module, c_str!(name));
-                if function.is_null() {
-                    return Err(format!("Function `{}` doesn't exist", name));
+                let mut llvm_elements: Vec<LLVMValueRef> = Vec::new();
+                for (element, element_type) in elements.iter().zip(expected_types) {
+                    llvm_elements.push(self.gen_expression_of_type(element, element_type)?);
+                }
+                let mut element_types = llvm_elements
+                    .iter()
+                    .map(|e| core::LLVMTypeOf(*e))
+                    .collect::<Vec<_>>();
+
+                let mut tuple = core::LLVMGetUndef(self.tuple_type(&mut element_types));
+                for (i, element) in llvm_elements.into_iter().enumerate() {
+                    tuple = core::LLVMBuildInsertValue(
+                        self.builder,
+                        tuple,
+                        element,
+                        i as u32,
+                        c_str!(""),
+                    );
+                }
+                Ok(tuple)
+            }
+
+            Expression::ArrayLiteralExpression { elements, .. } => {
+                trace!("Generating array literal expression");
+                // An array is a pointer to its first element, so its elements have the pointee
+                // type, or the type of the first element if there is no expected type
+                let mut element_type = expected_type
+                    .filter(|t| core::LLVMGetTypeKind(*t) == LLVMTypeKind::LLVMPointerTypeKind)
+                    .map(|t| core::LLVMGetElementType(t));
+                let mut llvm_elements: Vec<LLVMValueRef> = Vec::new();
+                for element in elements {
+                    let value = self.gen_expression_of_type(element, element_type)?;
+                    element_type.get_or_insert(core::LLVMTypeOf(value));
+                    llvm_elements.push(value);
+                }
+
+                let element_type = element_type.unwrap_or_else(|| self.i32_type());
+                let array = self.gen_entry_alloca(element_type, Some(elements.len() as u32));
+
+                for (i, element) in llvm_elements.into_iter().enumerate() {
+                    let mut indices = [core::LLVMConstInt(self.i32_type(), i as u64, false as i32)];
+                    let element_ptr = core::LLVMBuildGEP2(
+                        self.builder,
+                        element_type,
+                        array,
+                        indices.as_mut_ptr(),
+                        1,
+                        c_str!(""),
+                    );
+                    core::LLVMBuildStore(self.builder, element, element_ptr);
+                }
+                Ok(array)
+            }
+
+            Expression::BlockExpression {
+                statements, tail, ..
+            } => {
+                trace!("Generating block expression");
+                self.push_scope();
+                for statement in statements {
+                    self.gen_statement(statement)?;
                 }
-                Ok(core::LLVMBuildCall(
+                let value = self.gen_expression_of_type(tail, expected_type)?;
+                self.pop_scope();
+                Ok(value)
+            }
+
+            Expression::IndexExpression { array, index, .. } => {
+                trace!("Generating index expression");
+                let (element_ptr, element_type) = self.gen_element_pointer(array, index)?;
+                Ok(core::LLVMBuildLoad2(
                     self.builder,
-                    function,
-                    llvm_args.as_mut_ptr(),
-                    args.len() as u32,
+                    element_type,
+                    element_ptr,
                     c_str!(""),
                 ))
             }
 
+            Expression::FieldAccessExpression {
+                expression, field, ..
+            } => {
+                trace!("Generating field access expression: {}", field);
+                if let Some((field_ptr, field_type)) = self.gen_field_pointer(expression, field)? {
+                    return Ok(core::LLVMBuildLoad2(
+                        self.builder,
+                        field_type,
+                        field_ptr,
+                        c_str!(""),
+                    ));
+                }
+                // A struct that isn't stored in a variable, such as one returned from a call
+                let value = self.gen_expression(expression)?;
+                let (index, _) = self.struct_field(core::LLVMTypeOf(value), field)?;
+                Ok(core::LLVMBuildExtractValue(
+                    self.builder,
+                    value,
+                    index,
+                    c_str!(""),
+                ))
+            }
+
+            Expression::FunctionCallExpression { name, args, .. } => {
+                let value = self.gen_function_call(name, args)?;
+                if core::LLVMGetTypeKind(core::LLVMTypeOf(value)) == LLVMTypeKind::LLVMVoidTypeKind
+                {
+                    return Err(format!("Void function `{}` doesn't return a value", name));
+                }
+                Ok(value)
+            }
+
             Expression::BinaryExpression {
                 op,
                 l_expression,
                 r_expression,
+                ..
             } => {
                 trace!("Generating binary expression");
 
-                let r = self.gen_expression(r_expression)?;
-
                 if op == "=" {
-                    if let Expression::VariableReferenceExpression { name } = l_expression.as_ref()
-                    {
-                        let local_vars_immut = self.local_vars.borrow();
-                        let var = match local_vars_immut.get(name) {
-                            Some(v) => v,
-                            None => {
-                                return Err(format!(
-                                    "Tried to assign to undefined variable `{}`",
-                                    name
-                                ))
-                            }
-                        };
-
-                        core::LLVMBuildStore(self.builder, r, *var);
-
-                        Ok(r)
-                    } else {
-                        Err("Expected variable reference on assignment".to_string())
-                    }
+                    let (ptr, value_type) = match self.gen_assignable_pointer(l_expression)? {
+                        Some(ptr) => ptr,
+                        None => {
+                            return Err(
+                                "Expected variable reference, index, or field on assignment"
+                                    .to_string(),
+                            )
+                        }
+                    };
+                    let r = self.gen_expression_of_type(r_expression, Some(value_type))?;
+                    core::LLVMBuildStore(self.builder, r, ptr);
+
+                    Ok(r)
                 } else {
-                    let l = self.gen_expression(l_expression)?;
+                    // Comparisons always result in an i32, so their operands have no expected type
+                    let operand_type = match &op[..] {
+                        "+" | "-" | "*" | "/" | "**" => expected_type,
+                        _ => None,
+                    };
+                    if op == "/" && constant_value(r_expression, false) == Some(0) {
+                        return Err("division by zero".to_string());
+                    }
+                    // Operands are evaluated in source order, so their side effects are too
+                    let mut l = self.gen_expression_of_type(l_expression, operand_type)?;
+                    let mut r = self.gen_expression_of_type(r_expression, operand_type)?;
+                    let is_unsigned =
+                        self.is_unsigned(l_expression) || self.is_unsigned(r_expression);
+
+                    // The narrower operand is extended to the width of the wider operand
+                    let (l_type, r_type) = (core::LLVMTypeOf(l), core::LLVMTypeOf(r));
+                    if self.int_width(l_type) < self.int_width(r_type) {
+                        l = self.gen_int_conversion(l, r_type, self.is_unsigned(l_expression));
+                    } else if self.int_width(r_type) < self.int_width(l_type) {
+                        r = self.gen_int_conversion(r, l_type, self.is_unsigned(r_expression));
+                    }
+                    if self.is_float(l) || self.is_float(r) {
+                        return self.gen_float_binary_op(op, l, r);
+                    }
 
                     match &op[..] {
                         "+" => Ok(core::LLVMBuildAdd(self.builder, l, r, c_str!(""))),
                         "-" => Ok(core::LLVMBuildSub(self.builder, l, r, c_str!(""))),
                         "*" => Ok(core::LLVMBuildMul(self.builder, l, r, c_str!(""))),
+                        "/" if is_unsigned => {
+                            Ok(core::LLVMBuildUDiv(self.builder, l, r, c_str!("")))
+                        }
                         "/" => Ok(core::LLVMBuildSDiv(self.builder, l, r, c_str!(""))),
+                        "**" => self.gen_power(l, r, is_unsigned),
                         "==" | "!=" | "<" | ">" | "<=" | ">=" => {
                             let cmp = {
                                 core::LLVMBuildICmp(
@@ -114,6 +289,10 @@ impl Generator {
                                     match &op[..] {
                                         "==" => LLVMIntPredicate::LLVMIntEQ,
                                         "!=" => LLVMIntPredicate::LLVMIntNE,
+                                        "<" if is_unsigned => LLVMIntPredicate::LLVMIntULT,
+                                        ">" if is_unsigned => LLVMIntPredicate::LLVMIntUGT,
+                                        "<=" if is_unsigned => LLVMIntPredicate::LLVMIntULE,
+                                        ">=" if is_unsigned => LLVMIntPredicate::LLVMIntUGE,
                                         "<" => LLVMIntPredicate::LLVMIntSLT,
                                         ">" => LLVMIntPredicate::LLVMIntSGT,
                                         "<=" => LLVMIntPredicate::LLVMIntSLE,
@@ -141,17 +320,679 @@ impl Generator {
                 }
             }
 
-            Expression::UnaryExpression { op, expression } => {
+            Expression::UnaryExpression { op, expression, .. } => {
                 trace!("Generating unary expression");
                 match &op[..] {
-                    "-" => Ok(core::LLVMBuildNeg(
-                        self.builder,
-                        self.gen_expression(expression)?,
-                        c_str!(""),
-                    )),
+                    "-" => {
+                        let value = self.gen_expression_of_type(expression, expected_type)?;
+                        if self.is_float(value) {
+                            Ok(core::LLVMBuildFNeg(self.builder, value, c_str!("")))
+                        } else {
+                            Ok(core::LLVMBuildNeg(self.builder, value, c_str!("")))
+                        }
+                    }
+                    "&" => match self.gen_assignable_pointer(expression)? {
+                        Some((ptr, _)) => Ok(ptr),
+                        None => {
+                            Err("Expected variable reference, index, or field on `&`".to_string())
+                        }
+                    },
+                    "*" => {
+                        let (ptr, value_type) = self.gen_dereferenced_pointer(expression)?;
+                        Ok(core::LLVMBuildLoad2(
+                            self.builder,
+                            value_type,
+                            ptr,
+                            c_str!(""),
+                        ))
+                    }
                     _ => Err("Misidentified unary expression".to_string()),
                 }
             }
+
+            Expression::SizeOfExpression { target_type, .. } => {
+                trace!("Generating sizeof expression: {}", target_type);
+                let size = self.store_size(self.llvm_type(target_type))?;
+                Ok(core::LLVMConstInt(self.int_type(64), size, 0))
+            }
+
+            Expression::CastExpression {
+                expression,
+                target_type,
+                ..
+            } => {
+                trace!("Generating cast expression: {}", target_type);
+                let value = self.gen_expression(expression)?;
+                let llvm_type = self.llvm_type(target_type);
+                let is_float = |t| core::LLVMGetTypeKind(t) == LLVMTypeKind::LLVMDoubleTypeKind;
+                let value_type = core::LLVMTypeOf(value);
+
+                match (
+                    self.int_width(value_type) > 0,
+                    self.int_width(llvm_type) > 0,
+                ) {
+                    (true, true) => {
+                        Ok(self.gen_int_conversion(value, llvm_type, self.is_unsigned(expression)))
+                    }
+                    (true, false) if is_float(llvm_type) => Ok(if self.is_unsigned(expression) {
+                        core::LLVMBuildUIToFP(self.builder, value, llvm_type, c_str!(""))
+                    } else {
+                        core::LLVMBuildSIToFP(self.builder, value, llvm_type, c_str!(""))
+                    }),
+                    (false, true) if is_float(value_type) => Ok(if target_type.is_unsigned() {
+                        core::LLVMBuildFPToUI(self.builder, value, llvm_type, c_str!(""))
+                    } else {
+                        core::LLVMBuildFPToSI(self.builder, value, llvm_type, c_str!(""))
+                    }),
+                    (false, false) if is_float(value_type) && is_float(llvm_type) => Ok(value),
+                    _ => Err(format!(
+                        "Can't cast to {}, only numbers can be cast",
+                        target_type
+                    )),
+                }
+            }
+
+            Expression::PostfixExpression { op, expression, .. } => {
+                trace!("Generating postfix expression");
+                let (ptr, value_type) = match self.gen_assignable_pointer(expression)? {
+                    Some(ptr) => ptr,
+                    None => {
+                        return Err(format!(
+                            "Expected variable reference, index, or field on `{}`",
+                            op
+                        ))
+                    }
+                };
+                if core::LLVMGetTypeKind(value_type) != LLVMTypeKind::LLVMIntegerTypeKind {
+                    return Err(format!("`{}` can only be applied to integers", op));
+                }
+
+                let value = core::LLVMBuildLoad2(self.builder, value_type, ptr, c_str!(""));
+                let one = core::LLVMConstInt(value_type, 1, false as i32);
+                let updated = match &op[..] {
+                    "++" => core::LLVMBuildAdd(self.builder, value, one, c_str!("")),
+                    "--" => core::LLVMBuildSub(self.builder, value, one, c_str!("")),
+                    _ => return Err("Misidentified postfix expression".to_string()),
+                };
+                core::LLVMBuildStore(self.builder, updated, ptr);
+
+                Ok(value)
+            }
+        }
+    }
+
+    /// Generate a call to a function, which may be void.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the function.
+    /// * `args` - The arguments of the call.
+    pub unsafe fn gen_function_call(
+        &self,
+        name: &str,
+        args: &[Expression],
+    ) -> Result<LLVMValueRef> {
+        trace!("Generating function call expression: {}", name);
+        if self.struct_types.borrow().contains_key(name) {
+            return self.gen_struct_construction(name, args);
+        }
+        if let Some(value) = self.gen_builtin_call(name, args)? {
+            return Ok(value);
+        }
+
+        let function = self.called_function(name, args.len());
+        if function.is_null() {
+            return Err(format!("Function `{}` doesn't exist", name));
+        }
+
+        let function_type = core::LLVMGetElementType(core::LLVMTypeOf(function));
+        let param_count = core::LLVMCountParamTypes(function_type) as usize;
+        let is_variadic = core::LLVMIsFunctionVarArg(function_type) != 0;
+        if args.len() < param_count || (!is_variadic && args.len() > param_count) {
+            return Err(format!(
+                "Function `{}` expects {} arguments, got {}",
+                name,
+                param_count,
+                args.len()
+            ));
+        }
+        let mut param_types = vec![ptr::null_mut(); param_count];
+        core::LLVMGetParamTypes(function_type, param_types.as_mut_ptr());
+
+        let mut llvm_args: Vec<LLVMValueRef> = Vec::new();
+        for (arg, param_type) in args.iter().zip(param_types) {
+            let mut llvm_arg = self.gen_expression_of_type(arg, Some(param_type))?;
+            // Pointers such as function references are cast to the expected pointer type
+            if core::LLVMTypeOf(llvm_arg) != param_type
+                && core::LLVMGetTypeKind(core::LLVMTypeOf(llvm_arg))
+                    == LLVMTypeKind::LLVMPointerTypeKind
+                && core::LLVMGetTypeKind(param_type) == LLVMTypeKind::LLVMPointerTypeKind
+            {
+                llvm_arg = core::LLVMBuildBitCast(self.builder, llvm_arg, param_type, c_str!(""));
+            }
+            llvm_args.push(llvm_arg);
+        }
+        for arg in &args[param_count..] {
+            let mut llvm_arg = self.gen_expression(arg)?;
+            // Variadic arguments smaller than an int are promoted to an int in C
+            let arg_type = core::LLVMTypeOf(llvm_arg);
+            if core::LLVMGetTypeKind(arg_type) == LLVMTypeKind::LLVMIntegerTypeKind
+                && core::LLVMGetIntTypeWidth(arg_type) < 32
+            {
+                llvm_arg = if self.is_unsigned(arg) {
+                    core::LLVMBuildZExt(self.builder, llvm_arg, self.i32_type(), c_str!(""))
+                } else {
+                    core::LLVMBuildSExt(self.builder, llvm_arg, self.i32_type(), c_str!(""))
+                };
+            }
+            llvm_args.push(llvm_arg);
+        }
+
+        let call = core::LLVMBuildCall(
+            self.builder,
+            function,
+            llvm_args.as_mut_ptr(),
+            args.len() as u32,
+            c_str!(""),
+        );
+
+        // Code after a call to a `noreturn` function can't be reached, so the block ends there and
+        // the following code goes in a block without predecessors
+        let noreturn = core::LLVMGetEnumAttributeKindForName(c_str!("noreturn"), 8);
+        if !core::LLVMGetEnumAttributeAtIndex(function, LLVMAttributeFunctionIndex, noreturn)
+            .is_null()
+        {
+            core::LLVMBuildUnreachable(self.builder);
+            let parent = core::LLVMGetBasicBlockParent(core::LLVMGetInsertBlock(self.builder));
+            let after = core::LLVMAppendBasicBlockInContext(self.context, parent, c_str!("after"));
+            core::LLVMPositionBuilderAtEnd(self.builder, after);
+        }
+        Ok(call)
+    }
+
+    /// Generate a struct from the values of its fields.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the struct.
+    /// * `args` - The value of each field.
+    unsafe fn gen_struct_construction(
+        &self,
+        name: &str,
+        args: &[Expression],
+    ) -> Result<LLVMValueRef> {
+        trace!("Generating struct construction: {}", name);
+        let struct_type = self.struct_type(name);
+        let field_count = core::LLVMCountStructElementTypes(struct_type) as usize;
+        if args.len() != field_count {
+            return Err(format!(
+                "Struct `{}` has {} fields, got {} values",
+                name,
+                field_count,
+                args.len()
+            ));
+        }
+
+        let mut value = core::LLVMGetUndef(struct_type);
+        for (i, arg) in args.iter().enumerate() {
+            let field_type = core::LLVMStructGetTypeAtIndex(struct_type, i as u32);
+            let field = self.gen_expression_of_type(arg, Some(field_type))?;
+            value = core::LLVMBuildInsertValue(self.builder, value, field, i as u32, c_str!(""));
+        }
+        Ok(value)
+    }
+
+    /// Generate a number raised to a power.
+    ///
+    /// Integers are multiplied in a loop, where a negative exponent is treated as 0, and floats
+    /// use the `llvm.pow.f64` intrinsic.
+    ///
+    /// # Arguments
+    /// * `base` - The base.
+    /// * `exponent` - The exponent, of the same type as the base.
+    /// * `is_unsigned` - Whether or not the operands are unsigned integers.
+    unsafe fn gen_power(
+        &self,
+        base: LLVMValueRef,
+        exponent: LLVMValueRef,
+        is_unsigned: bool,
+    ) -> Result<LLVMValueRef> {
+        let value_type = core::LLVMTypeOf(base);
+        if core::LLVMTypeOf(exponent) != value_type {
+            return Err("Can't raise a number to a power of another type".to_string());
+        }
+        if core::LLVMGetTypeKind(value_type) == LLVMTypeKind::LLVMDoubleTypeKind {
+            let pow = self.get_or_declare_function(
+                "llvm.pow.f64",
+                value_type,
+                &mut [value_type, value_type],
+                false,
+            );
+            let mut args = [base, exponent];
+            return Ok(core::LLVMBuildCall(
+                self.builder,
+                pow,
+                args.as_mut_ptr(),
+                args.len() as u32,
+                c_str!(""),
+            ));
+        }
+        if core::LLVMGetTypeKind(value_type) != LLVMTypeKind::LLVMIntegerTypeKind {
+            return Err("Only numbers can be raised to a power".to_string());
+        }
+
+        let entry_block = core::LLVMGetInsertBlock(self.builder);
+        let function = core::LLVMGetBasicBlockParent(entry_block);
+        let condition_block =
+            core::LLVMAppendBasicBlockInContext(self.context, function, c_str!("pow_cond"));
+        let body_block =
+            core::LLVMAppendBasicBlockInContext(self.context, function, c_str!("pow_loop"));
+        let merge_block =
+            core::LLVMAppendBasicBlockInContext(self.context, function, c_str!("pow_merge"));
+        core::LLVMBuildBr(self.builder, condition_block);
+
+        core::LLVMPositionBuilderAtEnd(self.builder, condition_block);
+        let result = core::LLVMBuildPhi(self.builder, value_type, c_str!(""));
+        let remaining = core::LLVMBuildPhi(self.builder, value_type, c_str!(""));
+        let predicate = if is_unsigned {
+            LLVMIntPredicate::LLVMIntUGT
+        } else {
+            LLVMIntPredicate::LLVMIntSGT
+        };
+        let condition = core::LLVMBuildICmp(
+            self.builder,
+            predicate,
+            remaining,
+            core::LLVMConstNull(value_type),
+            c_str!(""),
+        );
+        core::LLVMBuildCondBr(self.builder, condition, body_block, merge_block);
+
+        core::LLVMPositionBuilderAtEnd(self.builder, body_block);
+        let next_result = core::LLVMBuildMul(self.builder, result, base, c_str!(""));
+        let next_remaining = core::LLVMBuildSub(
+            self.builder,
+            remaining,
+            core::LLVMConstInt(value_type, 1, 0),
+            c_str!(""),
+        );
+        core::LLVMBuildBr(self.builder, condition_block);
+
+        let mut result_values = [core::LLVMConstInt(value_type, 1, 0), next_result];
+        let mut remaining_values = [exponent, next_remaining];
+        let mut blocks = [entry_block, body_block];
+        core::LLVMAddIncoming(result, result_values.as_mut_ptr(), blocks.as_mut_ptr(), 2);
+        core::LLVMAddIncoming(
+            remaining,
+            remaining_values.as_mut_ptr(),
+            blocks.as_mut_ptr(),
+            2,
+        );
+
+        core::LLVMPositionBuilderAtEnd(self.builder, merge_block);
+        Ok(result)
+    }
+
+    /// Convert an integer to an integer type of another width, or else leave it as is.
+    ///
+    /// # Arguments
+    /// * `value` - The value to convert.
+    /// * `target_type` - The type to convert the value to.
+    /// * `is_unsigned` - Whether or not the value is zero extended instead of sign extended.
+    pub unsafe fn gen_int_conversion(
+        &self,
+        value: LLVMValueRef,
+        target_type: LLVMTypeRef,
+        is_unsigned: bool,
+    ) -> LLVMValueRef {
+        let width = self.int_width(core::LLVMTypeOf(value));
+        let target_width = self.int_width(target_type);
+        if width == 0 || target_width == 0 || width == target_width {
+            value
+        } else if width > target_width {
+            core::LLVMBuildTrunc(self.builder, value, target_type, c_str!(""))
+        } else if is_unsigned {
+            core::LLVMBuildZExt(self.builder, value, target_type, c_str!(""))
+        } else {
+            core::LLVMBuildSExt(self.builder, value, target_type, c_str!(""))
+        }
+    }
+
+    /// Get the width of an integer type, 0 if it isn't an integer type.
+    ///
+    /// # Arguments
+    /// * `llvm_type` - The type.
+    pub unsafe fn int_width(&self, llvm_type: LLVMTypeRef) -> u32 {
+        if core::LLVMGetTypeKind(llvm_type) == LLVMTypeKind::LLVMIntegerTypeKind {
+            core::LLVMGetIntTypeWidth(llvm_type)
+        } else {
+            0
+        }
+    }
+
+    /// Check if a value is an f64.
+    ///
+    /// # Arguments
+    /// * `value` - The LLVM value.
+    unsafe fn is_float(&self, value: LLVMValueRef) -> bool {
+        core::LLVMGetTypeKind(core::LLVMTypeOf(value)) == LLVMTypeKind::LLVMDoubleTypeKind
+    }
+
+    /// Generate a binary operation on two f64 values.
+    ///
+    /// Comparisons result in an i32 of 1 or 0, like comparisons of integers.
+    ///
+    /// # Arguments
+    /// * `op` - The binary operation.
+    /// * `l` - The left operand.
+    /// * `r` - The right operand.
+    unsafe fn gen_float_binary_op(
+        &self,
+        op: &str,
+        l: LLVMValueRef,
+        r: LLVMValueRef,
+    ) -> Result<LLVMValueRef> {
+        if core::LLVMTypeOf(l) != core::LLVMTypeOf(r) {
+            return Err(format!("Can't apply `{}` to an f64 and an integer", op));
+        }
+        let predicate = match op {
+            "+" => return Ok(core::LLVMBuildFAdd(self.builder, l, r, c_str!(""))),
+            "-" => return Ok(core::LLVMBuildFSub(self.builder, l, r, c_str!(""))),
+            "*" => return Ok(core::LLVMBuildFMul(self.builder, l, r, c_str!(""))),
+            "/" => return Ok(core::LLVMBuildFDiv(self.builder, l, r, c_str!(""))),
+            "**" => return self.gen_power(l, r, false),
+            "==" => LLVMRealPredicate::LLVMRealOEQ,
+            // NaN isn't equal to anything, including itself
+            "!=" => LLVMRealPredicate::LLVMRealUNE,
+            "<" => LLVMRealPredicate::LLVMRealOLT,
+            ">" => LLVMRealPredicate::LLVMRealOGT,
+            "<=" => LLVMRealPredicate::LLVMRealOLE,
+            ">=" => LLVMRealPredicate::LLVMRealOGE,
+            _ => return Err("Misidentified binary expression".to_string()),
+        };
+        let cmp = core::LLVMBuildFCmp(self.builder, predicate, l, r, c_str!(""));
+        Ok(core::LLVMBuildZExt(
+            self.builder,
+            cmp,
+            self.i32_type(),
+            c_str!(""),
+        ))
+    }
+
+    /// Check if the value of an expression is an unsigned integer, which changes how it is divided
+    /// and compared.
+    ///
+    /// An arithmetic expression is unsigned if either of its operands is, and literals are signed.
+    ///
+    /// # Arguments
+    /// * `expression` - The expression to check.
+    pub fn is_unsigned(&self, expression: &Expression) -> bool {
+        match expression {
+            Expression::LiteralExpression { .. }
+            | Expression::TupleExpression { .. }
+            | Expression::ArrayLiteralExpression { .. } => false,
+            Expression::VariableReferenceExpression { name, .. } => self
+                .find_variable(name)
+                .is_some_and(|variable| variable.is_unsigned),
+            Expression::FunctionCallExpression { name, args, .. } => {
+                // Only the numeric built-ins return a value of the same type as their arguments
+                let is_numeric_builtin =
+                    matches!(&name[..], "min" | "max" | "abs") && unsafe { self.is_builtin(name) };
+                let function = unsafe { self.called_function(name, args.len()) };
+                let returns_unsigned = !function.is_null()
+                    && self
+                        .unsigned_functions
+                        .borrow()
+                        .contains(&unsafe { self.value_name(function) });
+                returns_unsigned
+                    || is_numeric_builtin && args.iter().any(|arg| self.is_unsigned(arg))
+            }
+            Expression::BinaryExpression {
+                op,
+                l_expression,
+                r_expression,
+                ..
+            } => match &op[..] {
+                "=" => self.is_unsigned(l_expression),
+                "+" | "-" | "*" | "/" | "**" => {
+                    self.is_unsigned(l_expression) || self.is_unsigned(r_expression)
+                }
+                // Comparisons result in an i32
+                _ => false,
+            },
+            Expression::ParenExpression { expression, .. }
+            | Expression::UnaryExpression { expression, .. }
+            | Expression::PostfixExpression { expression, .. } => self.is_unsigned(expression),
+            Expression::IndexExpression { array, .. } => self.is_unsigned(array),
+            Expression::FieldAccessExpression {
+                expression, field, ..
+            } => unsafe {
+                self.static_type(expression)
+                    .and_then(|t| self.struct_field(t, field).ok())
+                    .is_some_and(|(_, field_type)| field_type.is_unsigned())
+            },
+            Expression::CastExpression { target_type, .. } => target_type.is_unsigned(),
+            Expression::SizeOfExpression { .. } => false,
+            Expression::BlockExpression { tail, .. } => self.is_unsigned(tail),
+        }
+    }
+
+    /// Get the LLVM type of an expression without generating it, `None` if it isn't known.
+    ///
+    /// Only the expressions whose value can be a struct are handled.
+    ///
+    /// # Arguments
+    /// * `expression` - The expression.
+    unsafe fn static_type(&self, expression: &Expression) -> Option<LLVMTypeRef> {
+        match expression {
+            Expression::VariableReferenceExpression { name, .. } => {
+                self.variable(name).map(|(_, var_type)| var_type)
+            }
+            Expression::ParenExpression { expression, .. } => self.static_type(expression),
+            Expression::IndexExpression { array: pointer, .. } => self.static_pointee_type(pointer),
+            Expression::UnaryExpression { op, expression, .. } if op == "*" => {
+                self.static_pointee_type(expression)
+            }
+            Expression::FieldAccessExpression {
+                expression, field, ..
+            } => {
+                let struct_type = self.static_type(expression)?;
+                let (index, _) = self.struct_field(struct_type, field).ok()?;
+                Some(core::LLVMStructGetTypeAtIndex(struct_type, index))
+            }
+            Expression::FunctionCallExpression { name, args, .. } => {
+                if self.struct_types.borrow().contains_key(name) {
+                    return Some(self.struct_type(name));
+                }
+                let function = self.called_function(name, args.len());
+                if function.is_null() {
+                    return None;
+                }
+                let function_type = core::LLVMGetElementType(core::LLVMTypeOf(function));
+                Some(core::LLVMGetReturnType(function_type))
+            }
+            _ => None,
+        }
+    }
+
+    /// Get the LLVM type an array or pointer points to without generating it, `None` if it isn't
+    /// known.
+    ///
+    /// # Arguments
+    /// * `pointer` - The array or pointer expression.
+    unsafe fn static_pointee_type(&self, pointer: &Expression) -> Option<LLVMTypeRef> {
+        let pointer_type = self.static_type(pointer)?;
+        match core::LLVMGetTypeKind(pointer_type) {
+            LLVMTypeKind::LLVMPointerTypeKind => Some(core::LLVMGetElementType(pointer_type)),
+            _ => None,
+        }
+    }
+
+    /// Get a pointer to a field of a struct and the type of the field, `None` if the struct isn't
+    /// stored in a variable or array.
+    ///
+    /// # Arguments
+    /// * `expression` - The struct expression.
+    /// * `field` - The name of the field.
+    unsafe fn gen_field_pointer(
+        &self,
+        expression: &Expression,
+        field: &str,
+    ) -> Result<Option<(LLVMValueRef, LLVMTypeRef)>> {
+        let (struct_ptr, struct_type) = match expression {
+            Expression::VariableReferenceExpression { name, .. } => match self.variable(name) {
+                Some(variable) => variable,
+                None => return Ok(None),
+            },
+            Expression::ParenExpression { expression, .. } => {
+                return self.gen_field_pointer(expression, field)
+            }
+            Expression::IndexExpression { array, index, .. } => {
+                self.gen_element_pointer(array, index)?
+            }
+            Expression::UnaryExpression { op, expression, .. } if op == "*" => {
+                self.gen_dereferenced_pointer(expression)?
+            }
+            Expression::FieldAccessExpression {
+                expression, field, ..
+            } => match self.gen_field_pointer(expression, field)? {
+                Some(field_ptr) => field_ptr,
+                None => return Ok(None),
+            },
+            _ => return Ok(None),
+        };
+
+        let (index, _) = self.struct_field(struct_type, field)?;
+        let field_ptr =
+            core::LLVMBuildStructGEP2(self.builder, struct_type, struct_ptr, index, c_str!(""));
+        Ok(Some((
+            field_ptr,
+            core::LLVMStructGetTypeAtIndex(struct_type, index),
+        )))
+    }
+
+    /// Get a pointer to the value of a variable, array element, field, or dereferenced pointer
+    /// that can be assigned to, and the type of the value, `None` for any other expression.
+    ///
+    /// # Arguments
+    /// * `expression` - The expression.
+    unsafe fn gen_assignable_pointer(
+        &self,
+        expression: &Expression,
+    ) -> Result<Option<(LLVMValueRef, LLVMTypeRef)>> {
+        match expression {
+            Expression::VariableReferenceExpression { name, .. } => {
+                self.check_assignable(name)?;
+                match self.variable(name) {
+                    Some(variable) => Ok(Some(variable)),
+                    None => Err(format!("Tried to assign to undefined variable `{}`", name)),
+                }
+            }
+            Expression::IndexExpression { array, index, .. } => {
+                Ok(Some(self.gen_element_pointer(array, index)?))
+            }
+            Expression::FieldAccessExpression {
+                expression, field, ..
+            } => {
+                // The fields of a const struct are as constant as the struct
+                let mut root = expression.as_ref();
+                while let Expression::FieldAccessExpression { expression, .. }
+                | Expression::ParenExpression { expression, .. } = root
+                {
+                    root = expression;
+                }
+                if let Expression::VariableReferenceExpression { name, .. } = root {
+                    self.check_assignable(name)?;
+                }
+
+                match self.gen_field_pointer(expression, field)? {
+                    Some(field_ptr) => Ok(Some(field_ptr)),
+                    None => Err(format!(
+                        "Can't assign to field `{}` of a struct that isn't stored in a variable",
+                        field
+                    )),
+                }
+            }
+            Expression::UnaryExpression { op, expression, .. } if op == "*" => {
+                Ok(Some(self.gen_dereferenced_pointer(expression)?))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Generate a pointer and get the type of the value it points to.
+    ///
+    /// # Arguments
+    /// * `pointer` - The pointer expression.
+    unsafe fn gen_dereferenced_pointer(
+        &self,
+        pointer: &Expression,
+    ) -> Result<(LLVMValueRef, LLVMTypeRef)> {
+        let ptr = self.gen_expression(pointer)?;
+        let ptr_type = core::LLVMTypeOf(ptr);
+        if core::LLVMGetTypeKind(ptr_type) != LLVMTypeKind::LLVMPointerTypeKind {
+            return Err("Tried to dereference a non-pointer value".to_string());
+        }
+        Ok((ptr, core::LLVMGetElementType(ptr_type)))
+    }
+
+    /// Get a pointer to an element of an array and the type of the element.
+    ///
+    /// # Arguments
+    /// * `array` - The array expression.
+    /// * `index` - The index of the element.
+    unsafe fn gen_element_pointer(
+        &self,
+        array: &Expression,
+        index: &Expression,
+    ) -> Result<(LLVMValueRef, LLVMTypeRef)> {
+        let array = self.gen_expression(array)?;
+        let array_type = core::LLVMTypeOf(array);
+        if core::LLVMGetTypeKind(array_type) != LLVMTypeKind::LLVMPointerTypeKind {
+            return Err("Tried to index a non-array value".to_string());
+        }
+
+        let element_type = core::LLVMGetElementType(array_type);
+        let mut indices = [self.gen_expression(index)?];
+        let element_ptr = core::LLVMBuildGEP2(
+            self.builder,
+            element_type,
+            array,
+            indices.as_mut_ptr(),
+            1,
+            c_str!(""),
+        );
+        Ok((element_ptr, element_type))
+    }
+
+    /// Generate an integer literal as the expected type, or i32 if there isn't one.
+    ///
+    /// # Arguments
+    /// * `value` - The value of the literal.
+    /// * `expected_type` - The type the literal is expected to have.
+    pub unsafe fn gen_integer_literal(
+        &self,
+        value: i32,
+        expected_type: Option<LLVMTypeRef>,
+    ) -> Result<LLVMValueRef> {
+        let llvm_type = match expected_type {
+            Some(t) if core::LLVMGetTypeKind(t) == LLVMTypeKind::LLVMIntegerTypeKind => t,
+            Some(t) if core::LLVMGetTypeKind(t) == LLVMTypeKind::LLVMDoubleTypeKind => {
+                return Ok(core::LLVMConstReal(t, value as f64));
+            }
+            _ => self.i32_type(),
+        };
+
+        let bits = core::LLVMGetIntTypeWidth(llvm_type);
+        if bits < 32 {
+            // Every bit above the sign bit must match the sign bit for the value to fit
+            let high_bits = value >> (bits - 1);
+            if high_bits != 0 && high_bits != -1 {
+                return Err(format!(
+                    "Integer literal {} doesn't fit in an i{}",
+                    value, bits
+                ));
+            }
         }
+        trace!("Integer literal type: i{}", bits);
+        Ok(core::LLVMConstInt(llvm_type, value as u64, true as i32))
     }
 }
@@ -1,76 +1,185 @@
 use crate::c_str;
 use crate::generator::Generator;
-use crate::parser::function::Function;
+use crate::parser::function::{Function, FunctionAttribute};
+use crate::parser::statement::Statement;
+use crate::parser::types::Type;
 use crate::Result;
-use llvm_sys::core;
-use log::{info, trace};
+use llvm_sys::analysis::LLVMVerifierFailureAction;
+use llvm_sys::{analysis, core, LLVMAttributeFunctionIndex};
+use log::trace;
+use std::collections::BTreeMap;
 
 impl Generator {
-    pub unsafe fn gen_function(&self, function: &Function) -> Result<()> {
-        trace!("Generating function");
+    /// Declare a function in the module without generating its body.
+    ///
+    /// # Arguments
+    /// * `function` - The function to declare.
+    /// * `symbol` - The name of the function in the module, mangled if it is overloaded.
+    pub unsafe fn gen_function_prototype(&self, function: &Function, symbol: &str) -> Result<()> {
+        trace!("Generating function prototype");
 
-        let args = match function {
+        let (attributes, args, return_type, is_variadic) = match function {
             Function::RegularFunction {
-                name: _,
+                attributes,
                 args,
-                statement: _,
-            } => args,
-            Function::ExternalFunction { name: _, args } => args,
-        };
-
-        let name = match function {
-            Function::RegularFunction {
-                name,
-                args: _,
-                statement: _,
-            } => name,
-            Function::ExternalFunction { name, args: _ } => name,
+                return_type,
+                ..
+            } => (attributes, args, return_type, false),
+            Function::ExternalFunction {
+                attributes,
+                args,
+                return_type,
+                is_variadic,
+                ..
+            } => (attributes, args, return_type, *is_variadic),
         };
-        // All args are i32 for now
-        let mut arg_types = vec![self.i32_type(); args.len()];
+        let mut arg_types = args
+            .iter()
+            .map(|arg| self.llvm_type(&arg.arg_type))
+            .collect::<Vec<_>>();
 
         // Create function
         let llvm_function = core::LLVMAddFunction(
             self.module,
-            c_str!(name),
+            c_str!(symbol),
             core::LLVMFunctionType(
-                self.i32_type(),
+                match return_type {
+                    Some(return_type) => self.llvm_type(return_type),
+                    None => core::LLVMVoidTypeInContext(self.context),
+                },
                 arg_types.as_mut_ptr(),
                 args.len() as u32,
-                0,
+                is_variadic as i32,
             ),
         );
 
+        for attribute in attributes {
+            let attribute_name = match attribute {
+                FunctionAttribute::Inline => "alwaysinline",
+                FunctionAttribute::NoInline => "noinline",
+                FunctionAttribute::NoReturn => "noreturn",
+            };
+            let kind =
+                core::LLVMGetEnumAttributeKindForName(c_str!(attribute_name), attribute_name.len());
+            core::LLVMAddAttributeAtIndex(
+                llvm_function,
+                LLVMAttributeFunctionIndex,
+                core::LLVMCreateEnumAttribute(self.context, kind, 0),
+            );
+        }
+
+        if return_type.as_ref().is_some_and(Type::is_unsigned) {
+            self.unsigned_functions
+                .borrow_mut()
+                .insert(symbol.to_string());
+        }
+
+        for (i, arg) in args.iter().enumerate() {
+            // Set arg name in function prototype
+            let param = core::LLVMGetParam(llvm_function, i as u32);
+            core::LLVMSetValueName2(param, c_str!(arg.name), arg.name.len());
+        }
+
+        Ok(())
+    }
+
+    /// Generate the body of a function that has already been declared.
+    ///
+    /// # Arguments
+    /// * `function` - The function to generate.
+    /// * `symbol` - The name of the function in the module, mangled if it is overloaded.
+    pub unsafe fn gen_function(&self, function: &Function, symbol: &str) -> Result<()> {
+        trace!("Generating function");
+
         if let Function::RegularFunction {
-            name: _,
-            args: _,
+            name,
+            line,
+            attributes,
+            args,
+            return_type,
             statement,
+            ..
         } = function
         {
+            let llvm_function = core::LLVMGetNamedFunction(self.module, c_str!(symbol));
+
             // Append empty block
             let entry =
                 core::LLVMAppendBasicBlockInContext(self.context, llvm_function, c_str!("entry"));
 
             core::LLVMPositionBuilderAtEnd(self.builder, entry);
+            self.gen_function_debug_info(llvm_function, name, *line);
+
+            if self.options.instrument {
+                if name == self.program.entry() {
+                    self.gen_profile_dump_registration();
+                }
+                self.gen_call_counter_increment(symbol);
+            }
 
-            for (i, arg_name) in args.iter().enumerate() {
-                // Set arg name in function prototype
-                let arg = core::LLVMGetParam(llvm_function, i as u32);
-                core::LLVMSetValueName2(arg, c_str!(arg_name), arg_name.len());
+            // Arguments are in the outermost scope of the function
+            *self.local_vars.borrow_mut() = vec![BTreeMap::new()];
+            for (i, arg) in args.iter().enumerate() {
+                let param = core::LLVMGetParam(llvm_function, i as u32);
+                let var = self.gen_local_variable(
+                    &arg.name,
+                    core::LLVMTypeOf(param),
+                    false,
+                    arg.arg_type.is_unsigned(),
+                );
+                core::LLVMBuildStore(self.builder, param, var);
+            }
 
-                let mut local_vars_mut = self.local_vars.borrow_mut();
+            // Generate function statement
+            self.gen_statement(statement)?;
 
-                let var = core::LLVMBuildAlloca(self.builder, self.i32_type(), c_str!(""));
-                if arg_name != "_" {
-                    info!("Adding `{}` to local vars", arg_name);
-                    local_vars_mut.insert(String::from(arg_name), var);
+            // A `noreturn` function can't return by reaching the end of its body
+            let last_block = core::LLVMGetInsertBlock(self.builder);
+            let is_noreturn = attributes.contains(&FunctionAttribute::NoReturn);
+            if is_noreturn && core::LLVMGetBasicBlockTerminator(last_block).is_null() {
+                core::LLVMBuildUnreachable(self.builder);
+            }
+
+            // Void functions can return by reaching the end of their body
+            if return_type.is_none() && core::LLVMGetBasicBlockTerminator(last_block).is_null() {
+                core::LLVMBuildRetVoid(self.builder);
+            }
+
+            // Other functions with an empty body, such as a placeholder `@main[] {}`, return zero
+            if let Statement::CompoundStatement { statements } = statement.as_ref() {
+                if return_type.is_some() && statements.is_empty() && !is_noreturn {
+                    let llvm_return_type = core::LLVMGetReturnType(core::LLVMGetElementType(
+                        core::LLVMTypeOf(llvm_function),
+                    ));
+                    core::LLVMBuildRet(self.builder, core::LLVMConstNull(llvm_return_type));
                 }
+            }
 
-                core::LLVMBuildStore(self.builder, arg, var);
+            // A block that can't be reached, such as the end of an if statement where every branch
+            // returns, still needs a terminator
+            let last_block = core::LLVMGetInsertBlock(self.builder);
+            if last_block != entry
+                && core::LLVMGetBasicBlockTerminator(last_block).is_null()
+                && core::LLVMGetFirstUse(core::LLVMBasicBlockAsValue(last_block)).is_null()
+            {
+                core::LLVMBuildUnreachable(self.builder);
             }
 
-            // Generate function statement
-            self.gen_statement(&statement)?;
+            self.clear_debug_location();
+
+            // Debug info isn't complete until it's finalized with the module, so functions with
+            // debug info are only verified with the module
+            if self.options.verify
+                && self.debug_info.is_none()
+                && analysis::LLVMVerifyFunction(
+                    llvm_function,
+                    LLVMVerifierFailureAction::LLVMReturnStatusAction,
+                ) != 0
+            {
+                // The function verifier has no message, but the module verifier does
+                let error = self.verify().err().unwrap_or_default();
+                return Err(format!("Verification failed in '{}': {}", symbol, error));
+            }
         }
 
         Ok(())
@@ -0,0 +1,131 @@
+use crate::c_str;
+use crate::generator::Generator;
+use crate::parser::function::Function;
+use crate::parser::program::Program;
+use llvm_sys::core;
+use llvm_sys::prelude::LLVMValueRef;
+use llvm_sys::LLVMLinkage;
+use log::{trace, warn};
+
+/// Name of the generated function that prints the call counts.
+const PROFILE_DUMP_FUNCTION: &str = "__yot_profile_dump";
+
+impl Generator {
+    /// Generate an increment of the call counter of a function at the builder's position.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the function being called.
+    pub unsafe fn gen_call_counter_increment(&self, name: &str) {
+        trace!("Generating call counter increment for `{}`", name);
+        let counter = self.get_or_add_call_counter(name);
+        let i64_type = self.int_type(64);
+        let count = core::LLVMBuildLoad2(self.builder, i64_type, counter, c_str!(""));
+        let count = core::LLVMBuildAdd(
+            self.builder,
+            count,
+            core::LLVMConstInt(i64_type, 1, false as i32),
+            c_str!(""),
+        );
+        core::LLVMBuildStore(self.builder, count, counter);
+    }
+
+    /// Register the profile dump function to be called when the program exits.
+    ///
+    /// Must be called at the start of the entry function.
+    pub unsafe fn gen_profile_dump_registration(&self) {
+        trace!("Generating profile dump registration");
+        let dump_function = self.get_or_declare_function(
+            PROFILE_DUMP_FUNCTION,
+            core::LLVMVoidTypeInContext(self.context),
+            &mut [],
+            false,
+        );
+        let atexit = self.get_or_declare_function(
+            "atexit",
+            self.i32_type(),
+            &mut [core::LLVMTypeOf(dump_function)],
+            false,
+        );
+        let mut args = [dump_function];
+        core::LLVMBuildCall(self.builder, atexit, args.as_mut_ptr(), 1, c_str!(""));
+    }
+
+    /// Generate the body of the profile dump function that prints each function's call count
+    /// to stderr.
+    ///
+    /// # Arguments
+    /// * `program` - The instrumented program.
+    pub unsafe fn gen_profile_dump(&self, program: &Program) {
+        trace!("Generating profile dump");
+        if program.function(program.entry()).is_none() {
+            warn!(
+                "Instrumented program has no {} function, call counts won't be printed",
+                program.entry()
+            );
+        }
+
+        let dump_function = self.get_or_declare_function(
+            PROFILE_DUMP_FUNCTION,
+            core::LLVMVoidTypeInContext(self.context),
+            &mut [],
+            false,
+        );
+        core::LLVMSetLinkage(dump_function, LLVMLinkage::LLVMPrivateLinkage);
+        let entry =
+            core::LLVMAppendBasicBlockInContext(self.context, dump_function, c_str!("entry"));
+        core::LLVMPositionBuilderAtEnd(self.builder, entry);
+
+        let dprintf = self.get_or_declare_function(
+            "dprintf",
+            self.i32_type(),
+            &mut [self.i32_type(), self.i8_ptr_type()],
+            true,
+        );
+        let format = self.gen_global_string("profile.fmt", "%s: %ld calls\n");
+
+        for function in &program.functions {
+            if let Function::RegularFunction { .. } = function {
+                let name = &program.symbol_name(function);
+                let count = core::LLVMBuildLoad2(
+                    self.builder,
+                    self.int_type(64),
+                    self.get_or_add_call_counter(name),
+                    c_str!(""),
+                );
+                let mut args = [
+                    // stderr
+                    core::LLVMConstInt(self.i32_type(), 2, false as i32),
+                    format,
+                    self.gen_global_string(&format!("profile.name.{}", name), name),
+                    count,
+                ];
+                core::LLVMBuildCall(
+                    self.builder,
+                    dprintf,
+                    args.as_mut_ptr(),
+                    args.len() as u32,
+                    c_str!(""),
+                );
+            }
+        }
+        core::LLVMBuildRetVoid(self.builder);
+    }
+
+    /// Get the global call counter of a function, adding it if it doesn't exist yet.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the function.
+    unsafe fn get_or_add_call_counter(&self, name: &str) -> LLVMValueRef {
+        let counter_name = format!("profile.calls.{}", name);
+        let mut counter = core::LLVMGetNamedGlobal(self.module, c_str!(counter_name));
+        if counter.is_null() {
+            counter = core::LLVMAddGlobal(self.module, self.int_type(64), c_str!(counter_name));
+            core::LLVMSetInitializer(
+                counter,
+                core::LLVMConstInt(self.int_type(64), 0, false as i32),
+            );
+            core::LLVMSetLinkage(counter, LLVMLinkage::LLVMPrivateLinkage);
+        }
+        counter
+    }
+}
@@ -0,0 +1,102 @@
+use crate::c_str;
+use crate::generator::{take_llvm_error, Generator};
+use crate::parser::program::Program;
+use crate::Result;
+use libc::c_char;
+use llvm_sys::execution_engine::{self, LLVMExecutionEngineRef};
+use llvm_sys::{core, target, LLVMTypeKind};
+use log::trace;
+use std::{mem, ptr};
+
+impl Generator {
+    /// Compile the module just in time and call a function that takes no arguments.
+    ///
+    /// The module is copied for the JIT, so functions can still be added to it afterwards. The
+    /// value returned by the function is `None` if it is `void`.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the function.
+    pub unsafe fn jit_call(&self, name: &str) -> Result<Option<i32>> {
+        trace!("JIT calling `{}`", name);
+        let function = core::LLVMGetNamedFunction(self.module, c_str!(name));
+        if function.is_null() {
+            return Err(format!("Function `{}` doesn't exist", name));
+        }
+        let return_type =
+            core::LLVMGetReturnType(core::LLVMGetElementType(core::LLVMTypeOf(function)));
+        let is_void = core::LLVMGetTypeKind(return_type) == LLVMTypeKind::LLVMVoidTypeKind;
+        if !is_void && return_type != self.i32_type() {
+            return Err(format!("Function `{}` must return an i32 or void", name));
+        }
+
+        execution_engine::LLVMLinkInMCJIT();
+        target::LLVM_InitializeNativeTarget();
+        target::LLVM_InitializeNativeAsmPrinter();
+
+        // The execution engine takes ownership of the copied module
+        let module = core::LLVMCloneModule(self.module);
+        let mut engine = ptr::null_mut::<execution_engine::LLVMOpaqueExecutionEngine>();
+        let mut error = ptr::null_mut::<c_char>();
+        if execution_engine::LLVMCreateExecutionEngineForModule(&mut engine, module, &mut error)
+            != 0
+        {
+            core::LLVMDisposeModule(module);
+            return Err(take_llvm_error(error)
+                .unwrap_or_else(|| "Unable to create the JIT execution engine".to_string()));
+        }
+
+        let value = call_jit_function(engine, name, is_void);
+        execution_engine::LLVMDisposeExecutionEngine(engine);
+        value
+    }
+
+    /// Remove the functions and global variables of a program from the module, such as after
+    /// generating the program failed.
+    ///
+    /// # Arguments
+    /// * `program` - The program whose definitions are removed.
+    pub unsafe fn remove_definitions(&self, program: &Program) {
+        for function in &program.functions {
+            let name = program.symbol_name(function);
+            let llvm_function = core::LLVMGetNamedFunction(self.module, c_str!(name));
+            if !llvm_function.is_null() {
+                core::LLVMDeleteFunction(llvm_function);
+            }
+            self.unsigned_functions.borrow_mut().remove(&name);
+        }
+        for global in &program.globals {
+            if let Some(variable) = self.global_vars.borrow_mut().remove(&global.name) {
+                core::LLVMDeleteGlobal(variable.ptr);
+            }
+        }
+    }
+}
+
+/// Call a compiled function that takes no arguments.
+///
+/// # Arguments
+/// * `engine` - The execution engine the function was compiled by.
+/// * `name` - The name of the function.
+/// * `is_void` - Whether or not the function returns `void`.
+unsafe fn call_jit_function(
+    engine: LLVMExecutionEngineRef,
+    name: &str,
+    is_void: bool,
+) -> Result<Option<i32>> {
+    let address = execution_engine::LLVMGetFunctionAddress(engine, c_str!(name));
+    if address == 0 {
+        return Err(format!("Unable to compile `{}`", name));
+    }
+
+    let value = if is_void {
+        let function: extern "C" fn() = mem::transmute(address as usize);
+        function();
+        None
+    } else {
+        let function: extern "C" fn() -> i32 = mem::transmute(address as usize);
+        Some(function())
+    };
+    // Output from the compiled code is buffered by libc rather than Rust
+    libc::fflush(ptr::null_mut());
+    Ok(value)
+}
@@ -1,29 +1,138 @@
+mod builtins;
+mod debug_info;
 mod expression;
 mod function;
+mod instrument;
+mod jit;
 mod program;
 mod statement;
 
 use crate::c_str;
-use crate::parser::program::Program;
+use crate::generator::debug_info::DebugInfo;
+use crate::parser::program::{mangle, Program};
+use crate::parser::types::Type;
 use crate::Result;
 use libc::c_char;
 use llvm_sys::analysis::LLVMVerifierFailureAction;
 use llvm_sys::prelude::{LLVMBuilderRef, LLVMContextRef, LLVMModuleRef, LLVMTypeRef, LLVMValueRef};
 use llvm_sys::target_machine::{
     LLVMCodeGenFileType, LLVMCodeGenOptLevel, LLVMCodeModel, LLVMRelocMode, LLVMTarget,
+    LLVMTargetMachineRef,
 };
-use llvm_sys::{analysis, core, target, target_machine};
-use log::{debug, error, info, trace, warn};
+use llvm_sys::transforms::pass_manager_builder;
+use llvm_sys::{analysis, core, target, target_machine, LLVMTypeKind};
+use log::{debug, info, trace, warn};
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::ffi::CStr;
+use std::io::ErrorKind;
 use std::process::Command;
 use std::ptr;
 
+/// The names and types of the fields of a struct.
+type Fields = Vec<(String, Type)>;
+
+/// A variable allocated in a function, or a global variable.
+#[derive(Clone, Copy)]
+struct Variable {
+    /// The allocated value.
+    ptr: LLVMValueRef,
+    /// The type of the variable.
+    var_type: LLVMTypeRef,
+    /// Whether or not the variable can't be assigned to.
+    is_const: bool,
+    /// Whether or not the variable, or the elements of the array it references, are unsigned.
+    is_unsigned: bool,
+}
+
+/// How generated code refers to addresses, which decides where it can be loaded.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Relocation {
+    /// The default of the target.
+    #[default]
+    Default,
+    /// Code that can only be loaded at the address it is linked at.
+    Static,
+    /// Position-independent code, for shared libraries and PIE executables.
+    PIC,
+}
+
+/// How far apart code and data can be, which decides the instructions used to address them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CodeModel {
+    /// The default of the target.
+    Default,
+    /// Code and data are within the lower 2GB of the address space.
+    Small,
+    /// Code is within the lower 2GB, data can be anywhere.
+    Medium,
+    /// Code and data can be anywhere.
+    Large,
+    /// Code and data are within the upper 2GB of the address space, for kernels.
+    Kernel,
+}
+
+/// Options that change how code is generated.
+pub struct GeneratorOptions {
+    /// Whether or not calls to built-in functions are handled by the compiler.
+    pub builtins: bool,
+    /// Whether or not local variables that shadow other local variables are warned about.
+    pub warn_shadow: bool,
+    /// Whether or not to count function calls and print the counts when the program exits.
+    pub instrument: bool,
+    /// Path to the source file to generate debug info for, `None` for no debug info.
+    pub debug_source: Option<String>,
+    /// How emitted files refer to addresses.
+    pub relocation: Relocation,
+    /// How far apart code and data in emitted files can be.
+    pub code_model: CodeModel,
+    /// Whether or not each function is verified as it is generated.
+    pub verify: bool,
+    /// Whether or not the program runs without libc, so built-ins that call it are unavailable
+    /// and the entry function isn't called as `main`.
+    pub freestanding: bool,
+}
+
+impl Default for GeneratorOptions {
+    fn default() -> Self {
+        GeneratorOptions {
+            builtins: true,
+            warn_shadow: false,
+            instrument: false,
+            debug_source: None,
+            relocation: Relocation::Default,
+            code_model: CodeModel::Default,
+            verify: true,
+            freestanding: false,
+        }
+    }
+}
+
+/// Options that change how executables are linked.
+#[derive(Default)]
+pub struct LinkOptions {
+    /// The linker command, `cc` or else `gcc` if not given.
+    pub linker: Option<String>,
+    /// Extra object files linked into the executable.
+    pub objects: Vec<String>,
+    /// Libraries linked into the executable.
+    pub libraries: Vec<String>,
+    /// Extra arguments passed to the linker.
+    pub args: Vec<String>,
+    /// The function a freestanding executable starts at, which is linked without the C runtime
+    /// and libc, `None` to link them.
+    pub freestanding_entry: Option<String>,
+    /// How the object file refers to addresses, executables are only linked as PIE if it is
+    /// position independent.
+    pub relocation: Relocation,
+}
+
 /// Generates LLVM IR based on the AST.
 pub struct Generator {
     /// The root of the AST.
     program: Program,
+    /// Code generation options.
+    options: GeneratorOptions,
 
     /// LLVM Context.
     context: LLVMContextRef,
@@ -32,10 +141,23 @@ pub struct Generator {
     /// LLVM Builder.
     builder: LLVMBuilderRef,
 
-    /// LLVM variable map.
-    local_vars: RefCell<HashMap<String, LLVMValueRef>>,
-    /// Variables in the current scope
-    scope_var_names: RefCell<Vec<Vec<String>>>,
+    /// LLVM variable maps of names to local variables for each scope, innermost scope last.
+    ///
+    /// The maps are ordered by name, so anything that iterates over them is deterministic.
+    local_vars: RefCell<Vec<BTreeMap<String, Variable>>>,
+    /// LLVM global variable map of names to global variables.
+    global_vars: RefCell<HashMap<String, Variable>>,
+    /// The names of the functions that return unsigned integers.
+    unsigned_functions: RefCell<HashSet<String>>,
+    /// Global string constants by their contents, so repeated string literals share one global.
+    string_pool: RefCell<HashMap<String, LLVMValueRef>>,
+    /// LLVM struct types and the fields of the structs they were created for, by name.
+    struct_types: RefCell<HashMap<String, (LLVMTypeRef, Fields)>>,
+    /// DWARF debug info builder, if debug info is enabled.
+    debug_info: Option<DebugInfo>,
+    /// The target machine that emits files and the optimization level it was created with,
+    /// created the first time a file is emitted.
+    target_machine: RefCell<Option<(u32, LLVMTargetMachineRef)>>,
 }
 
 impl Generator {
@@ -47,20 +169,44 @@ impl Generator {
     /// * `program` - The root of the AST.
     /// * `name` - The name of the module to be created.
     pub unsafe fn new(program: Program, name: &str) -> Self {
+        Self::with_options(program, name, GeneratorOptions::default())
+    }
+
+    /// Create a new generator from a [`Program`] with non-default [`GeneratorOptions`].
+    ///
+    /// [`Program`]: ../parser/program/struct.Program.html
+    /// [`GeneratorOptions`]: struct.GeneratorOptions.html
+    ///
+    /// # Arguments
+    /// * `program` - The root of the AST.
+    /// * `name` - The name of the module to be created.
+    /// * `options` - Code generation options.
+    pub unsafe fn with_options(program: Program, name: &str, options: GeneratorOptions) -> Self {
         let context = core::LLVMContextCreate();
-        Generator {
+        let mut generator = Generator {
             program,
+            options,
             context,
             module: core::LLVMModuleCreateWithNameInContext(c_str!(name), context),
             builder: core::LLVMCreateBuilderInContext(context),
-            local_vars: RefCell::new(HashMap::new()),
-            scope_var_names: RefCell::new(Vec::new()),
+            local_vars: RefCell::new(Vec::new()),
+            global_vars: RefCell::new(HashMap::new()),
+            unsigned_functions: RefCell::new(HashSet::new()),
+            string_pool: RefCell::new(HashMap::new()),
+            struct_types: RefCell::new(HashMap::new()),
+            debug_info: None,
+            target_machine: RefCell::new(None),
+        };
+        if let Some(source_path) = generator.options.debug_source.clone() {
+            generator.debug_info = Some(DebugInfo::new(&generator, &source_path));
         }
+        generator
     }
 
     /// Generate the LLVM IR from the module.
     pub unsafe fn generate(&self) -> Result<()> {
         self.gen_program(&self.program)?;
+        self.finalize_debug_info();
         debug!("Successfully generated program");
         Ok(())
     }
@@ -73,35 +219,159 @@ impl Generator {
             LLVMVerifierFailureAction::LLVMReturnStatusAction,
             &mut error,
         );
-        if !error.is_null() {
-            let error = CStr::from_ptr(error).to_str().unwrap().to_string();
-            if !error.is_empty() {
-                return Err(error);
-            }
+        if let Some(error) = take_llvm_error(error) {
+            return Err(error);
         }
         debug!("Successfully verified module");
         Ok(())
     }
 
+    /// Run the LLVM optimization passes of an optimization level over the module.
+    ///
+    /// # Arguments
+    /// * `optimization` - Optimization level (0-3).
+    pub unsafe fn optimize(&self, optimization: u32) -> Result<()> {
+        let target_machine = self.target_machine(optimization)?;
+        let pass_manager = core::LLVMCreatePassManager();
+        // Lets passes such as the vectorizer know about the target
+        target_machine::LLVMAddAnalysisPasses(target_machine, pass_manager);
+
+        let builder = pass_manager_builder::LLVMPassManagerBuilderCreate();
+        pass_manager_builder::LLVMPassManagerBuilderSetOptLevel(builder, optimization.min(3));
+        // The same inlining thresholds as clang
+        match optimization {
+            0 | 1 => (),
+            2 => pass_manager_builder::LLVMPassManagerBuilderUseInlinerWithThreshold(builder, 225),
+            _ => pass_manager_builder::LLVMPassManagerBuilderUseInlinerWithThreshold(builder, 275),
+        }
+        pass_manager_builder::LLVMPassManagerBuilderPopulateModulePassManager(
+            builder,
+            pass_manager,
+        );
+        pass_manager_builder::LLVMPassManagerBuilderDispose(builder);
+
+        core::LLVMRunPassManager(pass_manager, self.module);
+        core::LLVMDisposePassManager(pass_manager);
+        debug!("Successfully optimized module at level {}", optimization);
+        Ok(())
+    }
+
     /// Dump LLVM IR to stdout.
     pub unsafe fn generate_ir(&self, output: &str) -> Result<()> {
         let mut error = ptr::null_mut::<c_char>();
-        core::LLVMPrintModuleToFile(self.module, c_str!(output), &mut error);
-        if !error.is_null() {
-            let error = CStr::from_ptr(error).to_str().unwrap().to_string();
-            if !error.is_empty() {
-                return Err(error);
-            }
+        if core::LLVMPrintModuleToFile(self.module, c_str!(output), &mut error) != 0 {
+            return Err(take_llvm_error(error)
+                .unwrap_or_else(|| format!("Unable to write LLVM IR to `{}`", output)));
         }
         Ok(())
     }
 
+    /// Get the LLVM IR as text, such as to write it to stdout.
+    pub unsafe fn ir_string(&self) -> String {
+        let ir = core::LLVMPrintModuleToString(self.module);
+        let text = CStr::from_ptr(ir).to_string_lossy().into_owned();
+        core::LLVMDisposeMessage(ir);
+        text
+    }
+
+    /// Generate native assembly from the LLVM IR as text, such as to write it to stdout.
+    ///
+    /// # Arguments
+    /// * `optimization` - Optimization level (0-3).
+    pub unsafe fn assembly_string(&self, optimization: u32) -> Result<String> {
+        let target_machine = self.target_machine(optimization)?;
+
+        let mut error = ptr::null_mut::<c_char>();
+        let mut buffer = ptr::null_mut();
+        if target_machine::LLVMTargetMachineEmitToMemoryBuffer(
+            target_machine,
+            self.module,
+            LLVMCodeGenFileType::LLVMAssemblyFile,
+            &mut error,
+            &mut buffer,
+        ) != 0
+        {
+            return Err(
+                take_llvm_error(error).unwrap_or_else(|| String::from("Unable to emit assembly"))
+            );
+        }
+        let bytes = std::slice::from_raw_parts(
+            core::LLVMGetBufferStart(buffer) as *const u8,
+            core::LLVMGetBufferSize(buffer),
+        );
+        let text = String::from_utf8_lossy(bytes).into_owned();
+        core::LLVMDisposeMemoryBuffer(buffer);
+        trace!("Successfully emitted to memory");
+        Ok(text)
+    }
+
     /// Generate an object file from the LLVM IR.
     ///
     /// # Arguments
     /// * `optimization` - Optimization level (0-3).
     /// * `output` - Output file path.
     pub unsafe fn generate_object_file(&self, optimization: u32, output: &str) -> Result<()> {
+        self.emit_to_file(optimization, output, LLVMCodeGenFileType::LLVMObjectFile)
+            .map_err(|e| e.unwrap_or_else(|| format!("Unable to emit object file `{}`", output)))
+    }
+
+    /// Generate a native assembly file from the LLVM IR.
+    ///
+    /// # Arguments
+    /// * `optimization` - Optimization level (0-3).
+    /// * `output` - Output file path.
+    pub unsafe fn generate_assembly_file(&self, optimization: u32, output: &str) -> Result<()> {
+        self.emit_to_file(optimization, output, LLVMCodeGenFileType::LLVMAssemblyFile)
+            .map_err(|e| e.unwrap_or_else(|| format!("Unable to emit assembly file `{}`", output)))
+    }
+
+    /// Emit the module to a file with the target machine.
+    ///
+    /// On failure, the error from LLVM is returned if there is one.
+    ///
+    /// # Arguments
+    /// * `optimization` - Optimization level (0-3).
+    /// * `output` - Output file path.
+    /// * `file_type` - The type of file to emit.
+    unsafe fn emit_to_file(
+        &self,
+        optimization: u32,
+        output: &str,
+        file_type: LLVMCodeGenFileType,
+    ) -> std::result::Result<(), Option<String>> {
+        let target_machine = self.target_machine(optimization).map_err(Some)?;
+
+        let mut error = ptr::null_mut::<c_char>();
+        if target_machine::LLVMTargetMachineEmitToFile(
+            target_machine,
+            self.module,
+            c_str!(output) as *mut _,
+            file_type,
+            &mut error,
+        ) != 0
+        {
+            return Err(take_llvm_error(error));
+        }
+        trace!("Successfully emitted to file");
+        Ok(())
+    }
+
+    /// Get the target machine for the default target triple, creating it the first time it's
+    /// needed or when the optimization level changes.
+    ///
+    /// # Arguments
+    /// * `optimization` - Optimization level (0-3).
+    unsafe fn target_machine(&self, optimization: u32) -> Result<LLVMTargetMachineRef> {
+        if let Some((level, target_machine)) = *self.target_machine.borrow() {
+            if level == optimization {
+                trace!("Reusing target machine");
+                return Ok(target_machine);
+            }
+        }
+        if let Some((_, target_machine)) = self.target_machine.borrow_mut().take() {
+            target_machine::LLVMDisposeTargetMachine(target_machine);
+        }
+
         let target_triple = target_machine::LLVMGetDefaultTargetTriple();
 
         info!(
@@ -118,12 +388,10 @@ impl Generator {
 
         let mut target = ptr::null_mut::<LLVMTarget>();
         let mut error = ptr::null_mut::<c_char>();
-        target_machine::LLVMGetTargetFromTriple(target_triple, &mut target, &mut error);
-        if !error.is_null() {
-            let error = CStr::from_ptr(error).to_str().unwrap().to_string();
-            if !error.is_empty() {
-                return Err(error);
-            }
+        if target_machine::LLVMGetTargetFromTriple(target_triple, &mut target, &mut error) != 0 {
+            core::LLVMDisposeMessage(target_triple);
+            return Err(take_llvm_error(error)
+                .unwrap_or_else(|| "Unable to find a target for the default triple".to_string()));
         }
 
         let optimization_level = match optimization {
@@ -144,44 +412,274 @@ impl Generator {
             c_str!("generic"),
             c_str!(""),
             optimization_level,
-            LLVMRelocMode::LLVMRelocDefault, // TODO is this right?
-            LLVMCodeModel::LLVMCodeModelDefault, // TODO is this right?
+            match self.options.relocation {
+                Relocation::Default => LLVMRelocMode::LLVMRelocDefault,
+                Relocation::Static => LLVMRelocMode::LLVMRelocStatic,
+                Relocation::PIC => LLVMRelocMode::LLVMRelocPIC,
+            },
+            match self.options.code_model {
+                CodeModel::Default => LLVMCodeModel::LLVMCodeModelDefault,
+                CodeModel::Small => LLVMCodeModel::LLVMCodeModelSmall,
+                CodeModel::Medium => LLVMCodeModel::LLVMCodeModelMedium,
+                CodeModel::Large => LLVMCodeModel::LLVMCodeModelLarge,
+                CodeModel::Kernel => LLVMCodeModel::LLVMCodeModelKernel,
+            },
         );
+        core::LLVMDisposeMessage(target_triple);
+        if target_machine.is_null() {
+            return Err("Unable to create target machine".to_string());
+        }
         trace!("Successfully created target machine");
 
-        let mut target = ptr::null_mut::<c_char>();
-        target_machine::LLVMTargetMachineEmitToFile(
-            target_machine,
-            self.module,
-            c_str!(output) as *mut _,
-            LLVMCodeGenFileType::LLVMObjectFile,
-            &mut target,
-        );
-        if !target.is_null() {
-            let error = CStr::from_ptr(error).to_str().unwrap();
-            error!("{}", error);
-        };
-        trace!("Successfully emitted to file");
-        Ok(())
+        *self.target_machine.borrow_mut() = Some((optimization, target_machine));
+        Ok(target_machine)
+    }
+
+    /// Get the number of bytes a value of a type takes up on the target.
+    ///
+    /// # Arguments
+    /// * `llvm_type` - The type.
+    unsafe fn store_size(&self, llvm_type: LLVMTypeRef) -> Result<u64> {
+        // The data layout doesn't depend on the optimization level, so any target machine will do
+        let optimization = self.target_machine.borrow().map_or(2, |(level, _)| level);
+        let data_layout =
+            target_machine::LLVMCreateTargetDataLayout(self.target_machine(optimization)?);
+        let size = target::LLVMStoreSizeOfType(data_layout, llvm_type);
+        target::LLVMDisposeTargetData(data_layout);
+        Ok(size)
     }
 
-    /// Generates an executable from the object file by calling gcc.
+    /// Generates an executable from the object file by calling a linker.
     ///
     /// # Arguments
     /// * `object_file` - Path to the object file.
     /// * `output` - Path to the executable.
-    pub fn generate_executable(&self, object_file: &str, output: &str) -> Result<()> {
-        // TODO is there a better way to do this?
-        match Command::new("gcc")
-            .args(&[object_file, "-o", output])
-            .spawn()
-        {
-            Ok(_) => {
-                debug!("Successfully generated executable: {}", output);
-                Ok(())
+    /// * `options` - Linker options.
+    pub fn generate_executable(
+        &self,
+        object_file: &str,
+        output: &str,
+        options: &LinkOptions,
+    ) -> Result<()> {
+        let mut kind = Vec::new();
+        if options.relocation != Relocation::PIC {
+            kind.push("-no-pie");
+        }
+        if let Some(entry) = &options.freestanding_entry {
+            kind.extend(&["-e", entry]);
+        }
+        self.link(object_file, output, options, &kind)?;
+        debug!("Successfully generated executable: {}", output);
+        Ok(())
+    }
+
+    /// Generates a shared library from the object file by calling a linker.
+    ///
+    /// The object file must be position independent.
+    ///
+    /// # Arguments
+    /// * `object_file` - Path to the object file.
+    /// * `output` - Path to the shared library.
+    /// * `options` - Linker options.
+    pub fn generate_shared_library(
+        &self,
+        object_file: &str,
+        output: &str,
+        options: &LinkOptions,
+    ) -> Result<()> {
+        self.link(object_file, output, options, &["-shared"])?;
+        debug!("Successfully generated shared library: {}", output);
+        Ok(())
+    }
+
+    /// Link an object file by calling the first linker that is found.
+    ///
+    /// # Arguments
+    /// * `object_file` - Path to the object file.
+    /// * `output` - Path to the linked file.
+    /// * `options` - Linker options.
+    /// * `kind` - The linker flags for the kind of file to link.
+    fn link(
+        &self,
+        object_file: &str,
+        output: &str,
+        options: &LinkOptions,
+        kind: &[&str],
+    ) -> Result<()> {
+        let linkers = match &options.linker {
+            Some(linker) => vec![&linker[..]],
+            None => vec!["cc", "gcc"],
+        };
+
+        for linker in linkers {
+            match Command::new(linker)
+                .arg(object_file)
+                .args(&options.objects)
+                .args(kind)
+                .args(options.freestanding_entry.as_ref().map(|_| "-nostdlib"))
+                .args(["-o", output])
+                .args(options.libraries.iter().map(|l| format!("-l{}", l)))
+                .args(&options.args)
+                .output()
+            {
+                Ok(result) if result.status.success() => {
+                    debug!("Successfully linked with `{}`", linker);
+                    return Ok(());
+                }
+                Ok(result) => {
+                    return Err(format!(
+                        "`{}` failed with {}:\n{}",
+                        linker,
+                        result.status,
+                        String::from_utf8_lossy(&result.stderr).trim_end()
+                    ))
+                }
+                Err(e) if e.kind() == ErrorKind::NotFound => {
+                    info!("Linker `{}` wasn't found on PATH", linker)
+                }
+                Err(e) => return Err(format!("Unable to link object file:\n{}", e)),
             }
-            Err(e) => Err(format!("Unable to link object file:\n{}", e)),
         }
+
+        Err(match &options.linker {
+            Some(linker) => format!("Linker `{}` wasn't found on PATH", linker),
+            None => "Neither `cc` nor `gcc` was found on PATH, use --linker to choose a linker"
+                .to_string(),
+        })
+    }
+
+    /// Get a function from the module, declaring it if it doesn't exist yet.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the function.
+    /// * `return_type` - The return type of the function.
+    /// * `param_types` - The types of the fixed parameters of the function.
+    /// * `is_var_arg` - Whether or not the function is variadic.
+    unsafe fn get_or_declare_function(
+        &self,
+        name: &str,
+        return_type: LLVMTypeRef,
+        param_types: &mut [LLVMTypeRef],
+        is_var_arg: bool,
+    ) -> LLVMValueRef {
+        let function = core::LLVMGetNamedFunction(self.module, c_str!(name));
+        if !function.is_null() {
+            return function;
+        }
+
+        trace!("Declaring `{}`", name);
+        core::LLVMAddFunction(
+            self.module,
+            c_str!(name),
+            core::LLVMFunctionType(
+                return_type,
+                param_types.as_mut_ptr(),
+                param_types.len() as u32,
+                is_var_arg as i32,
+            ),
+        )
+    }
+
+    /// Get an `i8*` to a global constant string, creating the global if it doesn't exist yet.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the global.
+    /// * `value` - The contents of the string.
+    unsafe fn gen_global_string(&self, name: &str, value: &str) -> LLVMValueRef {
+        let mut global = core::LLVMGetNamedGlobal(self.module, c_str!(name));
+        if global.is_null() {
+            trace!("Adding global string `{}`", name);
+            global = core::LLVMBuildGlobalString(self.builder, c_str!(value), c_str!(name));
+        }
+        core::LLVMConstBitCast(global, self.i8_ptr_type())
+    }
+
+    /// Find a variable by name, either local or global, and get its value and type.
+    ///
+    /// Local variables shadow global variables with the same name.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the variable.
+    fn variable(&self, name: &str) -> Option<(LLVMValueRef, LLVMTypeRef)> {
+        self.find_variable(name)
+            .map(|variable| (variable.ptr, variable.var_type))
+    }
+
+    /// Find a variable by name, either local or global.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the variable.
+    fn find_variable(&self, name: &str) -> Option<Variable> {
+        self.local_variable(name)
+            .or_else(|| self.global_vars.borrow().get(name).copied())
+    }
+
+    /// Find a local variable by name in the innermost scope that declares it.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the variable.
+    fn local_variable(&self, name: &str) -> Option<Variable> {
+        self.local_vars
+            .borrow()
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .copied()
+    }
+
+    /// Check that a variable can be assigned to, `Err` if it is `const`.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the variable.
+    fn check_assignable(&self, name: &str) -> Result<()> {
+        match self.local_variable(name) {
+            Some(local) if local.is_const => Err(format!("cannot assign to const '{}'", name)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Get an `i8*` to a global constant string with the given contents, reusing an existing
+    /// global if the same string has been generated before.
+    ///
+    /// # Arguments
+    /// * `value` - The contents of the string.
+    unsafe fn gen_pooled_string(&self, value: &str) -> LLVMValueRef {
+        if let Some(global) = self.string_pool.borrow().get(value) {
+            return *global;
+        }
+
+        trace!("Adding pooled string {:?}", value);
+        let global = core::LLVMBuildGlobalString(self.builder, c_str!(value), c_str!(".str"));
+        let global = core::LLVMConstBitCast(global, self.i8_ptr_type());
+        self.string_pool
+            .borrow_mut()
+            .insert(value.to_string(), global);
+        global
+    }
+
+    /// Get the function that a call with a number of arguments calls, which is the overload taking
+    /// that many arguments if the function is overloaded, or null if there is none.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the called function.
+    /// * `arity` - The number of arguments of the call.
+    unsafe fn called_function(&self, name: &str, arity: usize) -> LLVMValueRef {
+        let overload = core::LLVMGetNamedFunction(self.module, c_str!(mangle(name, arity)));
+        if overload.is_null() {
+            core::LLVMGetNamedFunction(self.module, c_str!(name))
+        } else {
+            overload
+        }
+    }
+
+    /// Get the name of an LLVM value, such as a function.
+    ///
+    /// # Arguments
+    /// * `value` - The named value.
+    unsafe fn value_name(&self, value: LLVMValueRef) -> String {
+        let mut len = 0;
+        let name = core::LLVMGetValueName2(value, &mut len);
+        CStr::from_ptr(name).to_str().unwrap().to_string()
     }
 
     /// Get LLVM i32 type in context.
@@ -189,12 +687,124 @@ impl Generator {
     fn i32_type(&self) -> LLVMTypeRef {
         unsafe { core::LLVMInt32TypeInContext(self.context) }
     }
+
+    /// Get LLVM integer type of an arbitrary width in context.
+    ///
+    /// # Arguments
+    /// * `bits` - The width of the integer.
+    #[inline]
+    fn int_type(&self, bits: u32) -> LLVMTypeRef {
+        unsafe { core::LLVMIntTypeInContext(self.context, bits) }
+    }
+
+    /// Get LLVM i8* type in context.
+    #[inline]
+    fn i8_ptr_type(&self) -> LLVMTypeRef {
+        unsafe { core::LLVMPointerType(self.int_type(8), 0) }
+    }
+
+    /// Get the LLVM struct type that holds the elements of a tuple in context.
+    ///
+    /// # Arguments
+    /// * `element_types` - The types of the elements.
+    fn tuple_type(&self, element_types: &mut [LLVMTypeRef]) -> LLVMTypeRef {
+        unsafe {
+            core::LLVMStructTypeInContext(
+                self.context,
+                element_types.as_mut_ptr(),
+                element_types.len() as u32,
+                false as i32,
+            )
+        }
+    }
+
+    /// Get LLVM f64 type in context.
+    #[inline]
+    fn f64_type(&self) -> LLVMTypeRef {
+        unsafe { core::LLVMDoubleTypeInContext(self.context) }
+    }
+
+    /// Get the LLVM type of a yot [`Type`].
+    ///
+    /// [`Type`]: ../parser/types/enum.Type.html
+    ///
+    /// # Arguments
+    /// * `var_type` - The yot type.
+    fn llvm_type(&self, var_type: &Type) -> LLVMTypeRef {
+        match var_type {
+            Type::I8 => self.int_type(8),
+            Type::I16 => self.int_type(16),
+            Type::I32 | Type::U32 => self.i32_type(),
+            Type::I64 => self.int_type(64),
+            Type::F64 => self.f64_type(),
+            Type::Array(element_type) | Type::Pointer(element_type) => unsafe {
+                core::LLVMPointerType(self.llvm_type(element_type), 0)
+            },
+            Type::Str | Type::Function => self.i8_ptr_type(),
+            Type::Tuple(element_types) => {
+                let mut element_types = element_types
+                    .iter()
+                    .map(|t| self.llvm_type(t))
+                    .collect::<Vec<_>>();
+                self.tuple_type(&mut element_types)
+            }
+            Type::Struct(name) => self.struct_type(name),
+        }
+    }
+
+    /// Get the LLVM type of a struct, creating it without a body if it hasn't been declared.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the struct.
+    fn struct_type(&self, name: &str) -> LLVMTypeRef {
+        if let Some((struct_type, _)) = self.struct_types.borrow().get(name) {
+            return *struct_type;
+        }
+        let struct_type = unsafe { core::LLVMStructCreateNamed(self.context, c_str!(name)) };
+        self.struct_types
+            .borrow_mut()
+            .insert(name.to_string(), (struct_type, Vec::new()));
+        struct_type
+    }
+
+    /// Get the index and type of a field of an LLVM struct type.
+    ///
+    /// # Arguments
+    /// * `struct_type` - The LLVM type of the struct.
+    /// * `field` - The name of the field.
+    unsafe fn struct_field(&self, struct_type: LLVMTypeRef, field: &str) -> Result<(u32, Type)> {
+        let name = match core::LLVMGetTypeKind(struct_type) {
+            LLVMTypeKind::LLVMStructTypeKind if !core::LLVMGetStructName(struct_type).is_null() => {
+                CStr::from_ptr(core::LLVMGetStructName(struct_type)).to_string_lossy()
+            }
+            _ => {
+                return Err(format!(
+                    "Can't access field `{}` of a non-struct value",
+                    field
+                ))
+            }
+        };
+        let struct_types = self.struct_types.borrow();
+        let fields = match struct_types.get(&name[..]) {
+            Some((_, fields)) => fields,
+            None => return Err(format!("Unknown struct `{}`", name)),
+        };
+        match fields.iter().position(|(f, _)| f == field) {
+            Some(index) => Ok((index as u32, fields[index].1.clone())),
+            None => Err(format!("Struct `{}` has no field `{}`", name, field)),
+        }
+    }
 }
 
 impl Drop for Generator {
     fn drop(&mut self) {
         debug!("Cleaning up generator");
+        // The debug info builder must be disposed before the module it belongs to
+        self.debug_info.take();
         unsafe {
+            if let Some((_, target_machine)) = self.target_machine.borrow_mut().take() {
+                target_machine::LLVMDisposeTargetMachine(target_machine);
+            }
             core::LLVMDisposeBuilder(self.builder);
             core::LLVMDisposeModule(self.module);
             core::LLVMContextDispose(self.context);
@@ -202,6 +812,23 @@ impl Drop for Generator {
     }
 }
 
+/// Take ownership of an error message from LLVM, `None` if there isn't one.
+///
+/// # Arguments
+/// * `error` - The error message, which may be null.
+unsafe fn take_llvm_error(error: *mut c_char) -> Option<String> {
+    if error.is_null() {
+        return None;
+    }
+    let message = CStr::from_ptr(error).to_string_lossy().into_owned();
+    core::LLVMDisposeMessage(error);
+    if message.is_empty() {
+        None
+    } else {
+        Some(message)
+    }
+}
+
 /// Convert a `&str` into `*const libc::c_char`
 #[macro_export]
 macro_rules! c_str {
@@ -1,15 +1,205 @@
-use crate::generator::Generator;
-use crate::parser::program::Program;
+use crate::builtins::{BUILTIN_FUNCTIONS, LIBC_BUILTIN_FUNCTIONS};
+use crate::c_str;
+use crate::generator::{Generator, Variable};
+use crate::parser::function::Function;
+use crate::parser::optimize::constant_value;
+use crate::parser::program::{Global, Program, Struct};
+use crate::parser::types::Type;
 use crate::Result;
-use log::trace;
+use llvm_sys::core;
+use llvm_sys::LLVMTypeKind;
+use log::{trace, warn};
 
 impl Generator {
     pub unsafe fn gen_program(&self, program: &Program) -> Result<()> {
         trace!("Generating program");
+        self.gen_struct_types(&program.structs)?;
+        for global in &program.globals {
+            self.gen_global_variable(global)?;
+        }
+
+        // Declare every function first so they can be called before they are defined
+        for function in &program.functions {
+            if self.options.builtins {
+                self.warn_builtin_shadowing(function);
+            }
+            self.gen_function_prototype(function, &program.symbol_name(function))?;
+        }
+
         for function in &program.functions {
-            self.local_vars.borrow_mut().clear();
-            self.gen_function(&function)?;
+            self.gen_function(function, &program.symbol_name(function))?;
+        }
+
+        if self.options.instrument {
+            self.gen_profile_dump(program);
+        }
+        self.gen_entry_alias(program)
+    }
+
+    /// Add `main` as another name for the entry function if it isn't `main`, so the C runtime
+    /// starts the program at it.
+    ///
+    /// # Arguments
+    /// * `program` - The program with the entry function.
+    unsafe fn gen_entry_alias(&self, program: &Program) -> Result<()> {
+        let entry = program.entry();
+        // Freestanding programs start at the entry function itself
+        if entry == "main" || self.options.freestanding {
+            return Ok(());
+        }
+        if let Some(Function::RegularFunction { .. }) = program.function(entry) {
+            trace!("Generating main alias for {}", entry);
+            if program.function("main").is_some() {
+                return Err(format!(
+                    "Function `main` conflicts with the entry function `{}`",
+                    entry
+                ));
+            }
+            let function = core::LLVMGetNamedFunction(self.module, c_str!(entry));
+            core::LLVMAddAlias(
+                self.module,
+                core::LLVMTypeOf(function),
+                function,
+                c_str!("main"),
+            );
+        }
+        Ok(())
+    }
+
+    /// Add a global variable to the module, initialized with its constant value.
+    ///
+    /// # Arguments
+    /// * `global` - The global variable.
+    unsafe fn gen_global_variable(&self, global: &Global) -> Result<()> {
+        trace!("Generating global variable: {}", global.name);
+        if self.global_vars.borrow().contains_key(&global.name) {
+            return Err(format!("Global variable `{}` already exists", global.name));
         }
+        if self.program.function(&global.name).is_some() {
+            return Err(format!(
+                "Global variable `{}` has the same name as a function",
+                global.name
+            ));
+        }
+
+        let llvm_type = match &global.var_type {
+            Some(var_type) => self.llvm_type(var_type),
+            None => self.i32_type(),
+        };
+        match core::LLVMGetTypeKind(llvm_type) {
+            LLVMTypeKind::LLVMIntegerTypeKind | LLVMTypeKind::LLVMDoubleTypeKind => (),
+            _ => {
+                return Err(format!(
+                    "Global variable `{}` must be a number",
+                    global.name
+                ))
+            }
+        }
+
+        let value = match &global.value {
+            Some(value) => match constant_value(
+                value,
+                global.var_type.as_ref().is_some_and(|t| t.is_unsigned()),
+            ) {
+                Some(value) => value,
+                None => {
+                    return Err(format!(
+                        "Global variable `{}` must be initialized with a constant",
+                        global.name
+                    ))
+                }
+            },
+            None => 0,
+        };
+
+        let llvm_global = core::LLVMAddGlobal(self.module, llvm_type, c_str!(global.name));
+        core::LLVMSetInitializer(
+            llvm_global,
+            self.gen_integer_literal(value, Some(llvm_type))?,
+        );
+        let variable = Variable {
+            ptr: llvm_global,
+            var_type: llvm_type,
+            is_const: false,
+            is_unsigned: global.var_type.as_ref().is_some_and(Type::is_unsigned),
+        };
+        self.global_vars
+            .borrow_mut()
+            .insert(global.name.clone(), variable);
         Ok(())
     }
+
+    /// Create the LLVM struct types of structs, which can refer to each other in any order.
+    ///
+    /// A struct that was already created by an earlier program is reused if its fields are the
+    /// same.
+    ///
+    /// # Arguments
+    /// * `structs` - The structs.
+    unsafe fn gen_struct_types(&self, structs: &[Struct]) -> Result<()> {
+        let mut created = Vec::new();
+        for definition in structs {
+            trace!("Generating struct type: {}", definition.name);
+            match self.struct_types.borrow().get(&definition.name) {
+                Some((struct_type, fields)) if core::LLVMIsOpaqueStruct(*struct_type) == 0 => {
+                    if *fields == definition.fields {
+                        continue;
+                    }
+                    return Err(format!("Struct `{}` already exists", definition.name));
+                }
+                _ => (),
+            }
+            let struct_type = self.struct_type(&definition.name);
+            self.struct_types.borrow_mut().insert(
+                definition.name.clone(),
+                (struct_type, definition.fields.clone()),
+            );
+            created.push((struct_type, definition));
+        }
+
+        // Bodies are set once every struct exists, since a field can hold any of them
+        for (struct_type, definition) in created {
+            let mut field_types = definition
+                .fields
+                .iter()
+                .map(|(_, field_type)| self.llvm_type(field_type))
+                .collect::<Vec<_>>();
+            core::LLVMStructSetBody(
+                struct_type,
+                field_types.as_mut_ptr(),
+                field_types.len() as u32,
+                false as i32,
+            );
+        }
+        Ok(())
+    }
+
+    /// Warn if a function has the same name as a built-in function.
+    ///
+    /// # Arguments
+    /// * `function` - The user-defined function.
+    fn warn_builtin_shadowing(&self, function: &Function) {
+        let (name, args) = match function {
+            Function::RegularFunction { name, args, .. } => (name, args),
+            // Declaring the libc function that a built-in calls doesn't change what it does
+            Function::ExternalFunction { name, .. }
+                if LIBC_BUILTIN_FUNCTIONS.contains(&&name[..]) =>
+            {
+                return
+            }
+            Function::ExternalFunction { name, args, .. } => (name, args),
+        };
+
+        if let Some((_, builtin)) = BUILTIN_FUNCTIONS.iter().find(|(b, _)| b == name) {
+            let args = args
+                .iter()
+                .map(|arg| &arg.name[..])
+                .collect::<Vec<_>>()
+                .join(", ");
+            warn!(
+                "Function `{}[{}]` shadows the built-in `{}`, calls to `{}` will use the former",
+                name, args, builtin, name
+            );
+        }
+    }
 }
@@ -1,9 +1,14 @@
 use crate::c_str;
-use crate::generator::Generator;
+use crate::generator::{Generator, Variable};
+use crate::parser::expression::Expression;
+use crate::parser::function::Function;
 use crate::parser::statement::Statement;
 use crate::Result;
 use llvm_sys::core;
-use log::{info, trace};
+use llvm_sys::prelude::{LLVMBasicBlockRef, LLVMTypeRef, LLVMValueRef};
+use llvm_sys::{LLVMIntPredicate, LLVMTypeKind};
+use log::{info, trace, warn};
+use std::collections::BTreeMap;
 
 impl Generator {
     pub unsafe fn gen_statement(&self, statement: &Statement) -> Result<()> {
@@ -11,69 +16,403 @@ impl Generator {
         match statement {
             Statement::CompoundStatement { statements } => {
                 trace!("Generating compound statement");
-                self.scope_var_names.borrow_mut().push(Vec::new());
-                info!("Added new scope: #{}", self.scope_var_names.borrow().len());
+                self.push_scope();
                 for statement in statements {
                     self.gen_statement(statement)?;
                 }
+                self.pop_scope();
+                Ok(())
+            }
+
+            Statement::IfStatement {
+                condition,
+                then_statement,
+                else_statement,
+            } => {
+                trace!("Generating if statement");
+                let condition = self.gen_expression(condition)?;
+                let condition_type = core::LLVMTypeOf(condition);
+                if core::LLVMGetTypeKind(condition_type) != LLVMTypeKind::LLVMIntegerTypeKind {
+                    return Err("If statement condition must be an integer".to_string());
+                }
+                let condition = core::LLVMBuildICmp(
+                    self.builder,
+                    LLVMIntPredicate::LLVMIntNE,
+                    condition,
+                    core::LLVMConstNull(condition_type),
+                    c_str!(""),
+                );
+
+                let function =
+                    core::LLVMGetBasicBlockParent(core::LLVMGetInsertBlock(self.builder));
+                let then_block =
+                    core::LLVMAppendBasicBlockInContext(self.context, function, c_str!("then"));
+                let else_block =
+                    core::LLVMAppendBasicBlockInContext(self.context, function, c_str!("else"));
+                let merge_block =
+                    core::LLVMAppendBasicBlockInContext(self.context, function, c_str!("merge"));
+                core::LLVMBuildCondBr(self.builder, condition, then_block, else_block);
 
-                let mut local_vars_mut = self.local_vars.borrow_mut();
-                for var in self.scope_var_names.borrow().last().unwrap() {
-                    info!("Deleting variable `{}`", var);
-                    local_vars_mut.remove(var);
+                core::LLVMPositionBuilderAtEnd(self.builder, then_block);
+                self.gen_statement(then_statement)?;
+                self.gen_branch_if_unterminated(merge_block);
+
+                core::LLVMPositionBuilderAtEnd(self.builder, else_block);
+                if let Some(else_statement) = else_statement {
+                    self.gen_statement(else_statement)?;
                 }
+                self.gen_branch_if_unterminated(merge_block);
 
-                self.scope_var_names.borrow_mut().pop();
+                core::LLVMPositionBuilderAtEnd(self.builder, merge_block);
                 Ok(())
             }
 
-            Statement::IfStatement { .. } => {
-                trace!("Generating if statement");
-                unimplemented!()
+            Statement::SwitchStatement {
+                value,
+                cases,
+                default,
+            } => {
+                trace!("Generating switch statement");
+                let value = self.gen_expression(value)?;
+                let value_type = core::LLVMTypeOf(value);
+                if core::LLVMGetTypeKind(value_type) != LLVMTypeKind::LLVMIntegerTypeKind {
+                    return Err("Switch statement value must be an integer".to_string());
+                }
+
+                let function =
+                    core::LLVMGetBasicBlockParent(core::LLVMGetInsertBlock(self.builder));
+                let case_blocks = cases
+                    .iter()
+                    .map(|_| {
+                        core::LLVMAppendBasicBlockInContext(self.context, function, c_str!("case"))
+                    })
+                    .collect::<Vec<_>>();
+                let default_block =
+                    core::LLVMAppendBasicBlockInContext(self.context, function, c_str!("default"));
+                let merge_block =
+                    core::LLVMAppendBasicBlockInContext(self.context, function, c_str!("merge"));
+
+                let switch =
+                    core::LLVMBuildSwitch(self.builder, value, default_block, cases.len() as u32);
+                for ((case, _), case_block) in cases.iter().zip(&case_blocks) {
+                    core::LLVMAddCase(
+                        switch,
+                        self.gen_integer_literal(*case, Some(value_type))?,
+                        *case_block,
+                    );
+                }
+
+                // Every case branches to the merge block, so cases never fall through
+                for ((_, statement), case_block) in cases.iter().zip(case_blocks) {
+                    core::LLVMPositionBuilderAtEnd(self.builder, case_block);
+                    self.gen_statement(statement)?;
+                    self.gen_branch_if_unterminated(merge_block);
+                }
+
+                core::LLVMPositionBuilderAtEnd(self.builder, default_block);
+                if let Some(default) = default {
+                    self.gen_statement(default)?;
+                }
+                self.gen_branch_if_unterminated(merge_block);
+
+                core::LLVMPositionBuilderAtEnd(self.builder, merge_block);
+                Ok(())
             }
 
-            Statement::ReturnStatement { value } => {
+            Statement::DoWhileStatement { body, condition } => {
+                trace!("Generating do-while statement");
+                let function =
+                    core::LLVMGetBasicBlockParent(core::LLVMGetInsertBlock(self.builder));
+                let body_block =
+                    core::LLVMAppendBasicBlockInContext(self.context, function, c_str!("loop"));
+                let condition_block =
+                    core::LLVMAppendBasicBlockInContext(self.context, function, c_str!("cond"));
+                let merge_block =
+                    core::LLVMAppendBasicBlockInContext(self.context, function, c_str!("merge"));
+                core::LLVMBuildBr(self.builder, body_block);
+
+                core::LLVMPositionBuilderAtEnd(self.builder, body_block);
+                self.gen_statement(body)?;
+                self.gen_branch_if_unterminated(condition_block);
+
+                // The condition is checked after the body, so the body always runs once
+                core::LLVMPositionBuilderAtEnd(self.builder, condition_block);
+                let condition = self.gen_expression(condition)?;
+                let condition_type = core::LLVMTypeOf(condition);
+                if core::LLVMGetTypeKind(condition_type) != LLVMTypeKind::LLVMIntegerTypeKind {
+                    return Err("Do-while statement condition must be an integer".to_string());
+                }
+                let condition = core::LLVMBuildICmp(
+                    self.builder,
+                    LLVMIntPredicate::LLVMIntNE,
+                    condition,
+                    core::LLVMConstNull(condition_type),
+                    c_str!(""),
+                );
+                core::LLVMBuildCondBr(self.builder, condition, body_block, merge_block);
+
+                core::LLVMPositionBuilderAtEnd(self.builder, merge_block);
+                Ok(())
+            }
+
+            Statement::ReturnStatement { value, .. } => {
                 trace!("Generating return statement");
-                core::LLVMBuildRet(self.builder, self.gen_expression(value)?);
+                let function =
+                    core::LLVMGetBasicBlockParent(core::LLVMGetInsertBlock(self.builder));
+                let return_type =
+                    core::LLVMGetReturnType(core::LLVMGetElementType(core::LLVMTypeOf(function)));
+                let is_void = core::LLVMGetTypeKind(return_type) == LLVMTypeKind::LLVMVoidTypeKind;
+
+                match value {
+                    Some(value) if !is_void => {
+                        let value = self.gen_expression_of_type(value, Some(return_type))?;
+                        core::LLVMBuildRet(self.builder, value);
+                    }
+                    None if is_void => {
+                        core::LLVMBuildRetVoid(self.builder);
+                    }
+                    Some(_) => {
+                        return Err(format!(
+                            "Void function `{}` can't return a value",
+                            self.value_name(function)
+                        ))
+                    }
+                    None => {
+                        return Err(format!(
+                            "Function `{}` must return a value",
+                            self.value_name(function)
+                        ))
+                    }
+                }
                 Ok(())
             }
 
-            Statement::VariableDeclarationStatement { name, value } => {
+            Statement::VariableDeclarationStatement {
+                name,
+                is_const,
+                var_type,
+                value,
+                ..
+            } => {
                 trace!("Generating variable declaration statement: {}", name);
-                let mut local_vars_mut = self.local_vars.borrow_mut();
+                self.check_undeclared(name)?;
 
-                if local_vars_mut.contains_key(name) {
-                    return Err(format!("Variable `{}` already exists", name));
+                let declared_type = var_type.as_ref().map(|t| self.llvm_type(t));
+                let value_expression = value;
+                let value = match value {
+                    Some(value) => Some(self.gen_expression_of_type(value, declared_type)?),
+                    None => None,
+                };
+                let llvm_type = match (declared_type, value) {
+                    (Some(declared_type), _) => declared_type,
+                    (None, Some(value)) => core::LLVMTypeOf(value),
+                    (None, None) => self.i32_type(),
+                };
+                let is_unsigned = match (var_type, value_expression) {
+                    (Some(var_type), _) => var_type.is_unsigned(),
+                    (None, Some(value)) => self.is_unsigned(value),
+                    (None, None) => false,
+                };
+
+                let var = self.gen_local_variable(name, llvm_type, *is_const, is_unsigned);
+                if let Some(value) = value {
+                    core::LLVMBuildStore(self.builder, value, var);
                 }
+                Ok(())
+            }
 
-                let var = core::LLVMBuildAlloca(self.builder, self.i32_type(), c_str!(""));
-                if name != "_" {
-                    info!("Adding `{}` to local vars", name);
-                    local_vars_mut.insert(String::from(name), var);
-                    self.scope_var_names
-                        .borrow_mut()
-                        .last_mut()
-                        .unwrap()
-                        .push(String::from(name));
+            Statement::TupleDeclarationStatement { names, value, .. } => {
+                trace!("Generating tuple declaration statement: {:?}", names);
+                for name in names {
+                    self.check_undeclared(name)?;
                 }
 
-                drop(local_vars_mut);
-                if let Some(value) = value {
-                    core::LLVMBuildStore(self.builder, self.gen_expression(value)?, var);
+                let value = self.gen_expression(value)?;
+                let value_type = core::LLVMTypeOf(value);
+                if core::LLVMGetTypeKind(value_type) != LLVMTypeKind::LLVMStructTypeKind
+                    || core::LLVMCountStructElementTypes(value_type) as usize != names.len()
+                {
+                    return Err(format!("Expected a tuple of {} values", names.len()));
+                }
+
+                for (i, name) in names.iter().enumerate() {
+                    let element =
+                        core::LLVMBuildExtractValue(self.builder, value, i as u32, c_str!(""));
+                    let var =
+                        self.gen_local_variable(name, core::LLVMTypeOf(element), false, false);
+                    core::LLVMBuildStore(self.builder, element, var);
                 }
                 Ok(())
             }
 
             Statement::ExpressionStatement { expression } => {
                 trace!("Generating expression statement");
-                self.gen_expression(expression)?;
+                // Calls to void functions are only allowed when their value isn't used
+                if let Expression::FunctionCallExpression { name, args, .. } = expression.as_ref() {
+                    self.gen_function_call(name, args)?;
+                } else {
+                    self.gen_expression(expression)?;
+                }
                 Ok(())
             }
 
+            Statement::FunctionDefinitionStatement { function } => match function.as_ref() {
+                Function::RegularFunction { name, .. }
+                | Function::ExternalFunction { name, .. } => Err(format!(
+                    "Nested function `{}` must be hoisted before generating code",
+                    name
+                )),
+            },
+
             Statement::NoOpStatement => {
                 trace!("Generating no op statement");
                 Ok(())
             }
         }
     }
+
+    /// Start a new scope for variable declarations.
+    pub fn push_scope(&self) {
+        self.local_vars.borrow_mut().push(BTreeMap::new());
+        info!("Added new scope: #{}", self.local_vars.borrow().len());
+    }
+
+    /// Check that a variable isn't already declared in the current scope.
+    ///
+    /// Variables in outer scopes can be shadowed.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the variable.
+    fn check_undeclared(&self, name: &str) -> Result<()> {
+        match self.local_vars.borrow().last() {
+            Some(scope) if scope.contains_key(name) => {
+                Err(format!("Variable `{}` already exists", name))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Warn if a local variable shadows a local variable of an outer scope or an argument.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the new local variable.
+    /// * `function` - The function the variable is declared in.
+    unsafe fn warn_shadowing(&self, name: &str, function: LLVMValueRef) {
+        let shadowed = {
+            let local_vars = self.local_vars.borrow();
+            let outer_scopes = &local_vars[..local_vars.len() - 1];
+            outer_scopes
+                .iter()
+                .rposition(|scope| scope.contains_key(name))
+        };
+        let shadowed = match shadowed {
+            // Arguments are in the outermost scope
+            Some(0) => format!("the argument `{}`", name),
+            Some(_) => format!("`{}` from an outer scope", name),
+            None => return,
+        };
+
+        let function_name = self.value_name(function);
+        match self.program.function(&function_name) {
+            Some(Function::RegularFunction { line, .. }) if *line > 0 => warn!(
+                "Variable `{}` shadows {} in function `{}` at line {}",
+                name, shadowed, function_name, line
+            ),
+            _ => warn!(
+                "Variable `{}` shadows {} in function `{}`",
+                name, shadowed, function_name
+            ),
+        }
+    }
+
+    /// Allocate a local variable and add it to the current scope unless it is named `_`.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the variable.
+    /// * `llvm_type` - The type of the variable.
+    /// * `is_const` - Whether or not the variable can't be assigned to.
+    /// * `is_unsigned` - Whether or not the variable, or the elements of its array, are unsigned.
+    pub unsafe fn gen_local_variable(
+        &self,
+        name: &str,
+        llvm_type: LLVMTypeRef,
+        is_const: bool,
+        is_unsigned: bool,
+    ) -> LLVMValueRef {
+        let var = self.gen_entry_alloca(llvm_type, None);
+        if name != "_" {
+            if self.options.warn_shadow {
+                let function =
+                    core::LLVMGetBasicBlockParent(core::LLVMGetInsertBlock(self.builder));
+                self.warn_shadowing(name, function);
+            }
+            info!("Adding `{}` to local vars", name);
+            let local = Variable {
+                ptr: var,
+                var_type: llvm_type,
+                is_const,
+                is_unsigned,
+            };
+            self.local_vars
+                .borrow_mut()
+                .last_mut()
+                .unwrap()
+                .insert(String::from(name), local);
+        }
+        var
+    }
+
+    /// Allocate a value, or an array of values, at the start of the current function.
+    ///
+    /// Allocating in the entry block keeps loops from growing the stack on every iteration.
+    ///
+    /// # Arguments
+    /// * `llvm_type` - The type of the value.
+    /// * `count` - The number of values in the array, `None` if it isn't an array.
+    pub unsafe fn gen_entry_alloca(
+        &self,
+        llvm_type: LLVMTypeRef,
+        count: Option<u32>,
+    ) -> LLVMValueRef {
+        let function = core::LLVMGetBasicBlockParent(core::LLVMGetInsertBlock(self.builder));
+        let entry_block = core::LLVMGetEntryBasicBlock(function);
+        let builder = core::LLVMCreateBuilderInContext(self.context);
+        let first_instruction = core::LLVMGetFirstInstruction(entry_block);
+        if first_instruction.is_null() {
+            core::LLVMPositionBuilderAtEnd(builder, entry_block);
+        } else {
+            core::LLVMPositionBuilderBefore(builder, first_instruction);
+        }
+        let ptr = match count {
+            Some(count) => core::LLVMBuildArrayAlloca(
+                builder,
+                llvm_type,
+                core::LLVMConstInt(self.i32_type(), count as u64, false as i32),
+                c_str!(""),
+            ),
+            None => core::LLVMBuildAlloca(builder, llvm_type, c_str!("")),
+        };
+        core::LLVMDisposeBuilder(builder);
+        ptr
+    }
+
+    /// End the innermost scope, restoring the variables its declarations shadowed.
+    pub fn pop_scope(&self) {
+        if let Some(scope) = self.local_vars.borrow_mut().pop() {
+            for var in scope.keys() {
+                info!("Deleting variable `{}`", var);
+            }
+        }
+    }
+
+    /// Branch to a block unless the current block already ends with a terminator (e.g. a return).
+    ///
+    /// # Arguments
+    /// * `block` - The block to branch to.
+    unsafe fn gen_branch_if_unterminated(&self, block: LLVMBasicBlockRef) {
+        let current_block = core::LLVMGetInsertBlock(self.builder);
+        if core::LLVMGetBasicBlockTerminator(current_block).is_null() {
+            core::LLVMBuildBr(self.builder, block);
+        }
+    }
 }
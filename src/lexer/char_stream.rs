@@ -0,0 +1,118 @@
+use crate::lexer::tokens::Span;
+use log::trace;
+use std::io::BufRead;
+use std::vec::IntoIter;
+
+/// A stream of characters read line by line from a reader, with one character of lookahead.
+pub struct CharStream {
+    /// The reader to read lines from.
+    reader: Box<dyn BufRead>,
+    /// The characters of the current line.
+    chars: IntoIter<char>,
+    /// The next character, if it has been peeked.
+    peeked: Option<Option<char>>,
+    /// The error that ended the stream early, if any.
+    error: Option<String>,
+    /// The line of the next character, starting at 1.
+    line: usize,
+    /// The column of the next character, starting at 1.
+    column: usize,
+    /// The number of columns between tab stops.
+    tab_width: usize,
+}
+
+impl CharStream {
+    /// Create a character stream from a reader.
+    ///
+    /// # Arguments
+    /// * `reader` - The reader to stream characters from.
+    pub fn new(reader: Box<dyn BufRead>) -> Self {
+        CharStream {
+            reader,
+            chars: Vec::new().into_iter(),
+            peeked: None,
+            error: None,
+            line: 1,
+            column: 1,
+            tab_width: 1,
+        }
+    }
+
+    /// Set the number of columns between tab stops, so a tab advances the column to the next stop.
+    ///
+    /// # Arguments
+    /// * `tab_width` - The number of columns between tab stops, at least 1.
+    pub fn set_tab_width(&mut self, tab_width: usize) {
+        self.tab_width = tab_width.max(1);
+    }
+
+    /// Get the next character without consuming it.
+    pub fn peek(&mut self) -> Option<&char> {
+        if self.peeked.is_none() {
+            self.peeked = Some(self.read_char());
+        }
+        self.peeked.as_ref().unwrap().as_ref()
+    }
+
+    /// Get the position of the next character.
+    pub fn position(&self) -> Span {
+        Span {
+            line: self.line,
+            column: self.column,
+        }
+    }
+
+    /// Take the error that ended the stream, if any.
+    pub fn take_error(&mut self) -> Option<String> {
+        self.error.take()
+    }
+
+    /// Read the next character, reading a new line from the reader if needed.
+    fn read_char(&mut self) -> Option<char> {
+        loop {
+            if let Some(c) = self.chars.next() {
+                return Some(c);
+            }
+            if self.error.is_some() {
+                return None;
+            }
+
+            let mut line = String::new();
+            match self.reader.read_line(&mut line) {
+                Ok(0) => return None,
+                Ok(_) => {
+                    trace!("Read line of {} bytes", line.len());
+                    self.chars = line.chars().collect::<Vec<_>>().into_iter();
+                }
+                Err(e) => {
+                    self.error = Some(format!("Unable to read input: {}", e));
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+impl Iterator for CharStream {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        let c = match self.peeked.take() {
+            Some(c) => c,
+            None => self.read_char(),
+        };
+        match c {
+            Some('\n') => {
+                self.line += 1;
+                self.column = 1;
+            }
+            Some('\t') => {
+                self.column =
+                    (self.column - 1) / self.tab_width * self.tab_width + self.tab_width + 1
+            }
+            Some(_) => self.column += 1,
+            None => (),
+        }
+        c
+    }
+}
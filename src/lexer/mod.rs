@@ -1,18 +1,28 @@
+mod char_stream;
 pub mod tokens;
 
+use crate::lexer::char_stream::CharStream;
 use crate::lexer::tokens::*;
 use crate::Result;
 use log::trace;
-use std::iter::Peekable;
-use std::vec::IntoIter;
-use std::{fs, io};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Cursor};
+use std::num::IntErrorKind;
 
 /// A lexical analyzer that splits the program into [`Token`]s.
 ///
 /// [`Token`]: tokens/enum.Token.html
 pub struct Lexer {
     /// The raw program characters.
-    raw_data: Peekable<IntoIter<char>>,
+    raw_data: CharStream,
+    /// The position of the last token.
+    span: Span,
+    /// Whether or not comments are produced as tokens.
+    comments: bool,
+    /// Whether or not the last token ends an operand, so a `-` after it is a binary minus.
+    after_operand: bool,
+    /// Whether or not the last token is a unary minus.
+    negated: bool,
 }
 
 impl Lexer {
@@ -21,7 +31,7 @@ impl Lexer {
     /// # Arguments
     /// * `file_path` - The path to the program file.
     pub fn from_file(file_path: &str) -> io::Result<Self> {
-        Ok(Self::from_text(&fs::read_to_string(file_path)?))
+        Ok(Self::from_reader(BufReader::new(File::open(file_path)?)))
     }
 
     /// Create a lexer given the program data as plain text.
@@ -29,8 +39,55 @@ impl Lexer {
     /// # Arguments
     /// * `text` - The raw program.
     pub fn from_text(text: &str) -> Self {
+        Self::from_reader(Cursor::new(text.to_string()))
+    }
+
+    /// Create a lexer that reads the program incrementally from a reader.
+    ///
+    /// # Arguments
+    /// * `reader` - The reader to read the program from.
+    pub fn from_reader<R: BufRead + 'static>(reader: R) -> Self {
         Lexer {
-            raw_data: text.chars().collect::<Vec<_>>().into_iter().peekable(),
+            raw_data: CharStream::new(Box::new(reader)),
+            span: Span::default(),
+            comments: false,
+            after_operand: false,
+            negated: false,
+        }
+    }
+
+    /// Produce comments as [`Comment`] tokens instead of ignoring them.
+    ///
+    /// [`Comment`]: tokens/enum.Token.html#variant.Comment
+    pub fn with_comments(mut self) -> Self {
+        self.comments = true;
+        self
+    }
+
+    /// Count a tab as advancing the column to the next multiple of a tab width, instead of as a
+    /// single column, so positions line up with an editor's.
+    ///
+    /// # Arguments
+    /// * `tab_width` - The number of columns between tab stops.
+    pub fn with_tab_width(mut self, tab_width: usize) -> Self {
+        self.raw_data.set_tab_width(tab_width);
+        self
+    }
+
+    /// Get the position of the last token, or of the last error.
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    /// Get the number of lines that have been read, which is every line of the program once all
+    /// of its tokens have been lexed.
+    pub fn line_count(&self) -> usize {
+        let position = self.raw_data.position();
+        // The last line has been read completely if it ended with a newline
+        if position.column == 1 {
+            position.line - 1
+        } else {
+            position.line
         }
     }
 
@@ -57,6 +114,228 @@ impl Lexer {
         }
     }
 
+    /// Lex an integer literal in base 10, or in base 16, 8, or 2 with a `0x`, `0o`, or `0b` prefix.
+    ///
+    /// Digits can be separated by single underscores (`1_000`). After a unary minus, the literal
+    /// can be 2147483648 so that `-2147483648` is the i32 minimum. It is lexed as the wrapped
+    /// value, which stays the same when negated.
+    ///
+    /// Hexadecimal, octal, and binary literals can be up to `0xFFFFFFFF` so that every bit pattern
+    /// of a `u32` can be written. Values above the i32 maximum are lexed as the i32 with the same
+    /// bits.
+    ///
+    /// A literal can end with a width suffix (`i8`, `i16`, `i32`, or `i64`), such as `5i64`.
+    ///
+    /// # Arguments
+    /// * `first_char` - The first digit of the literal.
+    fn lex_integer_literal(&mut self, first_char: char) -> Result<Token> {
+        let mut raw = first_char.to_string();
+        let (radix, base_name) = match (first_char, self.raw_data.peek()) {
+            ('0', Some('x')) => (16, "hexadecimal"),
+            ('0', Some('o')) => (8, "octal"),
+            ('0', Some('b')) => (2, "binary"),
+            _ => (10, "decimal"),
+        };
+
+        let mut suffix = String::new();
+        let digits = if radix == 10 {
+            self.get_next_char_while(&mut raw, |c| c.is_numeric() || c == '_');
+            let digits = raw.clone();
+            if self.raw_data.peek() == Some(&'i') {
+                self.get_next_char_while(&mut suffix, |c| c.is_ascii_alphanumeric());
+                raw.push_str(&suffix);
+            }
+            digits
+        } else {
+            raw.push(self.raw_data.next().unwrap()); // Eat prefix
+            let mut digits = String::new();
+            self.get_next_char_while(&mut digits, |c| c.is_ascii_alphanumeric() || c == '_');
+            raw.push_str(&digits);
+            // `i` isn't a digit in any base
+            if let Some(i) = digits.find('i') {
+                suffix = digits.split_off(i);
+            }
+
+            if digits.is_empty() {
+                return Err(format!("Integer literal {} has no digits", raw));
+            }
+            if let Some(c) = digits.chars().find(|&c| c != '_' && !c.is_digit(radix)) {
+                return Err(format!(
+                    "Invalid digit `{}` in {} integer literal {}",
+                    c, base_name, raw
+                ));
+            }
+            digits
+        };
+
+        // Digit separators are only allowed between digits
+        if digits.starts_with('_') || digits.ends_with('_') || digits.contains("__") {
+            return Err(format!(
+                "Integer literal {} has a misplaced `_` separator",
+                raw
+            ));
+        }
+        let digits = digits.replace('_', "");
+        if !suffix.is_empty() {
+            return self.lex_sized_integer_literal(&raw, &digits, radix, &suffix);
+        }
+
+        match i32::from_str_radix(&digits, radix) {
+            Ok(i) => Ok(Token::Literal(Literal::Integer(i))),
+            Err(e) if *e.kind() == IntErrorKind::PosOverflow => {
+                if self.negated && i64::from_str_radix(&digits, radix) == Ok(1 << 31) {
+                    Ok(Token::Literal(Literal::Integer(i32::MIN)))
+                } else if radix != 10 {
+                    u32::from_str_radix(&digits, radix)
+                        .map(|i| Token::Literal(Literal::Integer(i as i32)))
+                        .map_err(|_| format!("Integer literal {} exceeds u32 range", raw))
+                } else {
+                    Err(format!("Integer literal {} exceeds i32 range", raw))
+                }
+            }
+            Err(_) => Err(format!("Integer literal {} is invalid", raw)),
+        }
+    }
+
+    /// Lex the value of an integer literal with a width suffix.
+    ///
+    /// Like literals without a suffix, the minimum of the width can be written after a unary
+    /// minus, and hexadecimal, octal, and binary literals can use every bit of the width.
+    ///
+    /// # Arguments
+    /// * `raw` - The whole literal, for errors.
+    /// * `digits` - The digits of the literal without separators.
+    /// * `radix` - The base of the digits.
+    /// * `suffix` - The width suffix, such as `i64`.
+    fn lex_sized_integer_literal(
+        &self,
+        raw: &str,
+        digits: &str,
+        radix: u32,
+        suffix: &str,
+    ) -> Result<Token> {
+        let bits = match suffix {
+            "i8" => 8,
+            "i16" => 16,
+            "i32" => 32,
+            "i64" => 64,
+            _ => {
+                return Err(format!(
+                    "Invalid suffix `{}` on integer literal {}",
+                    suffix, raw
+                ))
+            }
+        };
+        let max = match (radix, self.negated) {
+            (10, false) => (1 << (bits - 1)) - 1,
+            (10, true) => 1 << (bits - 1),
+            _ => u64::MAX >> (64 - bits),
+        };
+
+        match u64::from_str_radix(digits, radix) {
+            Ok(value) if value <= max => {
+                // Sign extend the bits of the width
+                let value = ((value << (64 - bits)) as i64) >> (64 - bits);
+                Ok(Token::Literal(Literal::SizedInteger { value, bits }))
+            }
+            _ => Err(format!("Integer literal {} exceeds i{} range", raw, bits)),
+        }
+    }
+
+    /// Lex a character literal after its opening `'`.
+    fn lex_char_literal(&mut self) -> Result<Token> {
+        let value = match self.raw_data.next() {
+            Some('\'') => return Err("Empty character literal".to_string()),
+            Some('\\') => match self.raw_data.next() {
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some('r') => '\r',
+                Some('0') => '\0',
+                Some(c @ '\'') | Some(c @ '\\') | Some(c @ '"') => c,
+                Some(c) => return Err(format!("Unknown escape sequence `\\{}`", c)),
+                None => return Err("Unterminated character literal".to_string()),
+            },
+            Some(c) => c,
+            None => return Err("Unterminated character literal".to_string()),
+        };
+
+        match self.raw_data.next() {
+            Some('\'') => Ok(Token::Literal(Literal::Char(value))),
+            Some(c) => {
+                let mut raw = format!("{}{}", value, c);
+                self.get_next_char_while(&mut raw, |c| c != '\'' && c != '\n');
+                self.raw_data.next(); // Eat ending '
+                Err(format!(
+                    "Character literal '{}' contains more than one character",
+                    raw
+                ))
+            }
+            None => Err("Unterminated character literal".to_string()),
+        }
+    }
+
+    /// Lex a string literal after its opening `"`, or a multiline string literal if it opens
+    /// with `"""`.
+    fn lex_string_literal(&mut self) -> Result<Token> {
+        let mut value = String::new();
+        if self.raw_data.peek() == Some(&'"') {
+            self.raw_data.next();
+            if self.raw_data.peek() != Some(&'"') {
+                return Ok(Token::Literal(Literal::Str(value)));
+            }
+            self.raw_data.next();
+            return self.lex_multiline_string_literal();
+        }
+
+        self.get_next_char_while(&mut value, |c| c != '"');
+
+        // Eat ending ", which is missing if the input ended first
+        match self.raw_data.next() {
+            Some(_) => Ok(Token::Literal(Literal::Str(value))),
+            None => Err(self.unterminated_string_error()),
+        }
+    }
+
+    /// Lex a multiline string literal after its opening `"""`, ending at the next `"""`.
+    ///
+    /// A newline right after the opening `"""` isn't a part of the string, so the first line can
+    /// start on the next line. Indentation is kept as written. A `\` at the end of a line
+    /// continues the line, leaving out the newline and the indentation of the next line.
+    fn lex_multiline_string_literal(&mut self) -> Result<Token> {
+        trace!("Lexing multiline string literal");
+        if self.raw_data.peek() == Some(&'\n') {
+            self.raw_data.next();
+        }
+
+        let mut value = String::new();
+        let mut quotes = 0;
+        while quotes < 3 {
+            match self.raw_data.next() {
+                Some('"') => quotes += 1,
+                Some(c) => {
+                    value.extend(std::iter::repeat('"').take(quotes));
+                    quotes = 0;
+                    if c == '\\' && self.raw_data.peek() == Some(&'\n') {
+                        let mut indentation = String::new();
+                        self.get_next_char_while(&mut indentation, char::is_whitespace);
+                    } else {
+                        value.push(c);
+                    }
+                }
+                None => return Err(self.unterminated_string_error()),
+            }
+        }
+        Ok(Token::Literal(Literal::Str(value)))
+    }
+
+    /// The error for a string literal that the input ended in.
+    fn unterminated_string_error(&self) -> String {
+        format!(
+            "Unterminated string literal at line {}, column {}",
+            self.span.line, self.span.column
+        )
+    }
+
     /// Check if a character is a part of an identifier.
     ///
     /// Identifiers must start with an alphabetic character or underscore, but can then include
@@ -67,27 +346,20 @@ impl Lexer {
     fn is_identifier(c: char) -> bool {
         c.is_ascii_alphanumeric() || c == '_'
     }
-}
-
-impl Iterator for Lexer {
-    type Item = Result<Token>;
 
     /// Identifies the next token, `None` if the end of the program has been reached.
-    fn next(&mut self) -> Option<Self::Item> {
+    fn lex_token(&mut self) -> Option<Result<Token>> {
         let token: Result<Token>;
-        let first_char: char;
 
         // Find first non-whitespace character
-        loop {
+        let first_char = loop {
+            self.span = self.raw_data.position();
             match self.raw_data.next() {
                 Some(c) if c.is_whitespace() => continue,
-                Some(c) => {
-                    first_char = c;
-                    break;
-                }
-                None => return None,
+                Some(c) => break c,
+                None => return self.raw_data.take_error().map(Err),
             }
-        }
+        };
 
         trace!("First char: {}", first_char);
 
@@ -102,34 +374,24 @@ impl Iterator for Lexer {
         // Integer Literal
         else if first_char.is_numeric() {
             trace!("Lexing integer literal");
-            let mut value = first_char.to_string();
-            self.get_next_char_while(&mut value, |c| c.is_numeric());
-
-            token = match value.parse() {
-                Ok(i) => Ok(Token::Literal(Literal::Integer(i))),
-                Err(_) => Err(format!("Integer literal {} is invalid", value)),
-            }
+            token = self.lex_integer_literal(first_char);
+        }
+        // Character Literal
+        else if first_char == '\'' {
+            trace!("Lexing character literal");
+            token = self.lex_char_literal();
         }
         // String Literal
         else if first_char == '"' {
             trace!("Lexing string literal");
-            let mut value = String::new();
-
-            self.get_next_char_while(&mut value, |c| c != '"');
-            self.raw_data.next(); // Eat ending "
-
-            token = Ok(Token::Literal(Literal::Str(value)));
+            token = self.lex_string_literal();
         }
         // Symbol
         else {
             trace!("Lexing symbol");
             let mut raw = first_char.to_string();
-            loop {
-                if let Some(peek) = self.raw_data.peek() {
-                    raw.push(*peek);
-                } else {
-                    break;
-                }
+            while let Some(peek) = self.raw_data.peek() {
+                raw.push(*peek);
 
                 if VALID_SYMBOLS.contains(&&raw[..]) {
                     self.raw_data.next();
@@ -141,13 +403,22 @@ impl Iterator for Lexer {
 
             token = match &raw[..] {
                 // Ignore comments until newline
-                s if s == "//" => {
-                    trace!("Ignoring comment");
-                    self.get_next_char_while(&mut String::new(), |c| c != '\n');
-                    self.next()?
+                "//" => {
+                    let mut text = String::new();
+                    self.get_next_char_while(&mut text, |c| c != '\n');
+                    if self.comments {
+                        trace!("Lexing comment");
+                        Ok(Token::Comment(text))
+                    } else {
+                        trace!("Ignoring comment");
+                        self.lex_token()?
+                    }
                 }
                 s if VALID_SYMBOLS.contains(&s) => Ok(Token::Symbol(raw)),
-                _ => Err(format!("Unknown token: {}", raw)),
+                _ => Err(format!(
+                    "Unknown token: {} at line {}, column {}",
+                    raw, self.span.line, self.span.column
+                )),
             }
         }
 
@@ -155,6 +426,27 @@ impl Iterator for Lexer {
     }
 }
 
+impl Iterator for Lexer {
+    type Item = Result<Token>;
+
+    /// Identifies the next token, `None` if the end of the program has been reached.
+    fn next(&mut self) -> Option<Self::Item> {
+        let token = self.lex_token()?;
+        match &token {
+            Ok(Token::Comment(_)) => (),
+            Ok(Token::Symbol(s)) => {
+                self.negated = s == "-" && !self.after_operand;
+                self.after_operand = [")", "]", "}", "++", "--"].contains(&&s[..]);
+            }
+            _ => {
+                self.negated = false;
+                self.after_operand = token.is_ok();
+            }
+        }
+        Some(token)
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
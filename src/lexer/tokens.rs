@@ -1,3 +1,5 @@
+use std::fmt;
+
 /// A token that is parsed by the [`Lexer`].
 ///
 /// [`Lexer`]: ../struct.Lexer.html
@@ -11,26 +13,80 @@ pub enum Token {
     Literal(Literal),
     /// A known symbol.
     Symbol(String),
+    /// The text of a comment after `//`, only produced by a lexer created with comments.
+    Comment(String),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Identifier(name) => write!(f, "{}", name),
+            Token::Literal(value) => write!(f, "{}", value),
+            Token::Symbol(s) => write!(f, "{}", s),
+            Token::Comment(text) => write!(f, "//{}", text),
+        }
+    }
 }
 
-/// A literal value token, either an integer or a string.
+/// The position of a token in the program, starting at line 1 and column 1.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Span {
+    /// The line of the first character of the token.
+    pub line: usize,
+    /// The column of the first character of the token.
+    pub column: usize,
+}
+
+/// A literal value token, either an integer, a character, or a string.
 #[derive(Debug, Clone, PartialEq)]
 pub enum Literal {
     /// A literal signed 32-bit integer.
     Integer(i32),
+    /// A literal signed integer with a width suffix, such as `5i64`.
+    SizedInteger { value: i64, bits: u32 },
+    /// A literal character.
+    Char(char),
     /// A literal string.
     Str(String),
 }
 
+impl fmt::Display for Literal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Literal::Integer(value) => write!(f, "{}", value),
+            Literal::SizedInteger { value, bits } => write!(f, "{}i{}", value, bits),
+            Literal::Char(c) => write!(f, "'{}'", escape(*c)),
+            // Strings don't have escape sequences
+            Literal::Str(s) => write!(f, "\"{}\"", s),
+        }
+    }
+}
+
+/// Escape a character literal the way it would be written in the source.
+///
+/// # Arguments
+/// * `c` - The character.
+fn escape(c: char) -> String {
+    match c {
+        '\n' => String::from("\\n"),
+        '\t' => String::from("\\t"),
+        '\r' => String::from("\\r"),
+        '\0' => String::from("\\0"),
+        '\\' => String::from("\\\\"),
+        '\'' => String::from("\\'"),
+        c => c.to_string(),
+    }
+}
+
 /// A list of valid symbols.
 ///
 /// If a symbol is not in this list, it will be regarded as an [`Unknown`] token and cause a lexer
-/// error.
+/// error. Symbols are lexed one character at a time, so `..` is only a symbol to lex `...`.
 ///
 /// [`Unknown`]: Token::Unknown
 pub const VALID_SYMBOLS: &[&str] = &[
-    "=", "+", "-", "*", "/", "==", "!=", "<", ">", "<=", ">=", "?", ":", "@", "@!", "->", ";", ",",
-    "{", "}", "[", "]", "(", ")", "//",
+    "=", "+", "-", "*", "/", "**", "==", "!=", "<", ">", "<=", ">=", "++", "--", "?", "%", "~",
+    ":", "@", "@!", "->", ";", ",", "{", "}", "[", "]", "(", ")", "//", "$", ".", "..", "...", "&",
 ];
 
 /// Gets the precedence of an binary operation.
@@ -42,12 +98,39 @@ pub const VALID_SYMBOLS: &[&str] = &[
 pub fn binary_op_precedence(op: &str) -> i32 {
     match op {
         "=" => 0,
-        "==" | "!=" | "<" | ">" | "<=" | ">=" => 10,
+        op if is_comparison(op) => 10,
         "+" | "-" => 20,
         "*" | "/" => 30,
+        "**" => 40,
         _ => -1,
     }
 }
 
+/// Check if a binary operation compares its operands, resulting in 1 or 0.
+///
+/// # Arguments
+/// * `op` - The binary operation.
+pub fn is_comparison(op: &str) -> bool {
+    matches!(op, "==" | "!=" | "<" | ">" | "<=" | ">=")
+}
+
+/// Check if a binary operation groups from right to left, so `a ** b ** c` is `a ** (b ** c)`.
+///
+/// # Arguments
+/// * `op` - The binary operation.
+pub fn is_right_associative(op: &str) -> bool {
+    op == "**"
+}
+
+/// The precedence of unary operations, compared to the precedence of binary operations.
+///
+/// Binary operations with a higher precedence bind tighter, so `-x ** 2` is `-(x ** 2)`.
+pub const UNARY_OP_PRECEDENCE: i32 = 35;
+
 /// A list of valid unary symbols.
-pub const UNARY_SYMBOLS: &[&str] = &["-"];
+///
+/// `**` is lexed as one symbol, so at the start of an expression it is two dereferences.
+pub const UNARY_SYMBOLS: &[&str] = &["-", "&", "*", "**"];
+
+/// A list of valid postfix symbols.
+pub const POSTFIX_SYMBOLS: &[&str] = &["++", "--"];
@@ -1,9 +1,25 @@
+#![allow(clippy::missing_safety_doc)]
+
+pub mod builtins;
+pub mod diagnostic;
 pub mod generator;
 pub mod lexer;
 pub mod parser;
+pub mod repl;
+pub mod watch;
 
+use crate::diagnostic::{Diagnostic, Severity};
+use crate::generator::{CodeModel, Generator, Relocation};
+use crate::lexer::Lexer;
+use crate::parser::const_eval::fold_const_calls;
+use crate::parser::optimize::fold_constants;
+use crate::parser::prelude::add_prelude;
+use crate::parser::typecheck::typecheck;
+use crate::parser::Parser;
 use clap::{App, Arg};
-use log::LevelFilter;
+use log::{Level, LevelFilter};
+use std::env;
+use std::io::Write;
 use std::path;
 
 pub type Result<T> = std::result::Result<T, String>;
@@ -12,15 +28,29 @@ pub type Result<T> = std::result::Result<T, String>;
 pub enum OutputFormat {
     /// LLVM Intermediate Representation.
     LLVM,
+    /// Native assembly.
+    Assembly,
     /// Unlinked object file.
     ObjectFile,
     /// Object file linked with `gcc`.
     Executable,
+    /// Position independent object file linked into a shared library with `gcc -shared`.
+    SharedLibrary,
+}
+
+/// Format of errors and warnings.
+pub enum MessageFormat {
+    /// Human readable log lines.
+    Human,
+    /// A JSON [`Diagnostic`] object per line.
+    ///
+    /// [`Diagnostic`]: diagnostic/struct.Diagnostic.html
+    JSON,
 }
 
 /// CLI input configuration and parameters.
 pub struct CLIInput {
-    /// Path to `.yot` input file.
+    /// Path to `.yot` input file, `-` for stdin.
     pub input_path: String,
     /// `input_path` file name without file extension.
     pub input_name: String,
@@ -30,10 +60,58 @@ pub struct CLIInput {
     pub output_format: OutputFormat,
     /// Optimization level (0-3)
     pub optimization: u32,
+    /// How emitted files refer to addresses.
+    pub relocation: Relocation,
+    /// How far apart code and data can be.
+    pub code_model: CodeModel,
     /// Whether or not raw tokens should be printed.
     pub print_tokens: bool,
     /// Whether or not raw AST should be printed.
     pub print_ast: bool,
+    /// Whether or not the declared functions and global variables should be printed.
+    pub dump_symbols: bool,
+    /// Whether or not the time taken by each compiler phase should be printed.
+    pub time_passes: bool,
+    /// Whether or not the size of the program should be printed.
+    pub stats: bool,
+    /// The number of columns between tab stops when counting the columns of errors.
+    pub tab_width: usize,
+    /// Whether or not built-in functions are available.
+    pub builtins: bool,
+    /// Whether or not the functions of the prelude are added to the program.
+    pub prelude: bool,
+    /// Whether or not local variables that shadow other local variables are warned about.
+    pub warn_shadow: bool,
+    /// Whether or not function calls are counted and printed when the program exits.
+    pub instrument: bool,
+    /// Whether or not LLVM IR, assembly, an object file, and an executable are all generated.
+    pub emit_all: bool,
+    /// The name of the function the program starts at.
+    pub entry: String,
+    /// Whether or not the program runs without the C runtime and libc.
+    pub freestanding: bool,
+    /// The command used to link executables, detected if not given.
+    pub linker: Option<String>,
+    /// Extra arguments passed to the linker.
+    pub link_args: Vec<String>,
+    /// Extra object files linked into executables.
+    pub link_objects: Vec<String>,
+    /// Libraries linked into executables.
+    pub libraries: Vec<String>,
+    /// Whether or not DWARF debug info is generated.
+    pub debug: bool,
+    /// Whether or not the program is only checked for errors without generating any files.
+    pub check: bool,
+    /// Whether or not the generated LLVM IR is verified.
+    pub verify: bool,
+    /// Whether or not lines from stdin are compiled and run interactively instead of a file.
+    pub repl: bool,
+    /// Whether or not to recompile whenever the program file or its included files change.
+    pub watch: bool,
+    /// Format of errors and warnings.
+    pub message_format: MessageFormat,
+    /// Whether or not errors are colored.
+    pub color: bool,
     /// Whether to filter logs or not.
     pub verbose: u32,
 }
@@ -45,13 +123,13 @@ pub fn init_cli() -> CLIInput {
         .about("Compiler for yot lang - a toy language")
         .arg(
             Arg::with_name("input")
-                .help("Path to the yot file")
-                .required(true)
+                .help("Path to the yot file, or `-` to read it from stdin")
+                .required_unless("repl")
                 .index(1),
         )
         .arg(
             Arg::with_name("output")
-                .help("Path to generated output")
+                .help("Path to generated output, or `-` to write LLVM IR or assembly to stdout")
                 .takes_value(true)
                 .short("o")
                 .long("output"),
@@ -60,7 +138,14 @@ pub fn init_cli() -> CLIInput {
             Arg::with_name("output format")
                 .help("The type of file to output")
                 .takes_value(true)
-                .possible_values(&["llvm", "executable", "object-file"])
+                .possible_values(&[
+                    "llvm",
+                    "executable",
+                    "object-file",
+                    "assembly",
+                    "shared",
+                    "dylib",
+                ])
                 .default_value("executable")
                 .short("f")
                 .long("output-format"),
@@ -75,6 +160,26 @@ pub fn init_cli() -> CLIInput {
                 .short("O")
                 .long("optimization"),
         )
+        .arg(
+            Arg::with_name("relocation")
+                .help("How generated code refers to addresses (defaults to the target's default)")
+                .takes_value(true)
+                .possible_values(&["default", "static", "pic"])
+                .long("reloc"),
+        )
+        .arg(
+            Arg::with_name("pic")
+                .help("Generate position-independent code, the same as `--reloc pic`")
+                .conflicts_with("relocation")
+                .long("pic"),
+        )
+        .arg(
+            Arg::with_name("code model")
+                .help("How far apart code and data can be (defaults to the target's default)")
+                .takes_value(true)
+                .possible_values(&["small", "medium", "large", "kernel"])
+                .long("code-model"),
+        )
         .arg(
             Arg::with_name("print tokens")
                 .help("Print raw tokens from the lexer")
@@ -85,6 +190,149 @@ pub fn init_cli() -> CLIInput {
                 .help("Print the raw abstract syntax tree")
                 .long("print-ast"),
         )
+        .arg(
+            Arg::with_name("dump symbols")
+                .help("Print the declared functions and global variables")
+                .long("dump-symbols"),
+        )
+        .arg(
+            Arg::with_name("time passes")
+                .help("Print the time taken by each compiler phase")
+                .long("time-passes"),
+        )
+        .arg(
+            Arg::with_name("stats")
+                .help("Print the number of tokens, functions, statements, and lines of the program")
+                .long("stats"),
+        )
+        .arg(
+            Arg::with_name("no builtins")
+                .help("Disable built-in functions such as `print`")
+                .long("no-builtins"),
+        )
+        .arg(
+            Arg::with_name("no prelude")
+                .help("Don't add the prelude functions such as `clamp` and `gcd` to the program")
+                .long("no-prelude"),
+        )
+        .arg(
+            Arg::with_name("warn shadow")
+                .help("Warn when a local variable shadows another local variable or an argument")
+                .long("warn-shadow"),
+        )
+        .arg(
+            Arg::with_name("instrument")
+                .help("Count function calls and print the counts to stderr on exit")
+                .long("instrument"),
+        )
+        .arg(
+            Arg::with_name("emit all")
+                .help(
+                    "Generate LLVM IR, assembly, an object file, and an executable next to the \
+                     output path",
+                )
+                .long("emit-all"),
+        )
+        .arg(
+            Arg::with_name("entry")
+                .help("Function the program starts at, which executables call as `main`")
+                .takes_value(true)
+                .value_name("NAME")
+                .default_value("main")
+                .long("entry"),
+        )
+        .arg(
+            Arg::with_name("freestanding")
+                .help("Link without the C runtime and libc, starting at the entry function itself")
+                .conflicts_with_all(&["instrument", "repl"])
+                .long("freestanding"),
+        )
+        .arg(
+            Arg::with_name("linker")
+                .help("Command used to link executables (defaults to `cc`, then `gcc`)")
+                .takes_value(true)
+                .long("linker"),
+        )
+        .arg(
+            Arg::with_name("link arg")
+                .help("Extra argument passed to the linker")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .allow_hyphen_values(true)
+                .long("link-arg"),
+        )
+        .arg(
+            Arg::with_name("link object")
+                .help("Extra object file linked into the executable")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .long("link-obj"),
+        )
+        .arg(
+            Arg::with_name("library")
+                .help("Library linked into the executable")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .short("l")
+                .long("library"),
+        )
+        .arg(
+            Arg::with_name("check")
+                .help("Check the program for errors without generating any files")
+                .long("check"),
+        )
+        .arg(
+            Arg::with_name("no verify")
+                .help("Emit the generated code without verifying it, for debugging the compiler")
+                .long("no-verify"),
+        )
+        .arg(
+            Arg::with_name("repl")
+                .help("Compile and run each line from stdin, printing the values of expressions")
+                .long("repl"),
+        )
+        .arg(
+            Arg::with_name("watch")
+                .help("Recompile whenever the program file or a file it includes changes")
+                .conflicts_with("repl")
+                .long("watch"),
+        )
+        .arg(
+            Arg::with_name("debug")
+                .help("Generate DWARF debug info")
+                .short("g")
+                .long("debug"),
+        )
+        .arg(
+            Arg::with_name("message format")
+                .help("The format of errors and warnings")
+                .takes_value(true)
+                .possible_values(&["human", "json"])
+                .default_value("human")
+                .long("message-format"),
+        )
+        .arg(
+            Arg::with_name("color")
+                .help("When to color errors (`auto` colors a terminal unless NO_COLOR is set)")
+                .takes_value(true)
+                .possible_values(&["auto", "always", "never"])
+                .default_value("auto")
+                .long("color"),
+        )
+        .arg(
+            Arg::with_name("tab width")
+                .help("The number of columns between tab stops in error positions")
+                .takes_value(true)
+                .default_value("1")
+                .validator(|width| match width.parse::<usize>() {
+                    Ok(width) if width > 0 => Ok(()),
+                    _ => Err("Tab width must be a positive integer".to_string()),
+                })
+                .long("tab-width"),
+        )
         .arg(
             Arg::with_name("verbose")
                 .help("Level of logging (0-2)")
@@ -93,17 +341,23 @@ pub fn init_cli() -> CLIInput {
         )
         .get_matches();
 
-    let input_path = matches.value_of("input").unwrap();
-    let input_name = path::Path::new(input_path)
-        .file_stem()
-        .unwrap()
-        .to_str()
-        .unwrap();
+    let input_path = matches.value_of("input").unwrap_or("-");
+    let input_name = if input_path == "-" {
+        "stdin"
+    } else {
+        path::Path::new(input_path)
+            .file_stem()
+            .unwrap()
+            .to_str()
+            .unwrap()
+    };
 
     let output_format = match matches.value_of("output format").unwrap_or("executable") {
         "llvm" => OutputFormat::LLVM,
         "object-file" => OutputFormat::ObjectFile,
+        "assembly" => OutputFormat::Assembly,
         "executable" => OutputFormat::Executable,
+        "shared" | "dylib" => OutputFormat::SharedLibrary,
         _ => panic!("Unhandled output format"),
     };
     let default_output_path = format!(
@@ -111,23 +365,121 @@ pub fn init_cli() -> CLIInput {
         input_name,
         match output_format {
             OutputFormat::LLVM => "ll",
+            OutputFormat::Assembly => "s",
             OutputFormat::ObjectFile => "o",
             OutputFormat::Executable => "out",
+            OutputFormat::SharedLibrary if cfg!(target_os = "macos") => "dylib",
+            OutputFormat::SharedLibrary => "so",
         }
     );
 
+    let relocation = match matches.value_of("relocation") {
+        _ if matches.is_present("pic") => Relocation::PIC,
+        Some("static") => Relocation::Static,
+        Some("pic") => Relocation::PIC,
+        // Shared libraries can be loaded anywhere, so they must be position independent
+        _ if matches!(output_format, OutputFormat::SharedLibrary) => Relocation::PIC,
+        _ => Relocation::Default,
+    };
+
     CLIInput {
         input_path: String::from(input_path),
         input_name: String::from(input_name),
         output_path: String::from(matches.value_of("output").unwrap_or(&default_output_path)),
         output_format,
         optimization: matches.value_of("optimization").unwrap().parse().unwrap(),
+        relocation,
+        code_model: match matches.value_of("code model") {
+            Some("small") => CodeModel::Small,
+            Some("medium") => CodeModel::Medium,
+            Some("large") => CodeModel::Large,
+            Some("kernel") => CodeModel::Kernel,
+            _ => CodeModel::Default,
+        },
         print_tokens: matches.is_present("print tokens"),
         print_ast: matches.is_present("print AST"),
+        dump_symbols: matches.is_present("dump symbols"),
+        time_passes: matches.is_present("time passes"),
+        stats: matches.is_present("stats"),
+        tab_width: matches.value_of("tab width").unwrap().parse().unwrap(),
+        builtins: !matches.is_present("no builtins"),
+        prelude: !matches.is_present("no prelude"),
+        warn_shadow: matches.is_present("warn shadow"),
+        instrument: matches.is_present("instrument"),
+        emit_all: matches.is_present("emit all"),
+        entry: String::from(matches.value_of("entry").unwrap()),
+        freestanding: matches.is_present("freestanding"),
+        linker: matches.value_of("linker").map(String::from),
+        link_args: matches
+            .values_of("link arg")
+            .map(|args| args.map(String::from).collect())
+            .unwrap_or_default(),
+        link_objects: matches
+            .values_of("link object")
+            .map(|objects| objects.map(String::from).collect())
+            .unwrap_or_default(),
+        libraries: matches
+            .values_of("library")
+            .map(|libraries| libraries.map(String::from).collect())
+            .unwrap_or_default(),
+        debug: matches.is_present("debug"),
+        check: matches.is_present("check"),
+        verify: !matches.is_present("no verify"),
+        repl: matches.is_present("repl"),
+        watch: matches.is_present("watch"),
+        message_format: match matches.value_of("message format").unwrap_or("human") {
+            "json" => MessageFormat::JSON,
+            _ => MessageFormat::Human,
+        },
+        color: match matches.value_of("color").unwrap() {
+            "always" => true,
+            "never" => false,
+            _ => {
+                env::var_os("NO_COLOR").map_or(true, |no_color| no_color.is_empty())
+                    && unsafe { libc::isatty(libc::STDERR_FILENO) } == 1
+            }
+        },
         verbose: matches.occurrences_of("verbose") as u32,
     }
 }
 
+/// Compile a program from its source into a verified LLVM module, without reading or writing any
+/// files.
+///
+/// The program goes through the same phases as a program compiled by the CLI with the default
+/// options, and an error is prefixed with the phase it is from.
+///
+/// # Arguments
+/// * `source` - The source of the program.
+/// * `name` - The name of the module to be created.
+///
+/// # Example
+/// ```
+/// let generator = yotc::compile_str("@main[] { -> 0; }", "m").unwrap();
+/// assert!(unsafe { generator.ir_string() }.contains("define i32 @main()"));
+/// ```
+pub fn compile_str(source: &str, name: &str) -> Result<Generator> {
+    let tokens = Lexer::from_text(source)
+        .collect::<Result<Vec<_>>>()
+        .map_err(|e| format!("Lexing: {}", e))?;
+    let mut program = Parser::new(tokens.into_iter().peekable())
+        .parse_program()
+        .map_err(|e| format!("Parsing: {}", e))?;
+    add_prelude(&mut program).map_err(|e| format!("Parsing: {}", e))?;
+    typecheck(&program).map_err(|(e, _)| format!("Type Checking: {}", e))?;
+    fold_const_calls(&mut program);
+    fold_constants(&mut program);
+
+    unsafe {
+        let generator = Generator::new(program, name);
+        generator
+            .generate()
+            .map_err(|e| format!("Code Generation: {}", e))?;
+        generator.verify().map_err(|e| format!("LLVM: {}", e))?;
+        Ok(generator)
+    }
+}
+
 /// Initialize logger with verbosity filter.
 pub fn init_logger(verbose: u32) {
     env_logger::builder()
@@ -140,3 +492,30 @@ pub fn init_logger(verbose: u32) {
         })
         .init()
 }
+
+/// Initialize logger that writes warnings and errors as JSON [`Diagnostic`]s.
+///
+/// Logs below warnings aren't written, so every line is a diagnostic.
+///
+/// [`Diagnostic`]: diagnostic/struct.Diagnostic.html
+///
+/// # Arguments
+/// * `file` - The path of the program file.
+pub fn init_json_logger(file: &str) {
+    let file = file.to_string();
+    env_logger::builder()
+        .format(move |buf, record| {
+            let diagnostic = Diagnostic {
+                file: file.clone(),
+                span: None,
+                severity: match record.level() {
+                    Level::Error => Severity::Error,
+                    _ => Severity::Warning,
+                },
+                message: record.args().to_string(),
+            };
+            writeln!(buf, "{}", diagnostic.to_json())
+        })
+        .filter_level(LevelFilter::Warn)
+        .init()
+}
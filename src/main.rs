@@ -1,9 +1,21 @@
-use log::{error, warn};
-use std::{fs, process};
-use yotc::generator::Generator;
+use log::{error, info, warn};
+use std::io::BufReader;
+use std::path::Path;
+use std::process::Command;
+use std::time::{Duration, Instant};
+use std::{env, fs, io, process};
+use yotc::diagnostic::{Diagnostic, Severity};
+use yotc::generator::{Generator, GeneratorOptions, LinkOptions};
+use yotc::lexer::tokens::Span;
 use yotc::lexer::Lexer;
+use yotc::parser::const_eval::fold_const_calls;
+use yotc::parser::optimize::fold_constants;
+use yotc::parser::prelude::add_prelude;
+use yotc::parser::typecheck::typecheck;
 use yotc::parser::Parser;
-use yotc::{init_cli, init_logger, OutputFormat};
+use yotc::repl::Repl;
+use yotc::watch::Watcher;
+use yotc::{init_cli, init_json_logger, init_logger, CLIInput, MessageFormat, OutputFormat};
 
 /// Unwrap and return result, or log and exit if Err.
 macro_rules! unwrap_or_exit {
@@ -18,58 +30,381 @@ macro_rules! unwrap_or_exit {
     };
 }
 
+/// Unwrap and return result, or report the error at a position in the program and exit if Err.
+macro_rules! unwrap_or_exit_at {
+    ($f:expr, $origin:tt, $cli_input:expr, $span:expr) => {
+        match $f {
+            Ok(a) => a,
+            Err(e) => exit_at($cli_input, $origin, &e, $span),
+        }
+    };
+}
+
 pub fn main() {
     let cli_input = init_cli();
-    init_logger(cli_input.verbose);
+    match cli_input.message_format {
+        MessageFormat::Human => init_logger(cli_input.verbose),
+        MessageFormat::JSON => init_json_logger(&cli_input.input_path),
+    }
+
+    if cli_input.repl {
+        unsafe { Repl::new(generator_options(&cli_input)).run() };
+        return;
+    }
+    if cli_input.watch {
+        if cli_input.input_path == "-" {
+            error!("IO: Can't watch a program read from stdin");
+            process::exit(1);
+        }
+        Watcher::new(&cli_input.input_path).run(|| {
+            compile_in_child(&cli_input);
+            true
+        });
+        return;
+    }
+
+    if cli_input.output_path == "-" {
+        let to_stdout = matches!(
+            cli_input.output_format,
+            OutputFormat::LLVM | OutputFormat::Assembly
+        );
+        if cli_input.emit_all || !to_stdout {
+            error!("IO: Only LLVM IR and assembly can be written to stdout");
+            process::exit(1);
+        }
+    }
+
+    let mut timer = PassTimer::new(cli_input.time_passes);
 
     // Lexer
-    let lexer = unwrap_or_exit!(Lexer::from_file(&cli_input.input_path), "IO");
-    let tokens = lexer
-        .map(|t| unwrap_or_exit!(t, "Lexing"))
-        .collect::<Vec<_>>();
+    let (tokens, spans, line_count) = timer.time("Lexing", || {
+        let mut lexer = if cli_input.input_path == "-" {
+            Lexer::from_reader(BufReader::new(io::stdin()))
+        } else {
+            unwrap_or_exit!(Lexer::from_file(&cli_input.input_path), "IO")
+        }
+        .with_tab_width(cli_input.tab_width);
+        let mut tokens = Vec::new();
+        let mut spans = Vec::new();
+        while let Some(token) = lexer.next() {
+            tokens.push(unwrap_or_exit_at!(
+                token,
+                "Lexing",
+                &cli_input,
+                lexer.span()
+            ));
+            spans.push(lexer.span());
+        }
+        (tokens, spans, lexer.line_count())
+    });
+    let token_count = tokens.len();
 
     if cli_input.print_tokens {
         println!("***TOKENS***");
-        tokens.iter().for_each(|t| println!("{:?}", t));
+        tokens.iter().for_each(|t| println!("{}", t));
     }
 
     // Parser
-    let mut parser = Parser::new(tokens.into_iter().peekable());
-    let program = unwrap_or_exit!(parser.parse_program(), "Parsing");
+    let mut parser = Parser::with_spans(tokens, spans).with_entry(&cli_input.entry);
+    if cli_input.input_path != "-" {
+        parser = parser.with_path(&cli_input.input_path);
+    }
+    let mut program = timer.time("Parsing", || match parser.parse_program() {
+        Ok(program) => program,
+        // Every definition that failed to parse is reported before exiting
+        Err(e) if parser.errors().is_empty() => exit_at(&cli_input, "Parsing", &e, parser.span()),
+        Err(_) => {
+            for (error, span) in parser.errors() {
+                report_at(&cli_input, "Parsing", error, *span);
+            }
+            process::exit(1);
+        }
+    });
     if cli_input.print_ast {
         println!("***AST***\n{:#?}", program);
     }
+    if cli_input.dump_symbols {
+        println!("***SYMBOLS***");
+        program.symbols().iter().for_each(|s| println!("{}", s));
+    }
+    if cli_input.stats {
+        println!("***STATS***");
+        println!("{:<20}{:>10}", "Tokens", token_count);
+        println!("{:<20}{:>10}", "Functions", program.function_count());
+        println!("{:<20}{:>10}", "Statements", program.statement_count());
+        println!("{:<20}{:>10}", "Lines", line_count);
+    }
+    if cli_input.prelude {
+        unwrap_or_exit!(add_prelude(&mut program), "Parsing");
+    }
+    timer.time("Type Checking", || {
+        if let Err((e, span)) = typecheck(&program) {
+            exit_at(&cli_input, "Type Checking", &e, span);
+        }
+    });
+    timer.time("Constant Folding", || {
+        fold_const_calls(&mut program);
+        fold_constants(&mut program);
+    });
 
     // Generator
-    let generator = unsafe { Generator::new(program, &cli_input.input_name) };
-    unsafe {
-        unwrap_or_exit!(generator.generate(), "Code Generation");
-        unwrap_or_exit!(generator.verify(), "LLVM");
+    let options = generator_options(&cli_input);
+    let generator = unsafe { Generator::with_options(program, &cli_input.input_name, options) };
+    timer.time("Code Generation", || unsafe {
+        unwrap_or_exit!(generator.generate(), "Code Generation")
+    });
+    if cli_input.verify {
+        timer.time("Verification", || unsafe {
+            unwrap_or_exit!(generator.verify(), "LLVM")
+        });
+    } else {
+        warn!("Skipping verification and optimization, the generated code may be invalid");
     }
 
-    match cli_input.output_format {
-        OutputFormat::LLVM => unsafe {
-            unwrap_or_exit!(generator.generate_ir(&cli_input.output_path), "LLVM");
-        },
-        OutputFormat::ObjectFile => unsafe {
-            unwrap_or_exit!(
-                generator.generate_object_file(cli_input.optimization, &cli_input.output_path),
-                "LLVM"
+    if cli_input.check {
+        info!("No errors found");
+    } else {
+        // Every output is optimized, not only the files emitted by the target machine, but the
+        // optimization passes can crash on invalid code
+        if cli_input.verify {
+            timer.time("Optimization", || unsafe {
+                unwrap_or_exit!(generator.optimize(cli_input.optimization), "LLVM")
+            });
+        }
+        timer.time("Emission", || emit(&generator, &cli_input));
+    }
+    timer.print();
+}
+
+/// Compile the program in a new compiler process with the same arguments except `--watch`, so
+/// errors are reported without ending the watch.
+///
+/// # Arguments
+/// * `cli_input` - The CLI input, for the program path.
+fn compile_in_child(cli_input: &CLIInput) {
+    let args = env::args().skip(1).filter(|arg| arg != "--watch");
+    match env::current_exe().and_then(|exe| Command::new(exe).args(args).status()) {
+        Ok(status) if status.success() => println!("Compiled `{}`", cli_input.input_path),
+        // The child has already reported its errors
+        Ok(_) => (),
+        Err(e) => error!("IO: Unable to run the compiler: {}", e),
+    }
+}
+
+/// Report an error at a position in the program and exit.
+///
+/// # Arguments
+/// * `cli_input` - The CLI input, for the program path and message format.
+/// * `origin` - The phase the error is from.
+/// * `message` - The error message.
+/// * `span` - The position of the error, unknown if its line is 0.
+fn exit_at(cli_input: &CLIInput, origin: &str, message: &str, span: Span) -> ! {
+    report_at(cli_input, origin, message, span);
+    process::exit(1);
+}
+
+/// Report an error at a position in the program.
+///
+/// # Arguments
+/// * `cli_input` - The CLI input, for the program path and message format.
+/// * `origin` - The phase the error is from.
+/// * `message` - The error message.
+/// * `span` - The position of the error, unknown if its line is 0.
+fn report_at(cli_input: &CLIInput, origin: &str, message: &str, span: Span) {
+    let diagnostic = Diagnostic {
+        file: cli_input.input_path.clone(),
+        span: Some(span).filter(|span| span.line > 0),
+        severity: Severity::Error,
+        message: format!("{}: {}", origin, message),
+    };
+    match cli_input.message_format {
+        MessageFormat::Human => {
+            // A program from stdin has already been read, so its lines can't be shown
+            let source = match cli_input.input_path.as_str() {
+                "-" => None,
+                path => fs::read_to_string(path).ok(),
+            };
+            eprintln!(
+                "{}",
+                diagnostic.render(source.as_deref(), cli_input.tab_width, cli_input.color)
             );
-        },
-        OutputFormat::Executable => unsafe {
+        }
+        MessageFormat::JSON => eprintln!("{}", diagnostic.to_json()),
+    }
+}
+
+/// Write the output files of a generated program, or exit on failure.
+fn emit(generator: &Generator, cli_input: &CLIInput) {
+    if cli_input.emit_all {
+        // Every artifact shares the output path without its extension
+        let base = Path::new(&cli_input.output_path).with_extension("");
+        let base = base.to_str().unwrap();
+        let object_file = format!("{}.o", base);
+        emit_ir(generator, &format!("{}.ll", base));
+        emit_assembly_file(generator, cli_input.optimization, &format!("{}.s", base));
+        emit_object_file(generator, cli_input.optimization, &object_file);
+        emit_executable(generator, cli_input, &object_file, &format!("{}.out", base));
+        return;
+    }
+
+    match cli_input.output_format {
+        OutputFormat::LLVM => emit_ir(generator, &cli_input.output_path),
+        OutputFormat::Assembly => {
+            emit_assembly_file(generator, cli_input.optimization, &cli_input.output_path)
+        }
+        OutputFormat::ObjectFile => {
+            emit_object_file(generator, cli_input.optimization, &cli_input.output_path)
+        }
+        OutputFormat::Executable => {
             let object_file = format!("{}.o", cli_input.input_name);
-            unwrap_or_exit!(
-                generator.generate_object_file(cli_input.optimization, &object_file),
-                "LLVM"
+            emit_object_file(generator, cli_input.optimization, &object_file);
+            let linked = generator.generate_executable(
+                &object_file,
+                &cli_input.output_path,
+                &link_options(cli_input),
             );
-            unwrap_or_exit!(
-                generator.generate_executable(&object_file, &cli_input.output_path),
-                "Linker"
+            // The object file is deleted even if linking fails
+            fs::remove_file(object_file).unwrap_or_else(|e| {
+                warn!("Unable to delete object file:\n{}", e);
+            });
+            unwrap_or_exit!(linked, "Linker");
+        }
+        OutputFormat::SharedLibrary => {
+            let object_file = format!("{}.o", cli_input.input_name);
+            emit_object_file(generator, cli_input.optimization, &object_file);
+            let linked = generator.generate_shared_library(
+                &object_file,
+                &cli_input.output_path,
+                &link_options(cli_input),
             );
             fs::remove_file(object_file).unwrap_or_else(|e| {
                 warn!("Unable to delete object file:\n{}", e);
             });
+            unwrap_or_exit!(linked, "Linker");
+        }
+    }
+}
+
+/// The wall-clock time of each compiler phase, only measured if enabled.
+struct PassTimer {
+    /// Whether or not phases are timed.
+    enabled: bool,
+    /// The name and duration of each timed phase, in order.
+    passes: Vec<(&'static str, Duration)>,
+}
+
+impl PassTimer {
+    /// Create a timer without any timed phases.
+    ///
+    /// # Arguments
+    /// * `enabled` - Whether or not phases are timed.
+    fn new(enabled: bool) -> Self {
+        PassTimer {
+            enabled,
+            passes: Vec::new(),
+        }
+    }
+
+    /// Run a compiler phase, timing it if enabled.
+    ///
+    /// # Arguments
+    /// * `pass` - The name of the phase.
+    /// * `f` - The phase.
+    fn time<T>(&mut self, pass: &'static str, f: impl FnOnce() -> T) -> T {
+        if !self.enabled {
+            return f();
+        }
+        let start = Instant::now();
+        let value = f();
+        self.passes.push((pass, start.elapsed()));
+        value
+    }
+
+    /// Print the time taken by each phase if enabled.
+    fn print(&self) {
+        if !self.enabled {
+            return;
+        }
+        println!("***PASS TIMES***");
+        for (pass, duration) in &self.passes {
+            println!("{:<20}{:>10.3} ms", pass, duration.as_secs_f64() * 1000.0);
+        }
+    }
+}
+
+/// Get the code generation options from the CLI input.
+fn generator_options(cli_input: &CLIInput) -> GeneratorOptions {
+    GeneratorOptions {
+        builtins: cli_input.builtins,
+        warn_shadow: cli_input.warn_shadow,
+        instrument: cli_input.instrument,
+        debug_source: if cli_input.debug {
+            Some(cli_input.input_path.clone())
+        } else {
+            None
+        },
+        relocation: cli_input.relocation,
+        code_model: cli_input.code_model,
+        verify: cli_input.verify,
+        freestanding: cli_input.freestanding,
+    }
+}
+
+/// Get the options for linking executables from the CLI input.
+fn link_options(cli_input: &CLIInput) -> LinkOptions {
+    LinkOptions {
+        linker: cli_input.linker.clone(),
+        objects: cli_input.link_objects.clone(),
+        libraries: cli_input.libraries.clone(),
+        args: cli_input.link_args.clone(),
+        freestanding_entry: if cli_input.freestanding {
+            Some(cli_input.entry.clone())
+        } else {
+            None
         },
+        relocation: cli_input.relocation,
+    }
+}
+
+/// Write LLVM IR to a file, or to stdout if the path is `-`, or exit on failure.
+fn emit_ir(generator: &Generator, output: &str) {
+    unsafe {
+        if output == "-" {
+            print!("{}", generator.ir_string());
+            return;
+        }
+        unwrap_or_exit!(generator.generate_ir(output), "LLVM");
+    }
+}
+
+/// Write a native assembly file, or to stdout if the path is `-`, or exit on failure.
+fn emit_assembly_file(generator: &Generator, optimization: u32, output: &str) {
+    unsafe {
+        if output == "-" {
+            print!(
+                "{}",
+                unwrap_or_exit!(generator.assembly_string(optimization), "LLVM")
+            );
+            return;
+        }
+        unwrap_or_exit!(
+            generator.generate_assembly_file(optimization, output),
+            "LLVM"
+        );
     }
 }
+
+/// Write an object file, or exit on failure.
+fn emit_object_file(generator: &Generator, optimization: u32, output: &str) {
+    unsafe {
+        unwrap_or_exit!(generator.generate_object_file(optimization, output), "LLVM");
+    }
+}
+
+/// Link an object file into an executable, or exit on failure.
+fn emit_executable(generator: &Generator, cli_input: &CLIInput, object_file: &str, output: &str) {
+    unwrap_or_exit!(
+        generator.generate_executable(object_file, output, &link_options(cli_input)),
+        "Linker"
+    );
+}
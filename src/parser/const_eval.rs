@@ -0,0 +1,516 @@
+use crate::lexer::tokens::Literal;
+use crate::parser::expression::Expression;
+use crate::parser::function::Function;
+use crate::parser::program::{mangle, Program};
+use crate::parser::statement::Statement;
+use crate::parser::types::Type;
+use crate::Result;
+use log::{debug, info, trace, warn};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// The maximum depth of nested calls when evaluating a `const` function.
+const MAX_CALL_DEPTH: usize = 256;
+
+/// A `const` function that can be evaluated at compile time.
+struct ConstFunction {
+    /// The names of the arguments.
+    args: Vec<String>,
+    /// The body of the function.
+    statement: Statement,
+}
+
+/// Evaluates calls to `const` functions at compile time.
+struct ConstEvaluator {
+    /// The `const` functions of the program by symbol name, which is mangled if overloaded.
+    functions: HashMap<String, Rc<ConstFunction>>,
+    /// The current depth of nested calls.
+    depth: usize,
+}
+
+/// The result of evaluating a statement.
+enum Flow {
+    /// The statement finished without returning.
+    Continue,
+    /// The statement returned a value.
+    Return(i32),
+}
+
+/// Replace calls to `const` functions that have constant arguments with the value they return.
+///
+/// Calls that can't be evaluated at compile time are left to be called at runtime.
+///
+/// # Arguments
+/// * `program` - The program to fold calls in.
+pub fn fold_const_calls(program: &mut Program) {
+    trace!("Folding const function calls");
+    let mut functions = HashMap::new();
+    for function in &program.functions {
+        if let Function::RegularFunction {
+            name,
+            is_const: true,
+            args,
+            return_type,
+            statement,
+            ..
+        } = function
+        {
+            if args.iter().any(|arg| arg.arg_type != Type::I32) {
+                warn!(
+                    "Const function `{}` has non-i32 arguments, it will be called at runtime",
+                    name
+                );
+                continue;
+            }
+            if *return_type != Some(Type::I32) {
+                warn!(
+                    "Const function `{}` doesn't return an i32, it will be called at runtime",
+                    name
+                );
+                continue;
+            }
+            functions.insert(
+                program.symbol_name(function),
+                Rc::new(ConstFunction {
+                    args: args.iter().map(|arg| arg.name.clone()).collect(),
+                    statement: statement.as_ref().clone(),
+                }),
+            );
+        }
+    }
+    if functions.is_empty() {
+        return;
+    }
+
+    let mut evaluator = ConstEvaluator {
+        functions,
+        depth: 0,
+    };
+    for function in &mut program.functions {
+        if let Function::RegularFunction { statement, .. } = function {
+            evaluator.fold_statement(statement);
+        }
+    }
+}
+
+impl ConstEvaluator {
+    /// Fold `const` function calls in every expression of a statement.
+    fn fold_statement(&mut self, statement: &mut Statement) {
+        match statement {
+            Statement::CompoundStatement { statements } => {
+                for statement in statements {
+                    self.fold_statement(statement);
+                }
+            }
+            Statement::IfStatement {
+                condition,
+                then_statement,
+                else_statement,
+            } => {
+                self.fold_expression(condition);
+                self.fold_statement(then_statement);
+                if let Some(else_statement) = else_statement {
+                    self.fold_statement(else_statement);
+                }
+            }
+            Statement::SwitchStatement {
+                value,
+                cases,
+                default,
+            } => {
+                self.fold_expression(value);
+                for (_, statement) in cases {
+                    self.fold_statement(statement);
+                }
+                if let Some(default) = default {
+                    self.fold_statement(default);
+                }
+            }
+            Statement::DoWhileStatement { body, condition } => {
+                self.fold_statement(body);
+                self.fold_expression(condition);
+            }
+            Statement::ReturnStatement { value, .. } => {
+                if let Some(value) = value {
+                    self.fold_expression(value);
+                }
+            }
+            Statement::VariableDeclarationStatement { value, .. } => {
+                if let Some(value) = value {
+                    self.fold_expression(value);
+                }
+            }
+            Statement::TupleDeclarationStatement { value, .. } => self.fold_expression(value),
+            Statement::ExpressionStatement { expression } => self.fold_expression(expression),
+            Statement::FunctionDefinitionStatement { .. } | Statement::NoOpStatement => (),
+        }
+    }
+
+    /// Find the `const` function that a call with a number of arguments calls.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the called function.
+    /// * `arity` - The number of arguments of the call.
+    fn function(&self, name: &str, arity: usize) -> Option<Rc<ConstFunction>> {
+        self.functions
+            .get(&mangle(name, arity))
+            .or_else(|| self.functions.get(name))
+            .cloned()
+    }
+
+    /// Fold `const` function calls in an expression and its subexpressions.
+    fn fold_expression(&mut self, expression: &mut Expression) {
+        match expression {
+            Expression::FunctionCallExpression { name, args, .. }
+                if self.function(name, args.len()).is_some() =>
+            {
+                let values = args
+                    .iter()
+                    .map(|arg| self.eval_expression(arg, &mut Vec::new()))
+                    .collect::<Result<Vec<_>>>();
+                match values.and_then(|values| self.call(name, values)) {
+                    Ok(value) => {
+                        debug!("Folded call to const function `{}` into {}", name, value);
+                        *expression = Expression::LiteralExpression {
+                            value: Literal::Integer(value),
+                            span: expression.span(),
+                        };
+                        return;
+                    }
+                    Err(e) => info!(
+                        "Call to const function `{}` will be evaluated at runtime: {}",
+                        name, e
+                    ),
+                }
+                for arg in args {
+                    self.fold_expression(arg);
+                }
+            }
+            Expression::FunctionCallExpression { args, .. } => {
+                for arg in args {
+                    self.fold_expression(arg);
+                }
+            }
+            Expression::ArrayLiteralExpression { elements, .. }
+            | Expression::TupleExpression { elements, .. } => {
+                for element in elements {
+                    self.fold_expression(element);
+                }
+            }
+            Expression::IndexExpression { array, index, .. } => {
+                self.fold_expression(array);
+                self.fold_expression(index);
+            }
+            Expression::BlockExpression {
+                statements, tail, ..
+            } => {
+                for statement in statements {
+                    self.fold_statement(statement);
+                }
+                self.fold_expression(tail);
+            }
+            Expression::ParenExpression { expression, .. }
+            | Expression::UnaryExpression { expression, .. }
+            | Expression::PostfixExpression { expression, .. }
+            | Expression::CastExpression { expression, .. }
+            | Expression::FieldAccessExpression { expression, .. } => {
+                self.fold_expression(expression)
+            }
+            Expression::BinaryExpression {
+                l_expression,
+                r_expression,
+                ..
+            } => {
+                self.fold_expression(l_expression);
+                self.fold_expression(r_expression);
+            }
+            Expression::LiteralExpression { .. }
+            | Expression::VariableReferenceExpression { .. }
+            | Expression::SizeOfExpression { .. } => (),
+        }
+    }
+
+    /// Evaluate a call to a `const` function.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the function.
+    /// * `values` - The values of the arguments.
+    fn call(&mut self, name: &str, values: Vec<i32>) -> Result<i32> {
+        if self.depth >= MAX_CALL_DEPTH {
+            return Err(format!(
+                "exceeded the maximum call depth of {}",
+                MAX_CALL_DEPTH
+            ));
+        }
+
+        let function = match self.function(name, values.len()) {
+            Some(function) => function,
+            None => return Err(format!("`{}` isn't a const function", name)),
+        };
+        if function.args.len() != values.len() {
+            return Err(format!(
+                "`{}` expects {} arguments, got {}",
+                name,
+                function.args.len(),
+                values.len()
+            ));
+        }
+
+        let scope = function.args.iter().cloned().zip(values).collect();
+        self.depth += 1;
+        let flow = self.eval_statement(&function.statement, &mut vec![scope]);
+        self.depth -= 1;
+        match flow? {
+            Flow::Return(value) => Ok(value),
+            Flow::Continue => Err(format!("`{}` doesn't return a value", name)),
+        }
+    }
+
+    /// Evaluate a statement.
+    ///
+    /// # Arguments
+    /// * `statement` - The statement to evaluate.
+    /// * `scopes` - The variables in each scope, innermost last.
+    fn eval_statement(
+        &mut self,
+        statement: &Statement,
+        scopes: &mut Vec<HashMap<String, i32>>,
+    ) -> Result<Flow> {
+        match statement {
+            Statement::CompoundStatement { statements } => {
+                scopes.push(HashMap::new());
+                let mut flow = Ok(Flow::Continue);
+                for statement in statements {
+                    flow = self.eval_statement(statement, scopes);
+                    if let Ok(Flow::Continue) = flow {
+                        continue;
+                    }
+                    break;
+                }
+                scopes.pop();
+                flow
+            }
+            Statement::IfStatement {
+                condition,
+                then_statement,
+                else_statement,
+            } => {
+                if self.eval_expression(condition, scopes)? != 0 {
+                    self.eval_statement(then_statement, scopes)
+                } else if let Some(else_statement) = else_statement {
+                    self.eval_statement(else_statement, scopes)
+                } else {
+                    Ok(Flow::Continue)
+                }
+            }
+            Statement::SwitchStatement {
+                value,
+                cases,
+                default,
+            } => {
+                let value = self.eval_expression(value, scopes)?;
+                match cases.iter().find(|(case, _)| *case == value) {
+                    Some((_, statement)) => self.eval_statement(statement, scopes),
+                    None => match default {
+                        Some(default) => self.eval_statement(default, scopes),
+                        None => Ok(Flow::Continue),
+                    },
+                }
+            }
+            Statement::DoWhileStatement { .. } => {
+                Err("loops can't be evaluated at compile time".to_string())
+            }
+            Statement::ReturnStatement { value, .. } => match value {
+                Some(value) => Ok(Flow::Return(self.eval_expression(value, scopes)?)),
+                None => Err("bare return".to_string()),
+            },
+            Statement::VariableDeclarationStatement {
+                name,
+                var_type,
+                value,
+                ..
+            } => {
+                if var_type.as_ref().is_some_and(|t| *t != Type::I32) {
+                    return Err(format!("variable `{}` isn't an i32", name));
+                }
+                let value = match value {
+                    Some(value) => self.eval_expression(value, scopes)?,
+                    None => return Err(format!("variable `{}` has no value", name)),
+                };
+                if name != "_" {
+                    scopes.last_mut().unwrap().insert(name.clone(), value);
+                }
+                Ok(Flow::Continue)
+            }
+            Statement::TupleDeclarationStatement { .. } => {
+                Err("tuples can't be evaluated at compile time".to_string())
+            }
+            Statement::ExpressionStatement { expression } => {
+                self.eval_expression(expression, scopes)?;
+                Ok(Flow::Continue)
+            }
+            Statement::FunctionDefinitionStatement { .. } | Statement::NoOpStatement => {
+                Ok(Flow::Continue)
+            }
+        }
+    }
+
+    /// Evaluate an expression.
+    ///
+    /// # Arguments
+    /// * `expression` - The expression to evaluate.
+    /// * `scopes` - The variables in each scope, innermost last.
+    fn eval_expression(
+        &mut self,
+        expression: &Expression,
+        scopes: &mut Vec<HashMap<String, i32>>,
+    ) -> Result<i32> {
+        match expression {
+            Expression::LiteralExpression { value, .. } => match value {
+                Literal::Integer(i) => Ok(*i),
+                Literal::Char(c) => Ok(*c as i32),
+                Literal::SizedInteger { value, bits: 32 } => Ok(*value as i32),
+                Literal::SizedInteger { bits, .. } => Err(format!(
+                    "i{} literals can't be evaluated at compile time",
+                    bits
+                )),
+                Literal::Str(_) => Err("strings can't be evaluated at compile time".to_string()),
+            },
+            Expression::ParenExpression { expression, .. } => {
+                self.eval_expression(expression, scopes)
+            }
+            Expression::BlockExpression {
+                statements, tail, ..
+            } => {
+                scopes.push(HashMap::new());
+                let mut value = Ok(0);
+                for statement in statements {
+                    match self.eval_statement(statement, scopes) {
+                        Ok(Flow::Continue) => continue,
+                        Ok(Flow::Return(_)) => {
+                            value = Err("returns from a block expression".to_string())
+                        }
+                        Err(e) => value = Err(e),
+                    }
+                    break;
+                }
+                if value.is_ok() {
+                    value = self.eval_expression(tail, scopes);
+                }
+                scopes.pop();
+                value
+            }
+            Expression::VariableReferenceExpression { name, .. } => scopes
+                .iter()
+                .rev()
+                .find_map(|scope| scope.get(name))
+                .copied()
+                .ok_or(format!("`{}` isn't a constant", name)),
+            Expression::FunctionCallExpression { name, args, .. } => {
+                let values = args
+                    .iter()
+                    .map(|arg| self.eval_expression(arg, scopes))
+                    .collect::<Result<Vec<_>>>()?;
+                self.call(name, values)
+            }
+            Expression::BinaryExpression {
+                op,
+                l_expression,
+                r_expression,
+                ..
+            } if op == "=" => {
+                let value = self.eval_expression(r_expression, scopes)?;
+                let name = match l_expression.as_ref() {
+                    Expression::VariableReferenceExpression { name, .. } => name,
+                    _ => return Err("only variables can be assigned".to_string()),
+                };
+                match scopes
+                    .iter_mut()
+                    .rev()
+                    .find_map(|scope| scope.get_mut(name))
+                {
+                    Some(var) => {
+                        *var = value;
+                        Ok(value)
+                    }
+                    None => Err(format!("`{}` isn't a constant", name)),
+                }
+            }
+            Expression::BinaryExpression {
+                op,
+                l_expression,
+                r_expression,
+                ..
+            } => {
+                let l = self.eval_expression(l_expression, scopes)?;
+                let r = self.eval_expression(r_expression, scopes)?;
+                match &op[..] {
+                    "+" => Ok(l.wrapping_add(r)),
+                    "-" => Ok(l.wrapping_sub(r)),
+                    "*" => Ok(l.wrapping_mul(r)),
+                    // Negative exponents are treated as 0
+                    "**" => Ok(l.wrapping_pow(r.max(0) as u32)),
+                    "/" => l
+                        .checked_div(r)
+                        .ok_or(format!("`{} / {}` is undefined", l, r)),
+                    "==" => Ok((l == r) as i32),
+                    "!=" => Ok((l != r) as i32),
+                    "<" => Ok((l < r) as i32),
+                    ">" => Ok((l > r) as i32),
+                    "<=" => Ok((l <= r) as i32),
+                    ">=" => Ok((l >= r) as i32),
+                    _ => Err(format!("`{}` can't be evaluated at compile time", op)),
+                }
+            }
+            Expression::CastExpression {
+                expression,
+                target_type,
+                ..
+            } => match target_type {
+                Type::I32 => self.eval_expression(expression, scopes),
+                _ => Err(format!(
+                    "casts to {} can't be evaluated at compile time",
+                    target_type
+                )),
+            },
+            Expression::UnaryExpression { op, expression, .. } => {
+                let value = self.eval_expression(expression, scopes)?;
+                match &op[..] {
+                    "-" => Ok(value.wrapping_neg()),
+                    _ => Err(format!("`{}` can't be evaluated at compile time", op)),
+                }
+            }
+            Expression::PostfixExpression { op, expression, .. } => {
+                let name = match expression.as_ref() {
+                    Expression::VariableReferenceExpression { name, .. } => name,
+                    _ => return Err("only variables can be assigned".to_string()),
+                };
+                let var = scopes
+                    .iter_mut()
+                    .rev()
+                    .find_map(|scope| scope.get_mut(name))
+                    .ok_or(format!("`{}` isn't a constant", name))?;
+                let value = *var;
+                *var = match &op[..] {
+                    "++" => value.wrapping_add(1),
+                    "--" => value.wrapping_sub(1),
+                    _ => return Err(format!("`{}` can't be evaluated at compile time", op)),
+                };
+                Ok(value)
+            }
+            Expression::TupleExpression { .. } => {
+                Err("tuples can't be evaluated at compile time".to_string())
+            }
+            Expression::ArrayLiteralExpression { .. } | Expression::IndexExpression { .. } => {
+                Err("arrays can't be evaluated at compile time".to_string())
+            }
+            Expression::FieldAccessExpression { .. } => {
+                Err("structs can't be evaluated at compile time".to_string())
+            }
+            // Sizes depend on the target, which is only known during code generation
+            Expression::SizeOfExpression { .. } => {
+                Err("sizeof can't be evaluated at compile time".to_string())
+            }
+        }
+    }
+}
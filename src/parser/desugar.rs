@@ -0,0 +1,566 @@
+use crate::parser::expression::Expression;
+use crate::parser::function::Function;
+use crate::parser::program::Program;
+use crate::parser::statement::Statement;
+use crate::parser::types::Type;
+use crate::Result;
+use log::trace;
+use std::collections::{HashMap, HashSet};
+
+/// Move functions defined inside other functions to the top level of a program.
+///
+/// A nested function is renamed to `parent.name` so it can't clash with other functions, along
+/// with the calls to it in its parent, itself, and the other functions nested in its parent.
+/// Nested functions can't use the variables of their parent, since nothing is captured.
+///
+/// # Arguments
+/// * `program` - The program to hoist nested functions in.
+pub fn hoist_nested_functions(program: &mut Program) -> Result<()> {
+    trace!("Hoisting nested functions");
+    let mut hoisted = Vec::new();
+    for function in &mut program.functions {
+        hoist_from_function(function, &mut hoisted)?;
+    }
+    program.functions.extend(hoisted);
+    Ok(())
+}
+
+/// Merge the external declaration of a function with its definition in the same program, so the
+/// declaration acts as a prototype and the definition gives it a body.
+///
+/// The signature of the definition must match the declaration, though the argument names can
+/// differ. The definition takes on the attributes of the declaration, such as `noreturn`.
+///
+/// # Arguments
+/// * `program` - The program to merge declarations in.
+pub fn merge_declarations(program: &mut Program) -> Result<()> {
+    trace!("Merging external declarations");
+    let mut merged = Vec::new();
+    for (i, declaration) in program.functions.iter().enumerate() {
+        let name = match declaration {
+            Function::ExternalFunction { name, .. } => name,
+            Function::RegularFunction { .. } => continue,
+        };
+        let mut definitions = program
+            .functions
+            .iter()
+            .filter(|f| is_definition_of(f, name));
+        // Overloads can't be declared externally, which is an error when type checking
+        if let (Some(definition), None) = (definitions.next(), definitions.next()) {
+            check_declaration(declaration, definition)?;
+            merged.push(i);
+        }
+    }
+
+    for i in merged.into_iter().rev() {
+        if let Function::ExternalFunction {
+            name,
+            attributes: declared,
+            ..
+        } = program.functions.remove(i)
+        {
+            trace!("Merging the external declaration of `{}`", name);
+            let definition = program
+                .functions
+                .iter_mut()
+                .find(|f| is_definition_of(f, &name));
+            if let Some(Function::RegularFunction { attributes, .. }) = definition {
+                for attribute in declared {
+                    if !attributes.contains(&attribute) {
+                        attributes.push(attribute);
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Check if a function is the definition of a function with a body with a name.
+///
+/// # Arguments
+/// * `function` - The function.
+/// * `name` - The name of the function.
+fn is_definition_of(function: &Function, name: &str) -> bool {
+    matches!(function, Function::RegularFunction { name: n, .. } if n == name)
+}
+
+/// Check that the signature of a function matches its external declaration.
+///
+/// # Arguments
+/// * `declaration` - The external declaration of the function.
+/// * `definition` - The definition of the function.
+fn check_declaration(declaration: &Function, definition: &Function) -> Result<()> {
+    let (declared_args, is_variadic, declared_return_type) = match declaration {
+        Function::ExternalFunction {
+            args,
+            is_variadic,
+            return_type,
+            ..
+        } => (args, *is_variadic, return_type),
+        Function::RegularFunction { .. } => return Ok(()),
+    };
+    let (name, args, return_type) = match definition {
+        Function::RegularFunction {
+            name,
+            args,
+            return_type,
+            ..
+        } => (name, args, return_type),
+        Function::ExternalFunction { .. } => return Ok(()),
+    };
+
+    if is_variadic {
+        return Err(format!(
+            "Function `{}` isn't variadic, but its external declaration is",
+            name
+        ));
+    }
+    if args.len() != declared_args.len() {
+        return Err(format!(
+            "Function `{}` takes {} arguments, but its external declaration takes {}",
+            name,
+            args.len(),
+            declared_args.len()
+        ));
+    }
+    for (arg, declared) in args.iter().zip(declared_args) {
+        if arg.arg_type != declared.arg_type {
+            return Err(format!(
+                "Argument `{}` of `{}` is {}, but {} in its external declaration",
+                arg.name, name, arg.arg_type, declared.arg_type
+            ));
+        }
+    }
+    if return_type != declared_return_type {
+        let display = |t: &Option<Type>| match t {
+            Some(t) => t.to_string(),
+            None => String::from("void"),
+        };
+        return Err(format!(
+            "Function `{}` returns {}, but its external declaration returns {}",
+            name,
+            display(return_type),
+            display(declared_return_type)
+        ));
+    }
+    Ok(())
+}
+
+/// Move the functions nested in a function, and the functions nested in those, to a list.
+///
+/// # Arguments
+/// * `function` - The function to remove nested functions from.
+/// * `hoisted` - The hoisted functions to add to.
+fn hoist_from_function(function: &mut Function, hoisted: &mut Vec<Function>) -> Result<()> {
+    let (parent, statement) = match function {
+        Function::RegularFunction {
+            name, statement, ..
+        } => (name.clone(), statement),
+        Function::ExternalFunction { .. } => return Ok(()),
+    };
+
+    let mut nested = Vec::new();
+    visit_statements(statement, &mut |s| {
+        if let Statement::FunctionDefinitionStatement { .. } = s {
+            if let Statement::FunctionDefinitionStatement { function } =
+                std::mem::replace(s, Statement::NoOpStatement)
+            {
+                nested.push(*function);
+            }
+        }
+    });
+    if nested.is_empty() {
+        return Ok(());
+    }
+
+    let renames = nested
+        .iter()
+        .map(|f| {
+            let name = function_name(f);
+            (name.to_string(), format!("{}.{}", parent, name))
+        })
+        .collect::<HashMap<_, _>>();
+    let parent_locals = local_names(function);
+    rename_functions(function, &renames, &parent_locals);
+
+    for mut nested_function in nested {
+        let locals = local_names(&nested_function);
+        let name = function_name(&nested_function).to_string();
+        if let Function::RegularFunction { statement, .. } = &mut nested_function {
+            let mut captured = None;
+            visit_expressions(statement, &mut |e| {
+                if let Expression::VariableReferenceExpression { name, .. } = e {
+                    if captured.is_none() && parent_locals.contains(name) && !locals.contains(name)
+                    {
+                        captured = Some(name.clone());
+                    }
+                }
+            });
+            if let Some(captured) = captured {
+                return Err(format!(
+                    "Nested function `{}` can't use `{}` of `{}`, variables can't be captured",
+                    name, captured, parent
+                ));
+            }
+        }
+
+        rename_functions(&mut nested_function, &renames, &locals);
+        if let Function::RegularFunction { name, .. } = &mut nested_function {
+            *name = renames[name].clone();
+        }
+        hoist_from_function(&mut nested_function, hoisted)?;
+        hoisted.push(nested_function);
+    }
+    Ok(())
+}
+
+/// Get the name of a function.
+///
+/// # Arguments
+/// * `function` - The function.
+fn function_name(function: &Function) -> &str {
+    match function {
+        Function::RegularFunction { name, .. } => name,
+        Function::ExternalFunction { name, .. } => name,
+    }
+}
+
+/// Get the names of the arguments and variables of a function, in any scope.
+///
+/// # Arguments
+/// * `function` - The function.
+fn local_names(function: &Function) -> HashSet<String> {
+    let mut names = HashSet::new();
+    if let Function::RegularFunction {
+        args, statement, ..
+    } = function
+    {
+        names.extend(args.iter().map(|arg| arg.name.clone()));
+        visit_statements_ref(statement, &mut |s| match s {
+            Statement::VariableDeclarationStatement { name, .. } => {
+                names.insert(name.clone());
+            }
+            Statement::TupleDeclarationStatement { names: n, .. } => {
+                names.extend(n.iter().cloned());
+            }
+            _ => (),
+        });
+    }
+    names
+}
+
+/// Get the names of the functions called in the body of a function, or referenced as function
+/// pointers by names that aren't its arguments or variables.
+///
+/// # Arguments
+/// * `function` - The function.
+pub(super) fn referenced_names(function: &Function) -> HashSet<String> {
+    let mut names = HashSet::new();
+    let locals = local_names(function);
+    if let Function::RegularFunction { statement, .. } = function {
+        visit_expressions_ref(statement, &mut |e| match e {
+            Expression::FunctionCallExpression { name, .. } => {
+                names.insert(name.clone());
+            }
+            Expression::VariableReferenceExpression { name, .. } if !locals.contains(name) => {
+                names.insert(name.clone());
+            }
+            _ => (),
+        });
+    }
+    names
+}
+
+/// Count the statements in the body of a function, not counting the braces of compound statements
+/// as statements of their own.
+///
+/// # Arguments
+/// * `function` - The function.
+pub(super) fn statement_count(function: &Function) -> usize {
+    let mut count = 0;
+    if let Function::RegularFunction { statement, .. } = function {
+        visit_statements_ref(statement, &mut |s| {
+            if !matches!(s, Statement::CompoundStatement { .. }) {
+                count += 1;
+            }
+        });
+    }
+    count
+}
+
+/// Rename calls and references to functions in the body of a function, unless a variable with the
+/// same name is declared in it.
+///
+/// # Arguments
+/// * `function` - The function whose body is renamed in.
+/// * `renames` - The new name of each renamed function.
+/// * `locals` - The names of the arguments and variables of the function.
+fn rename_functions(
+    function: &mut Function,
+    renames: &HashMap<String, String>,
+    locals: &HashSet<String>,
+) {
+    if let Function::RegularFunction { statement, .. } = function {
+        visit_expressions(statement, &mut |e| match e {
+            Expression::FunctionCallExpression { name, .. }
+            | Expression::VariableReferenceExpression { name, .. } => {
+                if let (Some(renamed), false) = (renames.get(name), locals.contains(name)) {
+                    *name = renamed.clone();
+                }
+            }
+            _ => (),
+        });
+    }
+}
+
+/// Call a function on a statement and every statement in it, including the statements of block
+/// expressions. A statement is visited before the statements in it, so it can be replaced first.
+///
+/// # Arguments
+/// * `statement` - The outermost statement.
+/// * `f` - The function to call on each statement.
+fn visit_statements(statement: &mut Statement, f: &mut dyn FnMut(&mut Statement)) {
+    f(statement);
+    let (statements, expressions) = statement_children(statement);
+    for statement in statements {
+        visit_statements(statement, f);
+    }
+    for expression in expressions {
+        visit_sub_expressions(expression, &mut |e| {
+            if let Expression::BlockExpression { statements, .. } = e {
+                for statement in statements {
+                    visit_statements(statement, f);
+                }
+            }
+        });
+    }
+}
+
+/// Call a function on every expression in a statement, including those in nested statements.
+///
+/// # Arguments
+/// * `statement` - The outermost statement.
+/// * `f` - The function to call on each expression.
+fn visit_expressions(statement: &mut Statement, f: &mut dyn FnMut(&mut Expression)) {
+    visit_statements(statement, &mut |s| {
+        for expression in statement_children(s).1 {
+            visit_sub_expressions(expression, f);
+        }
+    });
+}
+
+/// Call a function on an expression and the expressions in it, but not in the statements of
+/// block expressions.
+///
+/// # Arguments
+/// * `expression` - The outermost expression.
+/// * `f` - The function to call on each expression.
+fn visit_sub_expressions(expression: &mut Expression, f: &mut dyn FnMut(&mut Expression)) {
+    f(expression);
+    match expression {
+        Expression::ParenExpression { expression, .. }
+        | Expression::UnaryExpression { expression, .. }
+        | Expression::PostfixExpression { expression, .. }
+        | Expression::CastExpression { expression, .. }
+        | Expression::FieldAccessExpression { expression, .. } => {
+            visit_sub_expressions(expression, f)
+        }
+        Expression::TupleExpression { elements, .. }
+        | Expression::ArrayLiteralExpression { elements, .. } => {
+            for element in elements {
+                visit_sub_expressions(element, f);
+            }
+        }
+        Expression::FunctionCallExpression { args, .. } => {
+            for arg in args {
+                visit_sub_expressions(arg, f);
+            }
+        }
+        Expression::IndexExpression { array, index, .. } => {
+            visit_sub_expressions(array, f);
+            visit_sub_expressions(index, f);
+        }
+        Expression::BinaryExpression {
+            l_expression,
+            r_expression,
+            ..
+        } => {
+            visit_sub_expressions(l_expression, f);
+            visit_sub_expressions(r_expression, f);
+        }
+        Expression::BlockExpression { tail, .. } => visit_sub_expressions(tail, f),
+        Expression::LiteralExpression { .. }
+        | Expression::VariableReferenceExpression { .. }
+        | Expression::SizeOfExpression { .. } => {}
+    }
+}
+
+/// Get the statements and expressions directly in a statement.
+///
+/// # Arguments
+/// * `statement` - The statement.
+fn statement_children(statement: &mut Statement) -> (Vec<&mut Statement>, Vec<&mut Expression>) {
+    match statement {
+        Statement::CompoundStatement { statements } => (statements.iter_mut().collect(), vec![]),
+        Statement::IfStatement {
+            condition,
+            then_statement,
+            else_statement,
+        } => {
+            let mut statements = vec![then_statement.as_mut()];
+            statements.extend(else_statement.as_deref_mut());
+            (statements, vec![condition.as_mut()])
+        }
+        Statement::SwitchStatement {
+            value,
+            cases,
+            default,
+        } => {
+            let mut statements = cases.iter_mut().map(|(_, s)| s).collect::<Vec<_>>();
+            statements.extend(default.as_deref_mut());
+            (statements, vec![value.as_mut()])
+        }
+        Statement::DoWhileStatement { body, condition } => {
+            (vec![body.as_mut()], vec![condition.as_mut()])
+        }
+        Statement::ReturnStatement { value, .. }
+        | Statement::VariableDeclarationStatement { value, .. } => {
+            (vec![], value.as_deref_mut().into_iter().collect())
+        }
+        Statement::TupleDeclarationStatement { value, .. } => (vec![], vec![value.as_mut()]),
+        Statement::ExpressionStatement { expression } => (vec![], vec![expression.as_mut()]),
+        Statement::FunctionDefinitionStatement { .. } | Statement::NoOpStatement => {
+            (vec![], vec![])
+        }
+    }
+}
+
+/// Call a function on a statement and every statement in it, like [`visit_statements`], without
+/// changing them.
+///
+/// [`visit_statements`]: fn.visit_statements.html
+///
+/// # Arguments
+/// * `statement` - The outermost statement.
+/// * `f` - The function to call on each statement.
+fn visit_statements_ref(statement: &Statement, f: &mut dyn FnMut(&Statement)) {
+    f(statement);
+    let (statements, expressions) = statement_children_ref(statement);
+    for statement in statements {
+        visit_statements_ref(statement, f);
+    }
+    for expression in expressions {
+        visit_sub_expressions_ref(expression, &mut |e| {
+            if let Expression::BlockExpression { statements, .. } = e {
+                for statement in statements {
+                    visit_statements_ref(statement, f);
+                }
+            }
+        });
+    }
+}
+
+/// Call a function on every expression in a statement, like [`visit_expressions`], without
+/// changing them.
+///
+/// [`visit_expressions`]: fn.visit_expressions.html
+///
+/// # Arguments
+/// * `statement` - The outermost statement.
+/// * `f` - The function to call on each expression.
+fn visit_expressions_ref(statement: &Statement, f: &mut dyn FnMut(&Expression)) {
+    visit_statements_ref(statement, &mut |s| {
+        for expression in statement_children_ref(s).1 {
+            visit_sub_expressions_ref(expression, f);
+        }
+    });
+}
+
+/// Call a function on an expression and the expressions in it, like [`visit_sub_expressions`],
+/// without changing them.
+///
+/// [`visit_sub_expressions`]: fn.visit_sub_expressions.html
+///
+/// # Arguments
+/// * `expression` - The outermost expression.
+/// * `f` - The function to call on each expression.
+fn visit_sub_expressions_ref(expression: &Expression, f: &mut dyn FnMut(&Expression)) {
+    f(expression);
+    match expression {
+        Expression::ParenExpression { expression, .. }
+        | Expression::UnaryExpression { expression, .. }
+        | Expression::PostfixExpression { expression, .. }
+        | Expression::CastExpression { expression, .. }
+        | Expression::FieldAccessExpression { expression, .. } => {
+            visit_sub_expressions_ref(expression, f)
+        }
+        Expression::TupleExpression { elements, .. }
+        | Expression::ArrayLiteralExpression { elements, .. } => {
+            for element in elements {
+                visit_sub_expressions_ref(element, f);
+            }
+        }
+        Expression::FunctionCallExpression { args, .. } => {
+            for arg in args {
+                visit_sub_expressions_ref(arg, f);
+            }
+        }
+        Expression::IndexExpression { array, index, .. } => {
+            visit_sub_expressions_ref(array, f);
+            visit_sub_expressions_ref(index, f);
+        }
+        Expression::BinaryExpression {
+            l_expression,
+            r_expression,
+            ..
+        } => {
+            visit_sub_expressions_ref(l_expression, f);
+            visit_sub_expressions_ref(r_expression, f);
+        }
+        Expression::BlockExpression { tail, .. } => visit_sub_expressions_ref(tail, f),
+        Expression::LiteralExpression { .. }
+        | Expression::VariableReferenceExpression { .. }
+        | Expression::SizeOfExpression { .. } => {}
+    }
+}
+
+/// Get the statements and expressions directly in a statement, without changing them.
+///
+/// # Arguments
+/// * `statement` - The statement.
+fn statement_children_ref(statement: &Statement) -> (Vec<&Statement>, Vec<&Expression>) {
+    match statement {
+        Statement::CompoundStatement { statements } => (statements.iter().collect(), vec![]),
+        Statement::IfStatement {
+            condition,
+            then_statement,
+            else_statement,
+        } => {
+            let mut statements = vec![then_statement.as_ref()];
+            statements.extend(else_statement.as_deref());
+            (statements, vec![condition.as_ref()])
+        }
+        Statement::SwitchStatement {
+            value,
+            cases,
+            default,
+        } => {
+            let mut statements = cases.iter().map(|(_, s)| s).collect::<Vec<_>>();
+            statements.extend(default.as_deref());
+            (statements, vec![value.as_ref()])
+        }
+        Statement::DoWhileStatement { body, condition } => {
+            (vec![body.as_ref()], vec![condition.as_ref()])
+        }
+        Statement::ReturnStatement { value, .. }
+        | Statement::VariableDeclarationStatement { value, .. } => {
+            (vec![], value.as_deref().into_iter().collect())
+        }
+        Statement::TupleDeclarationStatement { value, .. } => (vec![], vec![value.as_ref()]),
+        Statement::ExpressionStatement { expression } => (vec![], vec![expression.as_ref()]),
+        Statement::FunctionDefinitionStatement { .. } | Statement::NoOpStatement => {
+            (vec![], vec![])
+        }
+    }
+}
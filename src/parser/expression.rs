@@ -1,42 +1,108 @@
 use crate::lexer::tokens;
-use crate::lexer::tokens::{Literal, Token, UNARY_SYMBOLS};
+use crate::lexer::tokens::{
+    Literal, Span, Token, POSTFIX_SYMBOLS, UNARY_OP_PRECEDENCE, UNARY_SYMBOLS,
+};
+use crate::parser::statement::Statement;
+use crate::parser::types::Type;
 use crate::parser::Parser;
 use crate::Result;
 use crate::{peek_identifier_or_err, peek_literal_or_err, peek_symbol_or_err};
 use log::trace;
 
 /// A yot expression.
-#[derive(Debug)]
+///
+/// The span of an expression is the position of its first token, or the default span if it wasn't
+/// parsed from source.
+#[derive(Debug, Clone, PartialEq)]
 pub enum Expression {
     /// A literal value.
     ///
     /// # Grammar
     /// * IntegerLiteral
     /// * StrLiteral
-    LiteralExpression { value: Literal },
+    LiteralExpression { value: Literal, span: Span },
 
     /// An expression enclosed in parentheses.
     ///
     /// # Grammar
     /// * "(" + Expression + ")"
-    ParenExpression { expression: Box<Expression> },
+    ParenExpression {
+        expression: Box<Expression>,
+        span: Span,
+    },
 
     /// A reference to a variable.
     ///
     /// # Grammar
     /// * Identifier
-    VariableReferenceExpression { name: String },
+    VariableReferenceExpression { name: String, span: Span },
+
+    /// Multiple values grouped together.
+    ///
+    /// # Grammar
+    /// * "(" + Expression + ("," + Expression)... + ")"
+    TupleExpression {
+        elements: Vec<Expression>,
+        span: Span,
+    },
+
+    /// An array of values.
+    ///
+    /// # Grammar
+    /// * "[" + (Expression + ",")... + "]"
+    ArrayLiteralExpression {
+        elements: Vec<Expression>,
+        span: Span,
+    },
+
+    /// An element of an array at an index.
+    ///
+    /// # Grammar
+    /// * Expression + "[" + Expression + "]"
+    IndexExpression {
+        array: Box<Expression>,
+        index: Box<Expression>,
+        span: Span,
+    },
+
+    /// A field of a struct.
+    ///
+    /// # Grammar
+    /// * Expression + "." + Identifier
+    FieldAccessExpression {
+        expression: Box<Expression>,
+        field: String,
+        span: Span,
+    },
 
-    /// A call to a function with arguments.
+    /// A call to a function with arguments, or the construction of a struct from the values of
+    /// its fields.
     ///
     /// # Grammar
     /// * Identifier + "(" + (Expression + ",")... + ")"
-    FunctionCallExpression { name: String, args: Vec<Expression> },
+    FunctionCallExpression {
+        name: String,
+        args: Vec<Expression>,
+        span: Span,
+    },
+
+    /// A block of statements whose value is its final expression.
+    ///
+    /// Variables declared in the block are only available inside it, like in a compound
+    /// statement.
+    ///
+    /// # Grammar
+    /// * "{" + Statement... + Expression + "}"
+    BlockExpression {
+        statements: Vec<Statement>,
+        tail: Box<Expression>,
+        span: Span,
+    },
 
     /// A link between two expresesions with a binary operator.
     ///
     /// Possible operators:
-    /// "=", "+", "-", "*", "/", "==", "!=", "<", ">", "<=", ">="
+    /// "=", "+", "-", "*", "/", "**", "==", "!=", "<", ">", "<=", ">="
     ///
     /// # Grammar
     /// * Expression + op + Expression
@@ -44,19 +110,57 @@ pub enum Expression {
         op: String,
         l_expression: Box<Expression>,
         r_expression: Box<Expression>,
+        span: Span,
     },
 
     /// A prefix operator to an expression.
     ///
     /// Possible operators:
-    /// "-"
+    /// "-", "&" (the address of a variable, array element, or field), "*" (the value a pointer
+    /// points to)
+    ///
+    /// A prefix operator binds tighter than any binary operator, except that `-x ** 2` is
+    /// `-(x ** 2)`. It binds looser than indexing, fields, postfix operators, and casts, so
+    /// `-x as f64` is `-(x as f64)` and `*p.x` is `*(p.x)`. A negated integer literal such as `-2`
+    /// is parsed as a literal instead, unless it is raised to a power.
     ///
     /// # Grammar
     /// * op + Expression
     UnaryExpression {
         op: String,
         expression: Box<Expression>,
+        span: Span,
     },
+
+    /// A postfix operator that increments or decrements a variable, array element, or field and
+    /// results in its previous value.
+    ///
+    /// Possible operators:
+    /// "++", "--"
+    ///
+    /// # Grammar
+    /// * Expression + op
+    PostfixExpression {
+        op: String,
+        expression: Box<Expression>,
+        span: Span,
+    },
+
+    /// A conversion of a number to another number type.
+    ///
+    /// # Grammar
+    /// * Expression + "as" + Type
+    CastExpression {
+        expression: Box<Expression>,
+        target_type: Type,
+        span: Span,
+    },
+
+    /// The size in bytes of a type on the target, as an i64.
+    ///
+    /// # Grammar
+    /// * "sizeof" + "(" + Type + ")"
+    SizeOfExpression { target_type: Type, span: Span },
 }
 
 impl Parser {
@@ -67,29 +171,52 @@ impl Parser {
     }
 
     fn parse_expression_no_binary(&mut self) -> Result<Expression> {
-        match self.tokens.peek() {
-            Some(Token::Literal(_)) => self.parse_literal_expression(),
-            Some(Token::Identifier(_)) => {
-                let name = peek_identifier_or_err!(self);
-                self.tokens.next();
-                if self.next_symbol_is("(") {
-                    self.parse_function_call_expression(name)
-                } else {
-                    self.parse_variable_reference_expression(name)
+        let mut expression = self.parse_expression_no_postfix()?;
+        loop {
+            match self.tokens.peek() {
+                Some(Token::Symbol(s)) if s == "[" => {
+                    self.tokens.next();
+                    expression = self.parse_index_expression(expression)?;
                 }
+                Some(Token::Symbol(s)) if s == "." => {
+                    expression = self.parse_field_access_expression(expression)?;
+                }
+                Some(Token::Symbol(s)) if POSTFIX_SYMBOLS.contains(&&s[..]) => {
+                    expression = self.parse_postfix_expression(expression)?;
+                }
+                Some(Token::Identifier(k)) if k == "as" => {
+                    expression = self.parse_cast_expression(expression)?;
+                }
+                _ => return Ok(expression),
             }
-            Some(Token::Symbol(s)) if s == "(" => self.parse_paren_expression(),
+        }
+    }
+
+    fn parse_expression_no_postfix(&mut self) -> Result<Expression> {
+        self.nested(|parser| match parser.tokens.peek() {
+            Some(Token::Literal(_)) => parser.parse_literal_expression(),
+            Some(Token::Identifier(k)) if k == "sizeof" && parser.nth_symbol_is(1, "(") => {
+                parser.parse_size_of_expression()
+            }
+            Some(Token::Identifier(_)) if parser.nth_symbol_is(1, "(") => {
+                parser.parse_function_call_expression()
+            }
+            Some(Token::Identifier(_)) => parser.parse_variable_reference_expression(),
+            Some(Token::Symbol(s)) if s == "(" => parser.parse_paren_expression(),
+            Some(Token::Symbol(s)) if s == "[" => parser.parse_array_literal_expression(),
+            Some(Token::Symbol(s)) if s == "{" => parser.parse_block_expression(),
             Some(Token::Symbol(s)) if UNARY_SYMBOLS.contains(&&s[..]) => {
-                self.parse_unary_expression()
+                parser.parse_unary_expression()
             }
             _ => Err("Unable to parse expression".to_string()),
-        }
+        })
     }
 
     fn parse_literal_expression(&mut self) -> Result<Expression> {
         trace!("Parsing literal expression");
         let expression = Ok(Expression::LiteralExpression {
             value: peek_literal_or_err!(self),
+            span: self.tokens.span(),
         });
         self.tokens.next();
         expression
@@ -97,23 +224,131 @@ impl Parser {
 
     fn parse_paren_expression(&mut self) -> Result<Expression> {
         trace!("Parsing paren expression");
+        let span = self.tokens.span();
         if !self.next_symbol_is("(") {
             return Err("Misidentified paren expression".to_string());
         }
-        let expression = Box::new(self.parse_expression()?);
+        let expression = self.parse_expression()?;
+        if self.next_symbol_is(",") {
+            trace!("Parsing tuple expression");
+            let mut elements = vec![expression];
+            loop {
+                elements.push(self.parse_expression()?);
+                match self.tokens.next() {
+                    Some(Token::Symbol(s)) if s == ")" => break,
+                    Some(Token::Symbol(s)) if s == "," => (),
+                    _ => return Err("Expected `)` or `,` after tuple element".to_string()),
+                }
+            }
+            return Ok(Expression::TupleExpression { elements, span });
+        }
         if !self.next_symbol_is(")") {
             return Err("Expected `)` after expression".to_string());
         }
-        Ok(Expression::ParenExpression { expression })
+        Ok(Expression::ParenExpression {
+            expression: Box::new(expression),
+            span,
+        })
     }
 
-    fn parse_variable_reference_expression(&mut self, name: String) -> Result<Expression> {
+    fn parse_block_expression(&mut self) -> Result<Expression> {
+        trace!("Parsing block expression");
+        let span = self.tokens.span();
+        self.tokens.next(); // Eat {
+        let mut statements: Vec<Statement> = Vec::new();
+        loop {
+            match self.tokens.peek() {
+                Some(Token::Symbol(s)) if ["{", "?", "->", "@", ";"].contains(&&s[..]) => {
+                    statements.push(self.parse_statement()?)
+                }
+                Some(Token::Symbol(s)) if s == "}" => {
+                    return Err("Expected an expression at the end of block expression".to_string())
+                }
+                _ => {
+                    let expression = self.parse_expression()?;
+                    if self.next_symbol_is(";") {
+                        statements.push(Statement::ExpressionStatement {
+                            expression: Box::new(expression),
+                        });
+                    } else if self.next_symbol_is("}") {
+                        return Ok(Expression::BlockExpression {
+                            statements,
+                            tail: Box::new(expression),
+                            span,
+                        });
+                    } else {
+                        return Err("Expected `;` or `}` after expression in block".to_string());
+                    }
+                }
+            }
+        }
+    }
+
+    fn parse_array_literal_expression(&mut self) -> Result<Expression> {
+        trace!("Parsing array literal expression");
+        let span = self.tokens.span();
+        if !self.next_symbol_is("[") {
+            return Err("Misidentified array literal expression".to_string());
+        }
+        let mut elements: Vec<Expression> = Vec::new();
+
+        if !self.next_symbol_is("]") {
+            loop {
+                elements.push(self.parse_expression()?);
+                match self.tokens.next() {
+                    Some(Token::Symbol(s)) if s == "]" => break,
+                    Some(Token::Symbol(s)) if s == "," => (),
+                    _ => return Err("Expected `]` or `,` after array element".to_string()),
+                }
+            }
+        }
+        Ok(Expression::ArrayLiteralExpression { elements, span })
+    }
+
+    fn parse_index_expression(&mut self, array: Expression) -> Result<Expression> {
+        trace!("Parsing index expression");
+        let index = Box::new(self.parse_expression()?);
+        if !self.next_symbol_is("]") {
+            return Err("Expected `]` after index".to_string());
+        }
+        Ok(Expression::IndexExpression {
+            span: array.span(),
+            array: Box::new(array),
+            index,
+        })
+    }
+
+    fn parse_field_access_expression(&mut self, expression: Expression) -> Result<Expression> {
+        trace!("Parsing field access expression");
+        self.tokens.next(); // Eat .
+        let field = match self.tokens.next() {
+            Some(Token::Identifier(field)) => field,
+            _ => return Err("Expected a field name after `.`".to_string()),
+        };
+        Ok(Expression::FieldAccessExpression {
+            span: expression.span(),
+            expression: Box::new(expression),
+            field,
+        })
+    }
+
+    fn parse_variable_reference_expression(&mut self) -> Result<Expression> {
         trace!("Parsing variable reference expression");
-        Ok(Expression::VariableReferenceExpression { name })
+        let span = self.tokens.span();
+        let name = peek_identifier_or_err!(self);
+        self.tokens.next();
+        Ok(Expression::VariableReferenceExpression { name, span })
     }
 
-    fn parse_function_call_expression(&mut self, name: String) -> Result<Expression> {
+    fn parse_function_call_expression(&mut self) -> Result<Expression> {
         trace!("Parsing function call expression");
+        let span = self.tokens.span();
+        let name = peek_identifier_or_err!(self);
+        self.tokens.next();
+        if !self.next_symbol_is("(") {
+            return Err("Misidentified function call expression".to_string());
+        }
+
         let mut args: Vec<Expression> = Vec::new();
 
         if !self.next_symbol_is(")") {
@@ -131,7 +366,7 @@ impl Parser {
                 }
             }
         }
-        Ok(Expression::FunctionCallExpression { name, args })
+        Ok(Expression::FunctionCallExpression { name, args, span })
     }
 
     fn parse_binary_r_expression(
@@ -141,47 +376,172 @@ impl Parser {
     ) -> Result<Expression> {
         trace!("Parsing binary r expression");
         let mut l_expression = l_expression;
-
-        macro_rules! peek_symbol_or_zero {
-            ($self:ident) => {
-                String::from(match $self.tokens.peek() {
-                    Some(Token::Symbol(s)) => s,
-                    _ => "0",
-                });
-            };
-        }
+        // Comparisons share a precedence, so a chain of them is always parsed by the same loop
+        let mut previous_comparison: Option<String> = None;
 
         loop {
-            let op = peek_symbol_or_zero!(self);
-            let current_precedence = tokens::binary_op_precedence(&op);
+            let current_precedence = self.next_binary_op_precedence();
 
             if current_precedence < precedence {
                 return Ok(l_expression);
             }
 
-            self.tokens.next();
+            // The operator is moved out of its token rather than cloned
+            let op = match self.tokens.next() {
+                Some(Token::Symbol(op)) => op,
+                _ => return Err("Misidentified binary expression".to_string()),
+            };
+            if tokens::is_comparison(&op) {
+                // `a < b < c` would compare the 1 or 0 result of `a < b` to `c`
+                if let Some(previous) = previous_comparison {
+                    return Err(format!(
+                        "Chained comparison `{}` then `{}` isn't allowed, put one of the comparisons in parentheses",
+                        previous, op
+                    ));
+                }
+                previous_comparison = Some(op.clone());
+            }
             let mut r_expression = self.parse_expression_no_binary()?;
 
-            let next_precedence = tokens::binary_op_precedence(&peek_symbol_or_zero!(self));
+            let next_precedence = self.next_binary_op_precedence();
 
-            if current_precedence < next_precedence {
-                r_expression =
-                    self.parse_binary_r_expression(current_precedence + 1, r_expression)?;
+            // A right associative operator takes the following operators of the same precedence
+            // into its right side
+            if tokens::is_right_associative(&op) && current_precedence <= next_precedence {
+                r_expression = self.nested(|parser| {
+                    parser.parse_binary_r_expression(current_precedence, r_expression)
+                })?;
+            } else if current_precedence < next_precedence {
+                r_expression = self.nested(|parser| {
+                    parser.parse_binary_r_expression(current_precedence + 1, r_expression)
+                })?;
             }
 
             l_expression = Expression::BinaryExpression {
                 op,
+                span: l_expression.span(),
                 l_expression: Box::new(l_expression),
                 r_expression: Box::new(r_expression),
             };
         }
     }
 
+    /// Get the precedence of the next token as a binary operator, -1 if it isn't one.
+    fn next_binary_op_precedence(&self) -> i32 {
+        match self.tokens.peek() {
+            Some(Token::Symbol(s)) => tokens::binary_op_precedence(s),
+            _ => -1,
+        }
+    }
+
     fn parse_unary_expression(&mut self) -> Result<Expression> {
         trace!("Parsing unary expression");
+        let span = self.tokens.span();
         let op = peek_symbol_or_err!(self);
         self.tokens.next();
-        let expression = Box::new(self.parse_expression_no_binary()?);
-        Ok(Expression::UnaryExpression { op, expression })
+
+        // A negated integer literal is a literal itself, so the whole i32 range can be written
+        let binds_tighter = match self.tokens.peek_nth(1) {
+            Some(Token::Symbol(s)) => tokens::binary_op_precedence(s) > UNARY_OP_PRECEDENCE,
+            _ => false,
+        };
+        let negated = match (op == "-" && !binds_tighter, self.tokens.peek()) {
+            (true, Some(Token::Literal(Literal::Integer(i)))) => {
+                Some(Literal::Integer(i.wrapping_neg()))
+            }
+            (true, Some(Token::Literal(Literal::SizedInteger { value, bits }))) => {
+                Some(Literal::SizedInteger {
+                    value: value.wrapping_neg(),
+                    bits: *bits,
+                })
+            }
+            _ => None,
+        };
+        if let Some(value) = negated {
+            self.tokens.next();
+            return Ok(Expression::LiteralExpression { value, span });
+        }
+
+        let mut expression = self.parse_expression_no_binary()?;
+        match &op[..] {
+            // Only negation takes a power into its operand, as in math
+            "-" => {
+                expression = self.parse_binary_r_expression(UNARY_OP_PRECEDENCE + 1, expression)?
+            }
+            // Two dereferences, which are lexed as one symbol
+            "**" => {
+                let expression = Expression::UnaryExpression {
+                    op: "*".to_string(),
+                    expression: Box::new(expression),
+                    span,
+                };
+                return Ok(Expression::UnaryExpression {
+                    op: "*".to_string(),
+                    expression: Box::new(expression),
+                    span,
+                });
+            }
+            _ => (),
+        }
+        Ok(Expression::UnaryExpression {
+            op,
+            expression: Box::new(expression),
+            span,
+        })
+    }
+
+    fn parse_postfix_expression(&mut self, expression: Expression) -> Result<Expression> {
+        trace!("Parsing postfix expression");
+        let op = peek_symbol_or_err!(self);
+        self.tokens.next();
+        Ok(Expression::PostfixExpression {
+            op,
+            span: expression.span(),
+            expression: Box::new(expression),
+        })
+    }
+
+    fn parse_size_of_expression(&mut self) -> Result<Expression> {
+        trace!("Parsing sizeof expression");
+        let span = self.tokens.span();
+        self.tokens.next(); // Eat sizeof
+        self.tokens.next(); // Eat (
+        let target_type = self.parse_type()?;
+        if !self.next_symbol_is(")") {
+            return Err("Expected `)` after type in sizeof".to_string());
+        }
+        Ok(Expression::SizeOfExpression { target_type, span })
+    }
+
+    fn parse_cast_expression(&mut self, expression: Expression) -> Result<Expression> {
+        trace!("Parsing cast expression");
+        self.tokens.next(); // Eat as
+        Ok(Expression::CastExpression {
+            span: expression.span(),
+            expression: Box::new(expression),
+            target_type: self.parse_type()?,
+        })
+    }
+}
+
+impl Expression {
+    /// Get the position of the first token of the expression.
+    pub fn span(&self) -> Span {
+        match self {
+            Expression::LiteralExpression { span, .. }
+            | Expression::ParenExpression { span, .. }
+            | Expression::VariableReferenceExpression { span, .. }
+            | Expression::TupleExpression { span, .. }
+            | Expression::ArrayLiteralExpression { span, .. }
+            | Expression::IndexExpression { span, .. }
+            | Expression::FieldAccessExpression { span, .. }
+            | Expression::FunctionCallExpression { span, .. }
+            | Expression::BlockExpression { span, .. }
+            | Expression::BinaryExpression { span, .. }
+            | Expression::UnaryExpression { span, .. }
+            | Expression::PostfixExpression { span, .. }
+            | Expression::CastExpression { span, .. }
+            | Expression::SizeOfExpression { span, .. } => *span,
+        }
     }
 }
@@ -1,27 +1,102 @@
 use crate::parser::statement::Statement;
+use crate::parser::types::Type;
 use crate::parser::{Parser, Token};
 use crate::Result;
 use crate::{peek_identifier_or_err, peek_symbol_or_err};
 use log::trace;
 
+/// An argument of a function with an optional type.
+///
+/// # Grammar
+/// * Identifier
+/// * Identifier + ":" + Type
+#[derive(Debug, Clone, PartialEq)]
+pub struct Argument {
+    /// The name of the argument, `_` if it is unused.
+    pub name: String,
+    /// The type of the argument, defaults to `i32`.
+    pub arg_type: Type,
+}
+
+/// An attribute that changes how a function is optimized, written before its name.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FunctionAttribute {
+    /// `inline`, calls to the function are always inlined.
+    Inline,
+    /// `noinline`, calls to the function are never inlined.
+    NoInline,
+    /// `noreturn`, the function never returns, such as one that exits the program.
+    NoReturn,
+}
+
+impl FunctionAttribute {
+    /// Get the attribute a keyword is for, `None` if it isn't an attribute.
+    ///
+    /// # Arguments
+    /// * `keyword` - The keyword before the function name.
+    fn from_keyword(keyword: &str) -> Option<Self> {
+        match keyword {
+            "inline" => Some(FunctionAttribute::Inline),
+            "noinline" => Some(FunctionAttribute::NoInline),
+            "noreturn" => Some(FunctionAttribute::NoReturn),
+            _ => None,
+        }
+    }
+
+    /// Get the keyword of the attribute.
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            FunctionAttribute::Inline => "inline",
+            FunctionAttribute::NoInline => "noinline",
+            FunctionAttribute::NoReturn => "noreturn",
+        }
+    }
+}
+
+/// Check if an identifier is a keyword that can come before the name of a function.
+///
+/// # Arguments
+/// * `keyword` - The identifier.
+pub(super) fn is_modifier(keyword: &str) -> bool {
+    keyword == "const" || FunctionAttribute::from_keyword(keyword).is_some()
+}
+
 /// A yot function, either with a body or extern.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Function {
     /// A regular yot function with a body.
     ///
+    /// Calls to a `const` function with constant arguments are evaluated at compile time.
+    ///
     /// # Grammar
-    /// * "@" + Identifier + "[" + (Identifier + ",")... + "]" + Statement
+    /// * "@" + ("const") + (FunctionAttribute)... + Identifier + "[" + (Argument + ",")... + "]" +
+    ///   (":" + ReturnType) + Statement
     RegularFunction {
         name: String,
-        args: Vec<String>,
+        /// The line the function is declared on, 0 if unknown.
+        line: usize,
+        is_const: bool,
+        attributes: Vec<FunctionAttribute>,
+        args: Vec<Argument>,
+        /// The return type of the function, `None` if it is `void`. Defaults to `i32`.
+        return_type: Option<Type>,
         statement: Box<Statement>,
     },
 
     /// An external function.
     ///
     /// # Grammar
-    /// * "@!" + Identifier + "[" + (Identifier + ",")... + "]"
-    ExternalFunction { name: String, args: Vec<String> },
+    /// * "@!" + ("noreturn") + Identifier + "[" + (Argument + ",")... + ("...") + "]" +
+    ///   (":" + ReturnType) + ";"
+    ExternalFunction {
+        name: String,
+        attributes: Vec<FunctionAttribute>,
+        args: Vec<Argument>,
+        /// Whether or not the function takes more arguments after `args`, such as `printf`.
+        is_variadic: bool,
+        /// The return type of the function, `None` if it is `void`. Defaults to `i32`.
+        return_type: Option<Type>,
+    },
 }
 
 impl Parser {
@@ -29,19 +104,89 @@ impl Parser {
         trace!("Parsing function");
         match &peek_symbol_or_err!(self)[..] {
             s @ "@" | s @ "@!" => {
+                let line = self.tokens.span().line;
                 self.tokens.next();
+
+                // A modifier is only a keyword if followed by the function name or another modifier
+                let mut is_const = false;
+                let mut attributes = Vec::new();
+                while let (Some(Token::Identifier(keyword)), Some(Token::Identifier(_))) =
+                    (self.tokens.peek(), self.tokens.peek_nth(1))
+                {
+                    if keyword == "const" && s == "@" && !is_const {
+                        is_const = true;
+                    } else if let Some(attribute) = FunctionAttribute::from_keyword(keyword) {
+                        if attributes.contains(&attribute) {
+                            return Err(format!("Duplicate attribute `{}`", keyword));
+                        }
+                        attributes.push(attribute);
+                    } else {
+                        break;
+                    }
+                    self.tokens.next();
+                }
                 let name = peek_identifier_or_err!(self);
                 self.tokens.next();
 
+                if attributes.contains(&FunctionAttribute::Inline)
+                    && attributes.contains(&FunctionAttribute::NoInline)
+                {
+                    return Err(format!(
+                        "Function `{}` can't be both `inline` and `noinline`",
+                        name
+                    ));
+                }
+                if s == "@!" {
+                    if let Some(attribute) = attributes
+                        .iter()
+                        .find(|a| **a != FunctionAttribute::NoReturn)
+                    {
+                        return Err(format!(
+                            "External function `{}` can't be `{}`",
+                            name,
+                            attribute.keyword()
+                        ));
+                    }
+                }
+
                 if !self.next_symbol_is("[") {
                     return Err(format!("Expected `[` after function `{}`", name));
                 }
 
-                let mut args: Vec<String> = Vec::new();
+                let mut args: Vec<Argument> = Vec::new();
+                let mut has_types = Vec::new();
+                let mut is_variadic = false;
                 if !self.next_symbol_is("]") {
                     loop {
-                        args.push(peek_identifier_or_err!(self));
+                        if self.next_symbol_is("...") {
+                            if s == "@" {
+                                return Err(format!(
+                                    "Only external functions can be variadic, not `{}`",
+                                    name
+                                ));
+                            }
+                            if !self.next_symbol_is("]") {
+                                return Err(format!(
+                                    "Expected `]` after `...` in function `{}`",
+                                    name
+                                ));
+                            }
+                            is_variadic = true;
+                            break;
+                        }
+                        let arg_name = peek_identifier_or_err!(self);
                         self.tokens.next();
+                        let has_type = self.next_symbol_is(":");
+                        let arg_type = if has_type {
+                            self.parse_type()?
+                        } else {
+                            Type::I32
+                        };
+                        has_types.push(has_type);
+                        args.push(Argument {
+                            name: arg_name,
+                            arg_type,
+                        });
                         match self.tokens.next() {
                             Some(Token::Symbol(s)) if s == "]" => break,
                             Some(Token::Symbol(s)) if s == "," => (),
@@ -55,20 +200,57 @@ impl Parser {
                     }
                 }
 
+                let return_type = if self.next_symbol_is(":") {
+                    self.parse_return_type()?
+                } else {
+                    Some(Type::I32)
+                };
+
+                // The command line arguments of `@main[argc, argv]` are an i32 and a `[str]`
+                if s == "@" && name == self.entry && args.len() == 2 && !has_types[1] {
+                    args[1].arg_type = Type::Array(Box::new(Type::Str));
+                }
+
                 if s == "@" {
                     let statement = Box::new(self.parse_statement()?);
                     Ok(Function::RegularFunction {
                         name,
+                        line,
+                        is_const,
+                        attributes,
                         args,
+                        return_type,
                         statement,
                     })
                 } else if !self.next_symbol_is(";") {
                     Err(format!("Expected `;` after external function `{}`", name))
                 } else {
-                    Ok(Function::ExternalFunction { name, args })
+                    Ok(Function::ExternalFunction {
+                        name,
+                        attributes,
+                        args,
+                        is_variadic,
+                        return_type,
+                    })
                 }
             }
             _ => Err("Expected `@` or `@!`. (Only top level functions allowed)".to_string()),
         }
     }
+
+    /// Parses the return type of a function, `None` if it is `void`.
+    ///
+    /// # Grammar
+    /// * "void"
+    /// * Type
+    fn parse_return_type(&mut self) -> Result<Option<Type>> {
+        trace!("Parsing return type");
+        match self.tokens.peek() {
+            Some(Token::Identifier(name)) if name == "void" => {
+                self.tokens.next();
+                Ok(None)
+            }
+            _ => Ok(Some(self.parse_type()?)),
+        }
+    }
 }
@@ -1,14 +1,67 @@
+pub mod const_eval;
+pub mod desugar;
 pub mod expression;
 pub mod function;
+pub mod optimize;
+pub mod prelude;
 pub mod program;
 pub mod statement;
+pub mod typecheck;
+pub mod types;
 
-use crate::lexer::tokens::Token;
+use crate::lexer::tokens::{Span, Token};
 use std::iter::Peekable;
+use std::path::PathBuf;
 use std::vec::IntoIter;
 
 type TokenIter = Peekable<IntoIter<Token>>;
 
+/// The remaining [`Token`]s of a program, which can be peeked at any distance ahead.
+///
+/// [`Token`]: ../lexer/tokens/enum.Token.html
+struct TokenStream {
+    tokens: IntoIter<Token>,
+    /// The position of every token, empty if unknown.
+    spans: Vec<Span>,
+    /// The number of `{` that have been consumed without a matching `}`.
+    braces: usize,
+}
+
+impl TokenStream {
+    /// Get the position of the next token, or the default position if it's unknown.
+    fn span(&self) -> Span {
+        let index = self.spans.len().saturating_sub(self.tokens.len());
+        self.spans.get(index).copied().unwrap_or_default()
+    }
+
+    /// Peeks at the next token without consuming it.
+    fn peek(&self) -> Option<&Token> {
+        self.peek_nth(0)
+    }
+
+    /// Peeks at the token `n` tokens after the next one without consuming anything.
+    ///
+    /// # Arguments
+    /// * `n` - The number of tokens to look past, `0` being the next token.
+    fn peek_nth(&self, n: usize) -> Option<&Token> {
+        self.tokens.as_slice().get(n)
+    }
+}
+
+impl Iterator for TokenStream {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.next();
+        match &token {
+            Some(Token::Symbol(s)) if s == "{" => self.braces += 1,
+            Some(Token::Symbol(s)) if s == "}" => self.braces = self.braces.saturating_sub(1),
+            _ => (),
+        }
+        token
+    }
+}
+
 /// A parser that generates an abstract syntax tree, modeled by a yot [`Program`].
 ///
 /// [`Program`]: program/struct.Program.html
@@ -17,15 +70,120 @@ pub struct Parser {
     ///
     /// [`Token`]: ../lexer/tokens/enum.Token.html
     /// [`Lexer`]: ../lexer/struct.Lexer.html
-    tokens: TokenIter,
+    tokens: TokenStream,
+    /// The path of the file being parsed, `None` if the program isn't from a file.
+    path: Option<PathBuf>,
+    /// The files that are being included, outermost first, to detect circular includes.
+    include_stack: Vec<PathBuf>,
+    /// Every file that has been included, which are only included once.
+    included: Vec<PathBuf>,
+    /// The number of statements and expressions being parsed that contain the next one.
+    depth: usize,
+    /// The deepest statements and expressions can be nested before parsing fails.
+    max_depth: usize,
+    /// The name of the function the program starts at.
+    entry: String,
+    /// The errors of the definitions that failed to parse and where they failed.
+    errors: Vec<(String, Span)>,
 }
 
+/// The default of how deep statements and expressions can be nested, which keeps parsing well
+/// within the stack of the main thread.
+pub const DEFAULT_MAX_DEPTH: usize = 500;
+
 impl Parser {
     /// Creates a parser from an iterator of [`Token`]s.
     ///
     /// [`Token`]: ../lexer/tokens/enum.Token.html
     pub fn new(tokens: TokenIter) -> Self {
-        Parser { tokens }
+        Self::with_spans(tokens.collect(), Vec::new())
+    }
+
+    /// Creates a parser from [`Token`]s and their positions, which are kept in the AST.
+    ///
+    /// [`Token`]: ../lexer/tokens/enum.Token.html
+    ///
+    /// # Arguments
+    /// * `tokens` - The tokens of the program.
+    /// * `spans` - The position of each token.
+    pub fn with_spans(tokens: Vec<Token>, spans: Vec<Span>) -> Self {
+        // Comments aren't a part of the AST
+        let mut kept_tokens = Vec::new();
+        let mut kept_spans = Vec::new();
+        for (i, token) in tokens.into_iter().enumerate() {
+            if let Token::Comment(_) = token {
+                continue;
+            }
+            if let Some(span) = spans.get(i) {
+                kept_spans.push(*span);
+            }
+            kept_tokens.push(token);
+        }
+
+        Parser {
+            tokens: TokenStream {
+                tokens: kept_tokens.into_iter(),
+                spans: kept_spans,
+                braces: 0,
+            },
+            path: None,
+            include_stack: Vec::new(),
+            included: Vec::new(),
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            entry: String::from("main"),
+            errors: Vec::new(),
+        }
+    }
+
+    /// Set the name of the function the program starts at, instead of `main`.
+    ///
+    /// # Arguments
+    /// * `entry` - The name of the entry function.
+    pub fn with_entry(mut self, entry: &str) -> Self {
+        self.entry = String::from(entry);
+        self
+    }
+
+    /// Set how deep statements and expressions can be nested before parsing fails, instead of
+    /// overflowing the stack.
+    ///
+    /// # Arguments
+    /// * `max_depth` - The number of levels of nesting.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Set the path of the file being parsed, which included files are resolved relative to.
+    ///
+    /// # Arguments
+    /// * `path` - The path to the program file.
+    pub fn with_path(mut self, path: &str) -> Self {
+        let path = PathBuf::from(path);
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.clone());
+        self.include_stack = vec![canonical.clone()];
+        self.included = vec![canonical];
+        self.path = Some(path);
+        self
+    }
+
+    /// Get every file that has been parsed, which is only the program file until includes are
+    /// parsed.
+    pub fn included_files(&self) -> &[PathBuf] {
+        &self.included
+    }
+
+    /// Get the position of the next token, such as where parsing failed.
+    pub fn span(&self) -> Span {
+        self.tokens.span()
+    }
+
+    /// Get every parse error and where it happened, in the order they were found.
+    ///
+    /// Parsing continues after a definition fails to parse, so there can be more than one error.
+    pub fn errors(&self) -> &[(String, Span)] {
+        &self.errors
     }
 
     /// Peeks at the next token and check if it's a particular symbol.
@@ -43,6 +201,48 @@ impl Parser {
             _ => false,
         }
     }
+
+    /// Peeks at the token `n` tokens after the next one and check if it's a particular symbol
+    /// without consuming anything.
+    ///
+    /// # Arguments
+    /// * `n` - The number of tokens to look past, `0` being the next token.
+    /// * `symbol` - The particular symbol that will be checked against.
+    fn nth_symbol_is(&self, n: usize, symbol: &str) -> bool {
+        matches!(self.tokens.peek_nth(n), Some(Token::Symbol(s)) if s == symbol)
+    }
+
+    /// Parse a statement, expression, or type that can contain itself, failing instead of
+    /// overflowing the stack if it is nested too deeply.
+    ///
+    /// # Arguments
+    /// * `parse` - Parses the statement or expression.
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> crate::Result<T>) -> crate::Result<T> {
+        if self.depth >= self.max_depth {
+            return Err(format!(
+                "Nesting too deep, more than {} levels",
+                self.max_depth
+            ));
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    /// Check if the next tokens are the start of a function after `@` or `@!`, with any modifiers
+    /// such as `const` or `inline` before its name, without consuming anything.
+    fn next_is_function_header(&self) -> bool {
+        let is_identifier = |n| matches!(self.tokens.peek_nth(n), Some(Token::Identifier(_)));
+        // A modifier is only a keyword if followed by the function name or another modifier
+        let mut name = 1;
+        while matches!(self.tokens.peek_nth(name), Some(Token::Identifier(k)) if function::is_modifier(k))
+            && is_identifier(name + 1)
+        {
+            name += 1;
+        }
+        is_identifier(name) && self.nth_symbol_is(name + 1, "[")
+    }
 }
 
 /// Peeks at the next token and returns the name of the identifier if it is one.
@@ -54,7 +254,7 @@ macro_rules! peek_identifier_or_err {
         match $self.tokens.peek() {
             Some(Token::Identifier(name)) => String::from(name),
             _ => return Err("Expected an identifier".to_string()),
-        };
+        }
     };
 }
 
@@ -67,7 +267,7 @@ macro_rules! peek_literal_or_err {
         match $self.tokens.peek() {
             Some(Token::Literal(value)) => value.clone(),
             _ => return Err("Expected a literal".to_string()),
-        };
+        }
     };
 }
 
@@ -80,6 +280,56 @@ macro_rules! peek_symbol_or_err {
         match $self.tokens.peek() {
             Some(Token::Symbol(s)) => String::from(s),
             _ => return Err("Expected a symbol".to_string()),
-        };
+        }
     };
 }
+
+#[cfg(test)]
+mod tests {
+
+    use super::Parser;
+    use crate::lexer::tokens::{Literal, Span};
+    use crate::lexer::Lexer;
+    use crate::parser::expression::Expression;
+
+    fn parser(source: &str) -> Parser {
+        let tokens = Lexer::from_text(source)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        Parser::new(tokens.into_iter().peekable())
+    }
+
+    #[test]
+    fn nth_symbol_is() {
+        let parser = parser("foo(1)");
+        assert!(parser.nth_symbol_is(1, "("));
+        assert!(!parser.nth_symbol_is(0, "("));
+        assert!(parser.nth_symbol_is(3, ")"));
+        assert!(!parser.nth_symbol_is(4, ")"));
+    }
+
+    #[test]
+    fn call_or_reference() {
+        assert_eq!(
+            parser("foo(1)").parse_expression(),
+            Ok(Expression::FunctionCallExpression {
+                name: "foo".to_string(),
+                args: vec![Expression::LiteralExpression {
+                    value: Literal::Integer(1),
+                    span: Span::default()
+                }],
+                span: Span::default(),
+            })
+        );
+        assert_eq!(
+            parser("foo + 1").parse_expression().map(|e| match e {
+                Expression::BinaryExpression { l_expression, .. } => *l_expression,
+                e => e,
+            }),
+            Ok(Expression::VariableReferenceExpression {
+                name: "foo".to_string(),
+                span: Span::default()
+            })
+        );
+    }
+}
@@ -0,0 +1,425 @@
+use crate::lexer::tokens::Literal;
+use crate::parser::expression::Expression;
+use crate::parser::function::Function;
+use crate::parser::program::Program;
+use crate::parser::statement::Statement;
+use log::trace;
+use std::collections::HashSet;
+
+/// Fold constant subexpressions, such as `2 + 3 * 4`, into a single literal.
+///
+/// Operations that would overflow an i32 or divide by zero are left as is. Operations on a `u32`
+/// value are folded as unsigned, so `y / 2 == 0xF0000000 / 2` holds for `@y: u32 = 0xF0000000`.
+///
+/// # Arguments
+/// * `program` - The program to fold constants in.
+pub fn fold_constants(program: &mut Program) {
+    trace!("Folding constants");
+    let global_names: HashSet<String> = program
+        .globals
+        .iter()
+        .filter(|global| global.var_type.as_ref().is_some_and(|t| t.is_unsigned()))
+        .map(|global| global.name.clone())
+        .collect();
+    for function in &mut program.functions {
+        if let Function::RegularFunction {
+            args, statement, ..
+        } = function
+        {
+            let mut unsigned_names = global_names.clone();
+            for arg in args.iter() {
+                if arg.arg_type.is_unsigned() {
+                    unsigned_names.insert(arg.name.clone());
+                } else {
+                    unsigned_names.remove(&arg.name);
+                }
+            }
+            fold_statement(statement, &mut unsigned_names);
+        }
+    }
+}
+
+/// Fold constant subexpressions in every expression of a statement.
+///
+/// # Arguments
+/// * `statement` - The statement to fold.
+/// * `unsigned_names` - The names of the variables in scope that are unsigned.
+fn fold_statement(statement: &mut Statement, unsigned_names: &mut HashSet<String>) {
+    match statement {
+        Statement::CompoundStatement { statements } => {
+            let mut unsigned_names = unsigned_names.clone();
+            for statement in statements {
+                fold_statement(statement, &mut unsigned_names);
+            }
+        }
+        Statement::IfStatement {
+            condition,
+            then_statement,
+            else_statement,
+        } => {
+            fold_expression(condition, false, unsigned_names);
+            fold_statement(then_statement, unsigned_names);
+            if let Some(else_statement) = else_statement {
+                fold_statement(else_statement, unsigned_names);
+            }
+        }
+        Statement::SwitchStatement {
+            value,
+            cases,
+            default,
+        } => {
+            let unsigned = is_unsigned(value, unsigned_names);
+            fold_expression(value, unsigned, unsigned_names);
+            for (_, statement) in cases {
+                fold_statement(statement, unsigned_names);
+            }
+            if let Some(default) = default {
+                fold_statement(default, unsigned_names);
+            }
+        }
+        Statement::DoWhileStatement { body, condition } => {
+            fold_statement(body, unsigned_names);
+            fold_expression(condition, false, unsigned_names);
+        }
+        Statement::ReturnStatement { value, .. } => {
+            if let Some(value) = value {
+                fold_expression(value, false, unsigned_names);
+            }
+        }
+        Statement::VariableDeclarationStatement {
+            name,
+            var_type,
+            value,
+            ..
+        } => {
+            let unsigned = match var_type {
+                Some(var_type) => var_type.is_unsigned(),
+                None => value
+                    .as_ref()
+                    .is_some_and(|value| is_unsigned(value, unsigned_names)),
+            };
+            if let Some(value) = value {
+                fold_expression(value, unsigned, unsigned_names);
+            }
+            if unsigned {
+                unsigned_names.insert(name.clone());
+            } else {
+                unsigned_names.remove(name);
+            }
+        }
+        Statement::TupleDeclarationStatement { names, value, .. } => {
+            fold_expression(value, false, unsigned_names);
+            for name in names {
+                unsigned_names.remove(name);
+            }
+        }
+        Statement::ExpressionStatement { expression } => {
+            fold_expression(expression, false, unsigned_names)
+        }
+        Statement::FunctionDefinitionStatement { .. } | Statement::NoOpStatement => (),
+    }
+}
+
+/// Get the value of an expression if it folds into a constant.
+///
+/// # Arguments
+/// * `expression` - The expression.
+/// * `unsigned` - Whether or not the expression's value is a `u32`.
+pub fn constant_value(expression: &Expression, unsigned: bool) -> Option<i32> {
+    let mut expression = expression.clone();
+    fold_expression(&mut expression, unsigned, &HashSet::new());
+    integer_value(&expression)
+}
+
+/// Fold an expression and its subexpressions.
+///
+/// # Arguments
+/// * `expression` - The expression to fold.
+/// * `unsigned` - Whether or not the expression's value is a `u32`.
+/// * `unsigned_names` - The names of the variables in scope that are unsigned.
+fn fold_expression(expression: &mut Expression, unsigned: bool, unsigned_names: &HashSet<String>) {
+    let folded = match expression {
+        Expression::ParenExpression { expression, .. } => {
+            fold_expression(expression, unsigned, unsigned_names);
+            integer_value(expression)
+        }
+        Expression::BinaryExpression {
+            op,
+            l_expression,
+            r_expression,
+            ..
+        } => {
+            // Both operands of a comparison have the type of the unsigned one, if either is
+            let operand_unsigned = match &op[..] {
+                "=" => is_unsigned(l_expression, unsigned_names),
+                "+" | "-" | "*" | "/" | "**" => unsigned,
+                _ => false,
+            } || is_unsigned(l_expression, unsigned_names)
+                || is_unsigned(r_expression, unsigned_names);
+            fold_expression(l_expression, operand_unsigned, unsigned_names);
+            fold_expression(r_expression, operand_unsigned, unsigned_names);
+            match (integer_value(l_expression), integer_value(r_expression)) {
+                (Some(l), Some(r)) if operand_unsigned => {
+                    fold_unsigned_binary_op(op, l as u32, r as u32)
+                }
+                (Some(l), Some(r)) => fold_binary_op(op, l, r),
+                _ => None,
+            }
+        }
+        Expression::UnaryExpression { op, expression, .. } => {
+            fold_expression(expression, unsigned, unsigned_names);
+            match (&op[..], integer_value(expression)) {
+                ("-", Some(value)) if unsigned => Some(value.wrapping_neg()),
+                ("-", Some(value)) => value.checked_neg(),
+                _ => None,
+            }
+        }
+        Expression::CastExpression {
+            expression,
+            target_type,
+            ..
+        } => {
+            fold_expression(expression, target_type.is_unsigned(), unsigned_names);
+            None
+        }
+        Expression::PostfixExpression { expression, .. }
+        | Expression::FieldAccessExpression { expression, .. } => {
+            fold_expression(expression, false, unsigned_names);
+            None
+        }
+        Expression::FunctionCallExpression { args, .. } => {
+            for arg in args {
+                fold_expression(arg, false, unsigned_names);
+            }
+            None
+        }
+        Expression::ArrayLiteralExpression { elements, .. }
+        | Expression::TupleExpression { elements, .. } => {
+            for element in elements {
+                fold_expression(element, false, unsigned_names);
+            }
+            None
+        }
+        Expression::IndexExpression { array, index, .. } => {
+            fold_expression(array, false, unsigned_names);
+            fold_expression(index, false, unsigned_names);
+            None
+        }
+        Expression::BlockExpression {
+            statements, tail, ..
+        } => {
+            let mut unsigned_names = unsigned_names.clone();
+            for statement in statements {
+                fold_statement(statement, &mut unsigned_names);
+            }
+            fold_expression(tail, unsigned, &unsigned_names);
+            None
+        }
+        Expression::LiteralExpression { .. }
+        | Expression::VariableReferenceExpression { .. }
+        | Expression::SizeOfExpression { .. } => None,
+    };
+
+    if let Some(value) = folded {
+        trace!("Folded expression into {}", value);
+        *expression = Expression::LiteralExpression {
+            value: Literal::Integer(value),
+            span: expression.span(),
+        };
+    }
+}
+
+/// Get the value of an integer or character literal expression.
+///
+/// # Arguments
+/// * `expression` - The expression.
+fn integer_value(expression: &Expression) -> Option<i32> {
+    match expression {
+        Expression::LiteralExpression {
+            value: Literal::Integer(i),
+            ..
+        } => Some(*i),
+        Expression::LiteralExpression {
+            value: Literal::Char(c),
+            ..
+        } => Some(*c as i32),
+        _ => None,
+    }
+}
+
+/// Check if an expression has a `u32` value, such as an unsigned variable or a cast to `u32`.
+///
+/// # Arguments
+/// * `expression` - The expression.
+/// * `unsigned_names` - The names of the variables in scope that are unsigned.
+fn is_unsigned(expression: &Expression, unsigned_names: &HashSet<String>) -> bool {
+    match expression {
+        Expression::VariableReferenceExpression { name, .. } => unsigned_names.contains(name),
+        Expression::CastExpression { target_type, .. } => target_type.is_unsigned(),
+        Expression::BinaryExpression {
+            op,
+            l_expression,
+            r_expression,
+            ..
+        } => match &op[..] {
+            "=" => is_unsigned(l_expression, unsigned_names),
+            "+" | "-" | "*" | "/" | "**" => {
+                is_unsigned(l_expression, unsigned_names)
+                    || is_unsigned(r_expression, unsigned_names)
+            }
+            _ => false,
+        },
+        Expression::ParenExpression { expression, .. }
+        | Expression::UnaryExpression { expression, .. }
+        | Expression::PostfixExpression { expression, .. } => {
+            is_unsigned(expression, unsigned_names)
+        }
+        Expression::IndexExpression { array, .. } => is_unsigned(array, unsigned_names),
+        _ => false,
+    }
+}
+
+/// Evaluate a binary operation on two constants, `None` if it can't be folded.
+///
+/// # Arguments
+/// * `op` - The binary operator.
+/// * `l` - The left operand.
+/// * `r` - The right operand.
+fn fold_binary_op(op: &str, l: i32, r: i32) -> Option<i32> {
+    match op {
+        "+" => l.checked_add(r),
+        "-" => l.checked_sub(r),
+        "*" => l.checked_mul(r),
+        "**" => l.checked_pow(r.max(0) as u32),
+        "/" => l.checked_div(r),
+        "==" => Some((l == r) as i32),
+        "!=" => Some((l != r) as i32),
+        "<" => Some((l < r) as i32),
+        ">" => Some((l > r) as i32),
+        "<=" => Some((l <= r) as i32),
+        ">=" => Some((l >= r) as i32),
+        _ => None,
+    }
+}
+
+/// Evaluate a binary operation on two unsigned constants, `None` if it can't be folded.
+///
+/// The result keeps the bits of the `u32`, the same way a `0xFFFFFFFF` literal does.
+///
+/// # Arguments
+/// * `op` - The binary operator.
+/// * `l` - The left operand.
+/// * `r` - The right operand.
+fn fold_unsigned_binary_op(op: &str, l: u32, r: u32) -> Option<i32> {
+    let value = match op {
+        "+" => l.checked_add(r),
+        "-" => l.checked_sub(r),
+        "*" => l.checked_mul(r),
+        "**" => l.checked_pow(r),
+        "/" => l.checked_div(r),
+        "==" => return Some((l == r) as i32),
+        "!=" => return Some((l != r) as i32),
+        "<" => return Some((l < r) as i32),
+        ">" => return Some((l > r) as i32),
+        "<=" => return Some((l <= r) as i32),
+        ">=" => return Some((l >= r) as i32),
+        _ => None,
+    };
+    value.map(|value| value as i32)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{constant_value, fold_expression};
+    use crate::lexer::tokens::{Literal, Span};
+    use crate::lexer::Lexer;
+    use crate::parser::expression::Expression;
+    use crate::parser::Parser;
+    use std::collections::HashSet;
+
+    fn parse(source: &str) -> Expression {
+        let tokens = Lexer::from_text(source)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        Parser::new(tokens.into_iter().peekable())
+            .parse_expression()
+            .unwrap()
+    }
+
+    fn fold(source: &str) -> Expression {
+        let mut expression = parse(source);
+        fold_expression(&mut expression, false, &HashSet::new());
+        expression
+    }
+
+    fn integer(i: i32) -> Expression {
+        Expression::LiteralExpression {
+            value: Literal::Integer(i),
+            span: Span::default(),
+        }
+    }
+
+    #[test]
+    fn fold_arithmetic() {
+        assert_eq!(fold("2 + 3 * 4"), integer(14));
+        assert_eq!(fold("(2 + 3) * 4"), integer(20));
+        assert_eq!(fold("-(10 - 4) / 2"), integer(-3));
+        assert_eq!(fold("1 + 2 == 3"), integer(1));
+        assert_eq!(fold("2 ** 3 ** 2"), integer(512));
+        assert_eq!(fold("-2 ** 2"), integer(-4));
+    }
+
+    #[test]
+    fn fold_partially() {
+        assert_eq!(
+            fold("x * (2 + 3)"),
+            Expression::BinaryExpression {
+                op: "*".to_string(),
+                l_expression: Box::new(Expression::VariableReferenceExpression {
+                    name: "x".to_string(),
+                    span: Span::default()
+                }),
+                r_expression: Box::new(integer(5)),
+                span: Span::default(),
+            }
+        );
+    }
+
+    #[test]
+    fn no_fold_division_by_zero() {
+        assert_eq!(
+            fold("1 / 0"),
+            Expression::BinaryExpression {
+                op: "/".to_string(),
+                l_expression: Box::new(integer(1)),
+                r_expression: Box::new(integer(0)),
+                span: Span::default(),
+            }
+        );
+    }
+
+    #[test]
+    fn fold_unsigned() {
+        let expression = Expression::BinaryExpression {
+            op: "/".to_string(),
+            l_expression: Box::new(integer(0xF0000000u32 as i32)),
+            r_expression: Box::new(integer(2)),
+            span: Span::default(),
+        };
+        assert_eq!(constant_value(&expression, false), Some(-0x08000000));
+        assert_eq!(constant_value(&expression, true), Some(0x78000000));
+        assert_eq!(fold("0xF0000000 / 2 == 0x78000000"), integer(0));
+
+        // The right side of a comparison with an unsigned variable is folded as unsigned
+        let mut expression = parse("y / 2 == 0xF0000000 / 2");
+        let unsigned_names = vec!["y".to_string()].into_iter().collect();
+        fold_expression(&mut expression, false, &unsigned_names);
+        match expression {
+            Expression::BinaryExpression { r_expression, .. } => {
+                assert_eq!(*r_expression, integer(0x78000000))
+            }
+            _ => panic!("Expected a binary expression"),
+        }
+    }
+}
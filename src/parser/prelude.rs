@@ -0,0 +1,77 @@
+use crate::lexer::Lexer;
+use crate::parser::desugar::referenced_names;
+use crate::parser::function::Function;
+use crate::parser::program::Program;
+use crate::parser::Parser;
+use crate::Result;
+use log::{debug, trace};
+use std::collections::HashSet;
+
+/// The source of the prelude, functions written in yot that every program can call without
+/// defining them.
+pub const PRELUDE: &str = include_str!("prelude.yot");
+
+/// Add the functions of the prelude that a program uses to the start of the program.
+///
+/// Only the prelude functions called or referenced by the program, or by the prelude functions
+/// it uses, are added, so unused ones aren't compiled into every program. A function of the
+/// prelude is left out if the program has a function or global variable with the same name, so
+/// programs can define their own versions.
+///
+/// # Arguments
+/// * `program` - The program to add the prelude to.
+pub fn add_prelude(program: &mut Program) -> Result<()> {
+    trace!("Adding prelude");
+    let tokens = Lexer::from_text(PRELUDE).collect::<Result<Vec<_>>>()?;
+    let prelude = Parser::new(tokens.into_iter().peekable())
+        .parse_definitions_only()
+        .map_err(|e| format!("{} in the prelude", e))?;
+
+    let mut referenced = HashSet::new();
+    for function in &program.functions {
+        referenced.extend(referenced_names(function));
+    }
+    let mut unused = Vec::new();
+    for function in prelude.functions {
+        let name = match &function {
+            Function::RegularFunction { name, .. } => name,
+            Function::ExternalFunction { name, .. } => name,
+        };
+        if program.function(name).is_some() {
+            debug!(
+                "Leaving out `{}` from the prelude, the program defines it",
+                name
+            );
+            continue;
+        }
+        if program.globals.iter().any(|global| global.name == *name) {
+            debug!(
+                "Leaving out `{}` from the prelude, the program has a global variable with its name",
+                name
+            );
+            continue;
+        }
+        unused.push((name.clone(), function));
+    }
+
+    // Prelude functions can use each other, so adding one can make others used
+    let mut used = Vec::new();
+    loop {
+        let (newly_used, still_unused) = unused
+            .into_iter()
+            .partition::<Vec<_>, _>(|(name, _)| referenced.contains(name));
+        unused = still_unused;
+        if newly_used.is_empty() {
+            break;
+        }
+        for (name, function) in newly_used {
+            debug!("Adding `{}` from the prelude", name);
+            referenced.extend(referenced_names(&function));
+            used.push(function);
+        }
+    }
+
+    used.append(&mut program.functions);
+    program.functions = used;
+    Ok(())
+}
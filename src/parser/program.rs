@@ -1,45 +1,505 @@
+use crate::lexer::tokens::Literal;
+use crate::lexer::Lexer;
+use crate::parser::desugar::{hoist_nested_functions, merge_declarations, statement_count};
+use crate::parser::expression::Expression;
 use crate::parser::function::Function;
-use crate::parser::Parser;
+use crate::parser::statement::Statement;
+use crate::parser::types::Type;
+use crate::parser::{Parser, Token};
+use crate::peek_identifier_or_err;
 use crate::Result;
-use log::{trace, warn};
+use log::{debug, info, trace, warn};
+use std::fmt;
+use std::path::Path;
 
 /// A yot program, a.k.a. the root of the abstract syntax tree.
 ///
 /// # Grammar
-/// * Function... + EOF
-#[derive(Debug)]
+/// * (Include | Struct | Function | Global)... + EOF
+#[derive(Debug, Default)]
 pub struct Program {
     /// The list of functions in the program.
     pub functions: Vec<Function>,
+    /// The list of global variables in the program.
+    pub globals: Vec<Global>,
+    /// The list of struct types in the program.
+    pub structs: Vec<Struct>,
+    /// The name of the function the program starts at, `None` if it is `main`.
+    pub entry: Option<String>,
+}
+
+/// A global variable with an optional type and a constant value.
+///
+/// If no type is given, it defaults to `i32`. If no value is given, it starts as 0.
+///
+/// # Grammar
+/// * "@" + Identifier + (":" + Type) + ";"
+/// * "@" + Identifier + (":" + Type) + "=" + Expression + ";"
+#[derive(Debug, Clone)]
+pub struct Global {
+    pub name: String,
+    pub var_type: Option<Type>,
+    pub value: Option<Expression>,
+}
+
+/// A struct type with named fields.
+///
+/// If a field has no type, it defaults to `i32`. A struct is constructed by calling it like a
+/// function with a value for each field, e.g. `Point(1, 2)`.
+///
+/// # Grammar
+/// * "$" + Identifier + "[" + (Identifier + (":" + Type) + ",")... + "]"
+#[derive(Debug, Clone, PartialEq)]
+pub struct Struct {
+    pub name: String,
+    pub fields: Vec<(String, Type)>,
+}
+
+/// A function or global variable declared by a program.
+#[derive(Debug, PartialEq)]
+pub enum Symbol {
+    /// A regular or external function with its number of arguments.
+    Function {
+        name: String,
+        arity: usize,
+        is_extern: bool,
+    },
+    /// A global variable with its type.
+    Global { name: String, var_type: Type },
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Symbol::Function {
+                name,
+                arity,
+                is_extern,
+            } => write!(
+                f,
+                "{:<8}{:<24}{} args",
+                if *is_extern { "extern" } else { "fn" },
+                name,
+                arity
+            ),
+            Symbol::Global { name, var_type } => {
+                write!(f, "{:<8}{:<24}{}", "global", name, var_type)
+            }
+        }
+    }
 }
 
 impl Parser {
     pub fn parse_program(&mut self) -> Result<Program> {
         trace!("Parsing program");
-        let mut functions: Vec<Function> = Vec::new();
+        let mut program = Program::default();
+        if self.entry != "main" {
+            program.entry = Some(self.entry.clone());
+        }
+        self.parse_definitions(&mut program);
+        if let Some((error, _)) = self.errors.first() {
+            return Err(error.clone());
+        }
 
-        loop {
-            if self.tokens.peek().is_none() {
+        // The signature of main is checked along with the types of the program
+        let has_main = program.functions.iter().any(|f| match f {
+            Function::RegularFunction { name, .. } => *name == self.entry,
+            _ => false,
+        });
+        if !has_main {
+            if program.functions.is_empty()
+                && program.globals.is_empty()
+                && program.structs.is_empty()
+            {
+                warn!("The program is empty");
+            } else {
+                warn!("No {} function found", self.entry);
+            }
+        }
+        hoist_nested_functions(&mut program)?;
+        merge_declarations(&mut program)?;
+        Ok(program)
+    }
+
+    /// Parse functions, global variables, and structs without requiring a `main` function, such
+    /// as the definitions entered into the REPL.
+    pub fn parse_definitions_only(&mut self) -> Result<Program> {
+        trace!("Parsing definitions");
+        let mut program = Program::default();
+        self.parse_definitions(&mut program);
+        if let Some((error, _)) = self.errors.first() {
+            return Err(error.clone());
+        }
+        hoist_nested_functions(&mut program)?;
+        merge_declarations(&mut program)?;
+        Ok(program)
+    }
+
+    /// Parse the functions, global variables, and structs of a file, including those of included
+    /// files.
+    ///
+    /// A definition that fails to parse is skipped and its error kept, so every error in the
+    /// program can be reported at once.
+    ///
+    /// # Arguments
+    /// * `program` - The program to add the definitions to.
+    fn parse_definitions(&mut self, program: &mut Program) {
+        while self.tokens.peek().is_some() {
+            let remaining = self.tokens.tokens.len();
+            if let Err(e) = self.parse_definition(program) {
+                debug!("Recovering from parse error: {}", e);
+                self.errors.push((e, self.span()));
+                self.synchronize(remaining);
+            }
+        }
+    }
+
+    /// Parse one function, global variable, struct, or include.
+    ///
+    /// # Arguments
+    /// * `program` - The program to add the definition to.
+    fn parse_definition(&mut self, program: &mut Program) -> Result<()> {
+        if matches!(self.tokens.peek(), Some(Token::Identifier(k)) if k == "include") {
+            self.parse_include(program)?;
+        } else if self.nth_symbol_is(0, "$") {
+            let definition = self.parse_struct()?;
+            if program.structs.iter().any(|s| s.name == definition.name) {
+                return Err(format!("Struct `{}` already exists", definition.name));
+            }
+            program.structs.push(definition);
+        } else if self.next_is_const_global() {
+            return Err("Const declarations are only allowed inside functions".to_string());
+        } else if self.next_is_global() {
+            program.globals.push(self.parse_global()?);
+        } else {
+            program.functions.push(self.parse_function()?);
+        }
+        Ok(())
+    }
+
+    /// Skip the rest of a definition that failed to parse, up to the next `@`, `$`, or `include`
+    /// outside of any braces, which starts the next definition.
+    ///
+    /// # Arguments
+    /// * `remaining` - The number of tokens that were left when the definition started.
+    fn synchronize(&mut self, remaining: usize) {
+        // Something must be skipped, or the same definition would fail again
+        if self.tokens.tokens.len() == remaining {
+            self.tokens.next();
+        }
+        while let Some(token) = self.tokens.peek() {
+            let starts_definition = match token {
+                Token::Symbol(s) => s == "@" || s == "$",
+                Token::Identifier(k) => k == "include",
+                _ => false,
+            };
+            if starts_definition && self.tokens.braces == 0 {
                 break;
             }
-            functions.push(self.parse_function()?);
+            self.tokens.next();
         }
+    }
 
-        let main_fn = functions.iter().any(|f| {
-            if let Function::RegularFunction {
-                name,
-                args: _,
-                statement: _,
-            } = f
-            {
-                name == "main"
-            } else {
-                false
+    /// Parse an include directive and the definitions of the included file.
+    ///
+    /// The path is relative to the including file. A file that was already included is skipped.
+    ///
+    /// # Grammar
+    /// * "include" + StrLiteral + ";"
+    ///
+    /// # Arguments
+    /// * `program` - The program to add the definitions to.
+    fn parse_include(&mut self, program: &mut Program) -> Result<()> {
+        trace!("Parsing include");
+        self.tokens.next(); // Eat include
+        let include = match self.tokens.next() {
+            Some(Token::Literal(Literal::Str(include))) => include,
+            _ => return Err("Expected a file path string after `include`".to_string()),
+        };
+        if !self.next_symbol_is(";") {
+            return Err(format!("Expected `;` after include of `{}`", include));
+        }
+
+        let path = match self.path.as_ref().and_then(|p| p.parent()) {
+            Some(dir) => dir.join(&include),
+            None => Path::new(&include).to_path_buf(),
+        };
+        let canonical = path
+            .canonicalize()
+            .map_err(|e| format!("Unable to include `{}`: {}", path.display(), e))?;
+        if self.include_stack.contains(&canonical) {
+            return Err(format!("Circular include of `{}`", path.display()));
+        }
+        if self.included.contains(&canonical) {
+            info!("Skipping `{}`, which was already included", path.display());
+            return Ok(());
+        }
+
+        let path_str = path.to_str().unwrap_or(&include);
+        let mut lexer = Lexer::from_file(path_str)
+            .map_err(|e| format!("Unable to include `{}`: {}", path_str, e))?;
+        let mut tokens = Vec::new();
+        let mut spans = Vec::new();
+        while let Some(token) = lexer.next() {
+            tokens.push(token.map_err(|e| format!("{} in `{}`", e, path_str))?);
+            spans.push(lexer.span());
+        }
+
+        let mut parser = Parser::with_spans(tokens, spans);
+        parser.path = Some(path.clone());
+        parser.include_stack = self.include_stack.clone();
+        parser.include_stack.push(canonical.clone());
+        parser.included = std::mem::take(&mut self.included);
+        parser.included.push(canonical);
+        parser.max_depth = self.max_depth;
+        parser.entry = self.entry.clone();
+        parser.parse_definitions(program);
+        self.included = parser.included;
+        // The errors are reported at the include, since their positions are in another file
+        let span = self.span();
+        self.errors.extend(
+            parser
+                .errors
+                .into_iter()
+                .map(|(e, _)| (format!("{} in `{}`", e, path_str), span)),
+        );
+        Ok(())
+    }
+
+    /// Check if the next tokens start a global variable rather than a function.
+    fn next_is_global(&self) -> bool {
+        let is_identifier = |n| matches!(self.tokens.peek_nth(n), Some(Token::Identifier(_)));
+        let is_const_function = matches!(self.tokens.peek_nth(1), Some(Token::Identifier(k)) if k == "const")
+            && is_identifier(2);
+        self.nth_symbol_is(0, "@")
+            && is_identifier(1)
+            && !self.next_is_function_header()
+            && !is_const_function
+    }
+
+    /// Check if the next tokens declare a `const` variable rather than a const function, such as
+    /// `@const k = 3;`.
+    fn next_is_const_global(&self) -> bool {
+        self.nth_symbol_is(0, "@")
+            && matches!(self.tokens.peek_nth(1), Some(Token::Identifier(k)) if k == "const")
+            && matches!(self.tokens.peek_nth(2), Some(Token::Identifier(_)))
+            && (self.nth_symbol_is(3, "=")
+                || self.nth_symbol_is(3, ":")
+                || self.nth_symbol_is(3, ";"))
+    }
+
+    fn parse_struct(&mut self) -> Result<Struct> {
+        trace!("Parsing struct");
+        self.tokens.next(); // Eat $
+        let name = peek_identifier_or_err!(self);
+        self.tokens.next();
+        if !self.next_symbol_is("[") {
+            return Err(format!("Expected `[` after struct `{}`", name));
+        }
+
+        let mut fields: Vec<(String, Type)> = Vec::new();
+        if !self.next_symbol_is("]") {
+            loop {
+                let field = peek_identifier_or_err!(self);
+                self.tokens.next();
+                let field_type = if self.next_symbol_is(":") {
+                    self.parse_type()?
+                } else {
+                    Type::I32
+                };
+                if fields.iter().any(|(f, _)| *f == field) {
+                    return Err(format!("Duplicate field `{}` in struct `{}`", field, name));
+                }
+                fields.push((field, field_type));
+
+                match self.tokens.next() {
+                    Some(Token::Symbol(s)) if s == "]" => break,
+                    Some(Token::Symbol(s)) if s == "," => (),
+                    _ => {
+                        return Err(format!(
+                            "Expected `]` or `,` after field in struct `{}`",
+                            name
+                        ))
+                    }
+                }
             }
+        }
+        Ok(Struct { name, fields })
+    }
+
+    fn parse_global(&mut self) -> Result<Global> {
+        trace!("Parsing global");
+        match self.parse_statement()? {
+            Statement::VariableDeclarationStatement {
+                name,
+                var_type,
+                value,
+                is_const: false,
+                ..
+            } => Ok(Global {
+                name,
+                var_type,
+                value: value.map(|value| *value),
+            }),
+            _ => Err("Misidentified global".to_string()),
+        }
+    }
+}
+
+/// Get the symbol of an overload of a function, its name followed by its number of arguments,
+/// such as `foo.2`. Identifiers can't start with a digit, so it can't be the name of another
+/// function, even a nested one renamed to `parent.name`.
+///
+/// # Arguments
+/// * `name` - The name of the function.
+/// * `arity` - The number of arguments of the overload.
+pub fn mangle(name: &str, arity: usize) -> String {
+    format!("{}.{}", name, arity)
+}
+
+impl Program {
+    /// Find a function in the program by name.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the function.
+    pub fn function(&self, name: &str) -> Option<&Function> {
+        self.functions.iter().find(|f| match f {
+            Function::RegularFunction { name: n, .. } => n == name,
+            Function::ExternalFunction { name: n, .. } => n == name,
+        })
+    }
+
+    /// Find the function that a call with a number of arguments calls, which is the overload
+    /// taking that many arguments if the function is overloaded.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the function.
+    /// * `arity` - The number of arguments of the call.
+    pub fn overload(&self, name: &str, arity: usize) -> Option<&Function> {
+        if !self.is_overloaded(name) {
+            return self.function(name);
+        }
+        self.functions.iter().find(|f| match f {
+            Function::RegularFunction { name: n, args, .. } => n == name && args.len() == arity,
+            Function::ExternalFunction { .. } => false,
+        })
+    }
+
+    /// Check if more than one function has a name.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the functions.
+    pub fn is_overloaded(&self, name: &str) -> bool {
+        let mut functions = self.functions.iter().filter(|f| match f {
+            Function::RegularFunction { name: n, .. } => n == name,
+            Function::ExternalFunction { name: n, .. } => n == name,
         });
-        if !main_fn {
-            warn!("No main function found");
+        functions.next().is_some() && functions.next().is_some()
+    }
+
+    /// Get the name of the symbol generated for a function, which is [mangled] if the function is
+    /// overloaded. External functions are never overloaded, so C functions keep their names.
+    ///
+    /// [mangled]: fn.mangle.html
+    ///
+    /// # Arguments
+    /// * `function` - A function of the program.
+    pub fn symbol_name(&self, function: &Function) -> String {
+        match function {
+            Function::RegularFunction { name, args, .. } if self.is_overloaded(name) => {
+                mangle(name, args.len())
+            }
+            Function::RegularFunction { name, .. } => name.clone(),
+            Function::ExternalFunction { name, .. } => name.clone(),
         }
-        Ok(Program { functions })
+    }
+
+    /// Get the name of the function the program starts at.
+    pub fn entry(&self) -> &str {
+        self.entry.as_deref().unwrap_or("main")
+    }
+
+    /// Find a struct in the program by name.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the struct.
+    pub fn structure(&self, name: &str) -> Option<&Struct> {
+        self.structs.iter().find(|s| s.name == name)
+    }
+
+    /// Get the number of functions the program defines, not counting external functions.
+    pub fn function_count(&self) -> usize {
+        self.functions
+            .iter()
+            .filter(|f| matches!(f, Function::RegularFunction { .. }))
+            .count()
+    }
+
+    /// Get the number of statements in the functions of the program.
+    pub fn statement_count(&self) -> usize {
+        self.functions.iter().map(statement_count).sum()
+    }
+
+    /// Get the functions and global variables declared by the program, functions first.
+    pub fn symbols(&self) -> Vec<Symbol> {
+        let functions = self.functions.iter().map(|f| match f {
+            Function::RegularFunction { name, args, .. } => Symbol::Function {
+                name: name.clone(),
+                arity: args.len(),
+                is_extern: false,
+            },
+            Function::ExternalFunction { name, args, .. } => Symbol::Function {
+                name: name.clone(),
+                arity: args.len(),
+                is_extern: true,
+            },
+        });
+        let globals = self.globals.iter().map(|g| Symbol::Global {
+            name: g.name.clone(),
+            var_type: g.var_type.clone().unwrap_or(Type::I32),
+        });
+        functions.chain(globals).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::Symbol;
+    use crate::lexer::Lexer;
+    use crate::parser::types::Type;
+    use crate::parser::Parser;
+
+    #[test]
+    fn symbols() {
+        let tokens = Lexer::from_text("@count: i64 = 1; @!puts[s: str]; @main[] -> 0; @ratio;")
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let program = Parser::new(tokens.into_iter().peekable())
+            .parse_program()
+            .unwrap();
+        assert_eq!(
+            program.symbols(),
+            vec![
+                Symbol::Function {
+                    name: "puts".to_string(),
+                    arity: 1,
+                    is_extern: true,
+                },
+                Symbol::Function {
+                    name: "main".to_string(),
+                    arity: 0,
+                    is_extern: false,
+                },
+                Symbol::Global {
+                    name: "count".to_string(),
+                    var_type: Type::I64,
+                },
+                Symbol::Global {
+                    name: "ratio".to_string(),
+                    var_type: Type::I32,
+                },
+            ]
+        );
     }
 }
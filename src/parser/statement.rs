@@ -1,12 +1,14 @@
-use crate::lexer::tokens::Token;
+use crate::lexer::tokens::{Literal, Span, Token};
 use crate::parser::expression::Expression;
+use crate::parser::function::Function;
+use crate::parser::types::Type;
 use crate::parser::Parser;
 use crate::peek_identifier_or_err;
 use crate::Result;
 use log::{debug, trace};
 
 /// A yot statement.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Statement {
     /// Multiple statements enclosed in braces.
     ///
@@ -25,22 +27,80 @@ pub enum Statement {
         else_statement: Option<Box<Statement>>,
     },
 
+    /// A statement that runs the case matching an integer value, or else the default case.
+    ///
+    /// Only the matching case runs, there is no fallthrough to the cases after it.
+    ///
+    /// # Grammar
+    /// * "%" + "[" + Expression + "]" + "{" + SwitchCase... + "}"
+    ///
+    /// SwitchCase:
+    /// * ("-") + IntLiteral + ":" + Statement
+    /// * "_" + ":" + Statement
+    SwitchStatement {
+        value: Box<Expression>,
+        cases: Vec<(i32, Statement)>,
+        default: Option<Box<Statement>>,
+    },
+
+    /// A loop that runs its body, then runs it again while the condition is nonzero.
+    ///
+    /// The body always runs at least once, since the condition is only checked after it.
+    ///
+    /// # Grammar
+    /// * CompoundStatement + "~" + "[" + Expression + "]" + ";"
+    DoWhileStatement {
+        body: Box<Statement>,
+        condition: Box<Expression>,
+    },
+
     /// A return statement.
     ///
     /// # Grammar
-    /// * "->" + Expression + ";"
-    ReturnStatement { value: Box<Expression> },
+    /// * "->" + (Expression) + ";"
+    ReturnStatement {
+        value: Option<Box<Expression>>,
+        /// The position of the `->`, the default span if unknown.
+        span: Span,
+    },
 
-    /// A variable declaration with an optional value.
+    /// A variable declaration with an optional type and value.
+    ///
+    /// If no type is given, it is inferred from the value or defaults to `i32`. A `const` variable
+    /// must have a value and can't be assigned to.
     ///
     /// # Grammar
-    /// * "@" + Identifier + ";"
-    /// * "@" + Identifier + "=" + Expression + ";"
+    /// * "@" + Identifier + (":" + Type) + ";"
+    /// * "@" + ("const") + Identifier + (":" + Type) + "=" + Expression + ";"
     VariableDeclarationStatement {
         name: String,
+        /// The position of the `@` the variable is declared with, the default span if unknown.
+        span: Span,
+        is_const: bool,
+        var_type: Option<Type>,
         value: Option<Box<Expression>>,
     },
 
+    /// A declaration of a variable for each value of a tuple.
+    ///
+    /// # Grammar
+    /// * "@" + Identifier + ("," + "@" + Identifier)... + "=" + Expression + ";"
+    TupleDeclarationStatement {
+        names: Vec<String>,
+        /// The position of the first `@` of the declaration, the default span if unknown.
+        span: Span,
+        value: Box<Expression>,
+    },
+
+    /// A function defined inside another function, which is moved to the top level of the
+    /// program by [`hoist_nested_functions`].
+    ///
+    /// [`hoist_nested_functions`]: ../desugar/fn.hoist_nested_functions.html
+    ///
+    /// # Grammar
+    /// * Function
+    FunctionDefinitionStatement { function: Box<Function> },
+
     /// An expression ending with a semicolon.
     ///
     /// # Grammar
@@ -57,14 +117,29 @@ pub enum Statement {
 impl Parser {
     pub fn parse_statement(&mut self) -> Result<Statement> {
         trace!("Parsing statement");
-        match self.tokens.peek() {
-            Some(Token::Symbol(s)) if s == "{" => self.parse_compound_statement(),
-            Some(Token::Symbol(s)) if s == "?" => self.parse_if_statement(),
-            Some(Token::Symbol(s)) if s == "->" => self.parse_return_statement(),
-            Some(Token::Symbol(s)) if s == "@" => self.parse_variable_declaration_statement(),
-            Some(Token::Symbol(s)) if s == ";" => self.parse_no_op_statement(),
-            _ => self.parse_expression_statement(),
-        }
+        self.nested(|parser| match parser.tokens.peek() {
+            Some(Token::Symbol(s)) if s == "{" => {
+                let statement = parser.parse_compound_statement()?;
+                if parser.next_symbol_is("~") {
+                    parser.parse_do_while_statement(statement)
+                } else {
+                    Ok(statement)
+                }
+            }
+            Some(Token::Symbol(s)) if s == "?" => parser.parse_if_statement(),
+            Some(Token::Symbol(s)) if s == "%" => parser.parse_switch_statement(),
+            Some(Token::Symbol(s)) if s == "->" => parser.parse_return_statement(),
+            Some(Token::Symbol(s)) if s == "@" && parser.next_is_nested_function() => {
+                parser.parse_function_definition_statement()
+            }
+            Some(Token::Symbol(s)) if s == "@" => parser.parse_variable_declaration_statement(),
+            Some(Token::Symbol(s)) if s == ";" => parser.parse_no_op_statement(),
+            // An else is only parsed right after the statement of an if
+            Some(Token::Symbol(s)) if s == ":" => {
+                Err("Unexpected `:`, else without a matching if".to_string())
+            }
+            _ => parser.parse_expression_statement(),
+        })
     }
 
     fn parse_compound_statement(&mut self) -> Result<Statement> {
@@ -104,23 +179,128 @@ impl Parser {
         })
     }
 
+    fn parse_switch_statement(&mut self) -> Result<Statement> {
+        trace!("Parsing switch statement");
+        self.tokens.next(); // Eat %
+        if !self.next_symbol_is("[") {
+            return Err("Expected `[` after `%` in switch statement".to_string());
+        }
+
+        let value = Box::new(self.parse_expression()?);
+        if !self.next_symbol_is("]") {
+            return Err("Expected `]` after value in switch statement".to_string());
+        }
+        if !self.next_symbol_is("{") {
+            return Err("Expected `{` before cases in switch statement".to_string());
+        }
+
+        let mut cases: Vec<(i32, Statement)> = Vec::new();
+        let mut default = None;
+        while !self.next_symbol_is("}") {
+            let case = match self.tokens.next() {
+                Some(Token::Identifier(name)) if name == "_" => None,
+                Some(Token::Literal(Literal::Integer(case))) => Some(case),
+                Some(Token::Symbol(s)) if s == "-" => match self.tokens.next() {
+                    Some(Token::Literal(Literal::Integer(case))) => Some(case.wrapping_neg()),
+                    _ => return Err("Expected an integer after `-` in switch case".to_string()),
+                },
+                _ => return Err("Expected an integer or `_` in switch case".to_string()),
+            };
+            if !self.next_symbol_is(":") {
+                return Err("Expected `:` after switch case".to_string());
+            }
+            let statement = self.parse_statement()?;
+
+            match case {
+                Some(case) if cases.iter().any(|(c, _)| *c == case) => {
+                    return Err(format!("Duplicate case `{}` in switch statement", case))
+                }
+                Some(case) => cases.push((case, statement)),
+                None if default.is_some() => {
+                    return Err("Duplicate default case in switch statement".to_string())
+                }
+                None => default = Some(Box::new(statement)),
+            }
+        }
+
+        Ok(Statement::SwitchStatement {
+            value,
+            cases,
+            default,
+        })
+    }
+
+    /// Parse the condition of a do-while loop after its body and `~`.
+    ///
+    /// # Arguments
+    /// * `body` - The body of the loop.
+    fn parse_do_while_statement(&mut self, body: Statement) -> Result<Statement> {
+        trace!("Parsing do-while statement");
+        if !self.next_symbol_is("[") {
+            return Err("Expected `[` after `~` in do-while statement".to_string());
+        }
+        let condition = Box::new(self.parse_expression()?);
+        if !self.next_symbol_is("]") {
+            return Err("Expected `]` after condition in do-while statement".to_string());
+        }
+        if !self.next_symbol_is(";") {
+            return Err("Expected `;` after do-while statement".to_string());
+        }
+        Ok(Statement::DoWhileStatement {
+            body: Box::new(body),
+            condition,
+        })
+    }
+
     fn parse_return_statement(&mut self) -> Result<Statement> {
         trace!("Parsing return statement");
+        let span = self.tokens.span();
         self.tokens.next(); // Eat ->
-        let value = Box::new(self.parse_expression()?);
+        if self.next_symbol_is(";") {
+            trace!("Found bare return");
+            return Ok(Statement::ReturnStatement { value: None, span });
+        }
+        let value = Some(Box::new(self.parse_expression()?));
 
         if !self.next_symbol_is(";") {
             return Err("Expected `;` after return statement".to_string());
         }
 
-        Ok(Statement::ReturnStatement { value })
+        Ok(Statement::ReturnStatement { value, span })
+    }
+
+    /// Check if the next tokens start a function definition rather than a variable declaration.
+    fn next_is_nested_function(&self) -> bool {
+        self.next_is_function_header()
+    }
+
+    fn parse_function_definition_statement(&mut self) -> Result<Statement> {
+        trace!("Parsing function definition statement");
+        Ok(Statement::FunctionDefinitionStatement {
+            function: Box::new(self.parse_function()?),
+        })
     }
 
     fn parse_variable_declaration_statement(&mut self) -> Result<Statement> {
         trace!("Parsing variable declaration statement");
+        let span = self.tokens.span();
         self.tokens.next(); // Eat @
+        let is_const = matches!(self.tokens.peek(), Some(Token::Identifier(k)) if k == "const")
+            && matches!(self.tokens.peek_nth(1), Some(Token::Identifier(_)));
+        if is_const {
+            self.tokens.next(); // Eat const
+        }
         let name = peek_identifier_or_err!(self);
         self.tokens.next();
+        if !is_const && self.next_symbol_is(",") {
+            return self.parse_tuple_declaration_statement(name, span);
+        }
+
+        let var_type = if self.next_symbol_is(":") {
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
 
         let value = if self.next_symbol_is("=") {
             trace!("Found expression after");
@@ -133,7 +313,49 @@ impl Parser {
         if !self.next_symbol_is(";") {
             return Err("Expected `;` after variable declaration statement".to_string());
         }
-        Ok(Statement::VariableDeclarationStatement { name, value })
+        if is_const && value.is_none() {
+            return Err(format!("Const variable `{}` must have a value", name));
+        }
+        Ok(Statement::VariableDeclarationStatement {
+            name,
+            span,
+            is_const,
+            var_type,
+            value,
+        })
+    }
+
+    /// Parse the rest of a tuple declaration after its first name and `,`.
+    ///
+    /// # Arguments
+    /// * `first_name` - The name of the variable for the first value.
+    /// * `span` - The position of the first `@` of the declaration.
+    fn parse_tuple_declaration_statement(
+        &mut self,
+        first_name: String,
+        span: Span,
+    ) -> Result<Statement> {
+        trace!("Parsing tuple declaration statement");
+        let mut names = vec![first_name];
+        loop {
+            if !self.next_symbol_is("@") {
+                return Err("Expected `@` before variable in tuple declaration".to_string());
+            }
+            names.push(peek_identifier_or_err!(self));
+            self.tokens.next();
+            if !self.next_symbol_is(",") {
+                break;
+            }
+        }
+
+        if !self.next_symbol_is("=") {
+            return Err("Expected `=` after variables in tuple declaration".to_string());
+        }
+        let value = Box::new(self.parse_expression()?);
+        if !self.next_symbol_is(";") {
+            return Err("Expected `;` after tuple declaration statement".to_string());
+        }
+        Ok(Statement::TupleDeclarationStatement { names, span, value })
     }
 
     fn parse_expression_statement(&mut self) -> Result<Statement> {
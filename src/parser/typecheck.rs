@@ -0,0 +1,950 @@
+use crate::builtins::BUILTIN_FUNCTIONS;
+use crate::lexer::tokens::{Literal, Span};
+use crate::parser::expression::Expression;
+use crate::parser::function::{Argument, Function};
+use crate::parser::program::{Program, Struct};
+use crate::parser::statement::Statement;
+use crate::parser::types::Type;
+use crate::Result;
+use log::{trace, warn};
+use std::collections::{BTreeMap, HashMap};
+
+/// The type of a value, `None` if it isn't known before code generation.
+type ValueType = Option<Type>;
+
+/// Checks the types of a program before code generation.
+struct TypeChecker<'a> {
+    /// The program being checked.
+    program: &'a Program,
+    /// The types of the global variables.
+    globals: HashMap<&'a str, ValueType>,
+    /// The local variables, innermost scope last, ordered by name so warnings about them are
+    /// printed in a consistent order.
+    scopes: Vec<BTreeMap<String, Local>>,
+    /// The local variables of the function being checked that were never referenced, and the
+    /// lines they were declared on.
+    unused: Vec<(String, usize)>,
+    /// The name and return type of the function being checked.
+    function: (&'a str, Option<&'a Type>),
+    /// The position of the innermost expression or statement an error was found in, if it is
+    /// known.
+    error_span: Option<Span>,
+}
+
+/// A local variable in scope.
+struct Local {
+    /// The type of the variable.
+    var_type: ValueType,
+    /// Whether or not the variable has been referenced.
+    used: bool,
+    /// The line the variable is declared on, 0 if unknown.
+    line: usize,
+}
+
+/// Check that variables and functions exist and are used with the right types.
+///
+/// Integers of different widths are compatible with each other, as are strings and `[i8]`
+/// arrays. Types that can only be known during code generation are left unchecked.
+///
+/// Local variables that are never referenced are warned about.
+///
+/// On failure, the error is returned with the position of the expression or statement it was
+/// found in, the default span if that isn't known.
+///
+/// # Arguments
+/// * `program` - The program to check.
+pub fn typecheck(program: &Program) -> std::result::Result<(), (String, Span)> {
+    typecheck_after(program, 0)
+}
+
+/// Check a program whose first functions were already checked, only warning about the functions
+/// after them.
+///
+/// # Arguments
+/// * `program` - The program to check.
+/// * `checked_functions` - The number of functions at the start that were already checked.
+pub fn typecheck_after(
+    program: &Program,
+    checked_functions: usize,
+) -> std::result::Result<(), (String, Span)> {
+    trace!("Type checking program");
+    let mut checker = TypeChecker {
+        program,
+        globals: HashMap::new(),
+        scopes: Vec::new(),
+        unused: Vec::new(),
+        function: ("", None),
+        error_span: None,
+    };
+    checker
+        .check_program(checked_functions)
+        .map_err(|e| (e, checker.error_span.unwrap_or_default()))
+}
+
+/// Check that overloaded functions each take a different number of arguments, and that external
+/// functions and the entry function aren't overloaded.
+///
+/// # Arguments
+/// * `program` - The program to check.
+fn check_overloads(program: &Program) -> Result<()> {
+    for (i, function) in program.functions.iter().enumerate() {
+        let (name, arity) = match function {
+            Function::RegularFunction { name, args, .. } => (name, args.len()),
+            Function::ExternalFunction { name, .. } => {
+                if program.is_overloaded(name) {
+                    return Err(format!("External function `{}` can't be overloaded", name));
+                }
+                continue;
+            }
+        };
+        if !program.is_overloaded(name) {
+            continue;
+        }
+        if name == program.entry() {
+            return Err(format!("The entry function `{}` can't be overloaded", name));
+        }
+        let is_duplicate = program.functions[i + 1..].iter().any(|f| match f {
+            Function::RegularFunction { name: n, args, .. } => n == name && args.len() == arity,
+            Function::ExternalFunction { .. } => false,
+        });
+        // Calls are resolved by their number of arguments, not the types of the arguments
+        if is_duplicate {
+            return Err(format!(
+                "Function `{}` is defined more than once with {} argument{}, overloads must have \
+                 different numbers of arguments",
+                name,
+                arity,
+                if arity == 1 { "" } else { "s" }
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Check that `main` can be called as the entry point of a program, with no arguments or with
+/// `argc: i32, argv: [str]`, and that it returns an i32 exit code.
+///
+/// The type of `argv` can be left out, since it is given when `main` is parsed.
+///
+/// # Arguments
+/// * `name` - The name of the entry function, usually `main`.
+/// * `args` - The arguments of the entry function.
+/// * `return_type` - The return type of the entry function, `None` if it is void.
+fn check_main_signature(name: &str, args: &[Argument], return_type: Option<&Type>) -> Result<()> {
+    let arg_types = args.iter().map(|arg| &arg.arg_type).collect::<Vec<_>>();
+    let argv = Type::Array(Box::new(Type::Str));
+    if !arg_types.is_empty() && arg_types != [&Type::I32, &argv] {
+        let args = args
+            .iter()
+            .map(|arg| format!("{}: {}", arg.name, arg.arg_type))
+            .collect::<Vec<_>>();
+        return Err(format!(
+            "`{}` must have no arguments or `[argc, argv]`, not `[{}]`",
+            name,
+            args.join(", ")
+        ));
+    }
+    match return_type {
+        Some(Type::I32) => Ok(()),
+        Some(t) => Err(format!(
+            "`{}` must return an i32 exit code, not {}",
+            name, t
+        )),
+        None => Err(format!("`{}` must return an i32 exit code, not void", name)),
+    }
+}
+
+/// Check if a value of one type can be used as another.
+///
+/// # Arguments
+/// * `expected` - The type that is expected.
+/// * `actual` - The type of the value.
+fn compatible(expected: &Type, actual: &Type) -> bool {
+    match (expected, actual) {
+        (Type::Array(e), Type::Array(a)) => compatible(e, a),
+        (Type::Tuple(e), Type::Tuple(a)) => {
+            e.len() == a.len() && e.iter().zip(a).all(|(e, a)| compatible(e, a))
+        }
+        (Type::Array(e), Type::Str) | (Type::Str, Type::Array(e)) => is_integer(e),
+        (e, a) => e == a || (is_integer(e) && is_integer(a)),
+    }
+}
+
+/// Check if a type is an integer.
+///
+/// # Arguments
+/// * `t` - The type.
+fn is_integer(t: &Type) -> bool {
+    matches!(t, Type::I8 | Type::I16 | Type::I32 | Type::I64 | Type::U32)
+}
+
+/// Check if a type is a number.
+///
+/// # Arguments
+/// * `t` - The type.
+fn is_number(t: &Type) -> bool {
+    is_integer(t) || *t == Type::F64
+}
+
+impl<'a> TypeChecker<'a> {
+    /// Check the structs, globals, and functions of the program.
+    ///
+    /// # Arguments
+    /// * `checked_functions` - The number of functions at the start that were already checked.
+    fn check_program(&mut self, checked_functions: usize) -> Result<()> {
+        let program = self.program;
+        for definition in &program.structs {
+            self.check_struct(definition)
+                .map_err(|e| format!("{} in struct `{}`", e, definition.name))?;
+        }
+
+        for global in &program.globals {
+            if let Some(var_type) = &global.var_type {
+                self.check_type(var_type)
+                    .map_err(|e| format!("{} in global `{}`", e, global.name))?;
+            }
+            let value_type = match &global.value {
+                Some(value) => self
+                    .expression_type(value)
+                    .map_err(|e| format!("{} in global `{}`", e, global.name))?,
+                None => None,
+            };
+            let var_type = self
+                .declared_type(&global.name, global.var_type.as_ref(), value_type)
+                .map_err(|e| format!("{} in global `{}`", e, global.name))?;
+            self.globals.insert(&global.name, var_type);
+        }
+
+        check_overloads(program)?;
+        for (i, function) in program.functions.iter().enumerate() {
+            if let Function::RegularFunction {
+                name,
+                line,
+                args,
+                return_type,
+                statement,
+                ..
+            } = function
+            {
+                self.function = (name, return_type.as_ref());
+                // Arguments can be unused, such as those of callbacks
+                self.scopes = vec![args
+                    .iter()
+                    .filter(|arg| arg.name != "_")
+                    .map(|arg| {
+                        let local = Local {
+                            var_type: Some(arg.arg_type.clone()),
+                            used: true,
+                            line: *line,
+                        };
+                        (arg.name.clone(), local)
+                    })
+                    .collect()];
+                let signature = if name == program.entry() {
+                    check_main_signature(name, args, return_type.as_ref())
+                } else {
+                    self.check_signature(args, return_type.as_ref())
+                };
+                signature
+                    .and_then(|_| self.check_statement(statement))
+                    .map_err(|e| {
+                        // The line of the function is used when the error's position isn't known
+                        let line = self.error_span.map_or(*line, |span| span.line);
+                        if line > 0 {
+                            format!("{} in function `{}` at line {}", e, name, line)
+                        } else {
+                            format!("{} in function `{}`", e, name)
+                        }
+                    })?;
+                let unused = std::mem::take(&mut self.unused);
+                if i < checked_functions {
+                    continue;
+                }
+                for (variable, declared_line) in unused {
+                    // Variables of programs parsed without spans fall back to the function's line
+                    let line = if declared_line > 0 {
+                        declared_line
+                    } else {
+                        *line
+                    };
+                    if line > 0 {
+                        warn!(
+                            "Unused variable `{}` in function `{}` at line {}",
+                            variable, name, line
+                        );
+                    } else {
+                        warn!("Unused variable `{}` in function `{}`", variable, name);
+                    }
+                }
+            } else if let Function::ExternalFunction {
+                name,
+                args,
+                return_type,
+                ..
+            } = function
+            {
+                self.check_signature(args, return_type.as_ref())
+                    .map_err(|e| format!("{} in function `{}`", e, name))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that a type only names structs that exist.
+    ///
+    /// # Arguments
+    /// * `t` - The type.
+    fn check_type(&self, t: &Type) -> Result<()> {
+        match t {
+            Type::Array(element_type) | Type::Pointer(element_type) => {
+                self.check_type(element_type)
+            }
+            Type::Tuple(element_types) => element_types.iter().try_for_each(|t| self.check_type(t)),
+            Type::Struct(name) if self.program.structure(name).is_none() => {
+                Err(format!("Unknown type `{}`", name))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Check the argument and return types of a function.
+    ///
+    /// # Arguments
+    /// * `args` - The arguments of the function.
+    /// * `return_type` - The return type of the function, `None` if it is void.
+    fn check_signature(&self, args: &[Argument], return_type: Option<&Type>) -> Result<()> {
+        for arg in args {
+            self.check_type(&arg.arg_type)?;
+        }
+        return_type.map_or(Ok(()), |t| self.check_type(t))
+    }
+
+    /// Check that the fields of a struct have known types and that the struct doesn't contain
+    /// itself, which would make its size infinite.
+    ///
+    /// # Arguments
+    /// * `definition` - The struct.
+    fn check_struct(&self, definition: &Struct) -> Result<()> {
+        if self.program.function(&definition.name).is_some() {
+            return Err("A struct can't have the same name as a function".to_string());
+        }
+        let mut unchecked = vec![definition];
+        let mut checked = Vec::new();
+        while let Some(s) = unchecked.pop() {
+            for (field, field_type) in &s.fields {
+                self.check_type(field_type)
+                    .map_err(|e| format!("{} of field `{}`", e, field))?;
+                // Arrays are references, so only fields holding a struct directly are followed
+                let mut contained = vec![field_type];
+                while let Some(t) = contained.pop() {
+                    match t {
+                        Type::Tuple(element_types) => contained.extend(element_types),
+                        Type::Struct(name) if *name == definition.name => {
+                            return Err(format!(
+                                "Field `{}` makes the struct contain itself",
+                                field
+                            ))
+                        }
+                        Type::Struct(name) if !checked.contains(&name) => {
+                            checked.push(name);
+                            unchecked.extend(self.program.structure(name));
+                        }
+                        _ => (),
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the type of a variable, `Err` if it doesn't exist.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the variable.
+    fn variable_type(&mut self, name: &str) -> Result<ValueType> {
+        if let Some(local) = self
+            .scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.get_mut(name))
+        {
+            local.used = true;
+            return Ok(local.var_type.clone());
+        }
+        if let Some(var_type) = self.globals.get(name) {
+            return Ok(var_type.clone());
+        }
+        // Functions can be referenced as callbacks
+        if self.program.is_overloaded(name) {
+            return Err(format!(
+                "Overloaded function `{}` can't be referenced without calling it",
+                name
+            ));
+        }
+        match self.program.function(name) {
+            Some(_) => Ok(Some(Type::Function)),
+            None => Err(format!("Undefined variable `{}`", name)),
+        }
+    }
+
+    /// Add a local variable to the innermost scope, unless it is the `_` placeholder.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the variable.
+    /// * `var_type` - The type of the variable.
+    /// * `line` - The line the variable is declared on.
+    fn declare(&mut self, name: &str, var_type: ValueType, line: usize) {
+        if name == "_" {
+            return;
+        }
+        let local = Local {
+            var_type,
+            used: false,
+            line,
+        };
+        // A variable declared again in the same scope replaces the first one
+        if let Some(replaced) = self
+            .scopes
+            .last_mut()
+            .unwrap()
+            .insert(name.to_string(), local)
+        {
+            if !replaced.used {
+                self.unused.push((name.to_string(), replaced.line));
+            }
+        }
+    }
+
+    /// Leave the innermost scope, keeping the variables in it that were never referenced.
+    fn pop_scope(&mut self) {
+        let scope = self.scopes.pop().unwrap();
+        self.unused.extend(
+            scope
+                .into_iter()
+                .filter(|(_, local)| !local.used)
+                .map(|(name, local)| (name, local.line)),
+        );
+    }
+
+    /// Check if a local or global variable exists.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the variable.
+    fn is_variable(&self, name: &str) -> bool {
+        self.scopes.iter().any(|scope| scope.contains_key(name)) || self.globals.contains_key(name)
+    }
+
+    /// Get the type of a declared variable, checking that its value can be assigned to it.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the variable.
+    /// * `var_type` - The declared type of the variable, if any.
+    /// * `value_type` - The type of the value of the variable, if any.
+    fn declared_type(
+        &self,
+        name: &str,
+        var_type: Option<&Type>,
+        value_type: ValueType,
+    ) -> Result<ValueType> {
+        match (var_type, value_type) {
+            (Some(var_type), Some(value_type)) if !compatible(var_type, &value_type) => {
+                Err(format!(
+                    "Can't assign {} to `{}` of type {}",
+                    value_type, name, var_type
+                ))
+            }
+            (Some(var_type), _) => Ok(Some(var_type.clone())),
+            (None, value_type) => Ok(value_type),
+        }
+    }
+
+    /// Check a statement, recording its position if an error is found in it.
+    ///
+    /// # Arguments
+    /// * `statement` - The statement.
+    fn check_statement(&mut self, statement: &Statement) -> Result<()> {
+        let result = self.check_statement_inner(statement);
+        if let (
+            Err(_),
+            Statement::ReturnStatement { span, .. }
+            | Statement::VariableDeclarationStatement { span, .. }
+            | Statement::TupleDeclarationStatement { span, .. },
+        ) = (&result, statement)
+        {
+            self.record_error_span(*span);
+        }
+        result
+    }
+
+    fn check_statement_inner(&mut self, statement: &Statement) -> Result<()> {
+        match statement {
+            Statement::CompoundStatement { statements } => {
+                self.scopes.push(BTreeMap::new());
+                for statement in statements {
+                    self.check_statement(statement)?;
+                }
+                self.pop_scope();
+                Ok(())
+            }
+            Statement::IfStatement {
+                condition,
+                then_statement,
+                else_statement,
+            } => {
+                if let Some(condition_type) = self.expression_type(condition)? {
+                    if !is_integer(&condition_type) {
+                        self.record_error_span(condition.span());
+                        return Err(format!(
+                            "If statement condition must be an integer, not {}",
+                            condition_type
+                        ));
+                    }
+                }
+                self.check_statement(then_statement)?;
+                if let Some(else_statement) = else_statement {
+                    self.check_statement(else_statement)?;
+                }
+                Ok(())
+            }
+            Statement::DoWhileStatement { body, condition } => {
+                self.check_statement(body)?;
+                if let Some(condition_type) = self.expression_type(condition)? {
+                    if !is_integer(&condition_type) {
+                        self.record_error_span(condition.span());
+                        return Err(format!(
+                            "Do-while statement condition must be an integer, not {}",
+                            condition_type
+                        ));
+                    }
+                }
+                Ok(())
+            }
+            Statement::SwitchStatement {
+                value,
+                cases,
+                default,
+            } => {
+                if let Some(value_type) = self.expression_type(value)? {
+                    if !is_integer(&value_type) {
+                        self.record_error_span(value.span());
+                        return Err(format!(
+                            "Switch statement value must be an integer, not {}",
+                            value_type
+                        ));
+                    }
+                }
+                for (_, statement) in cases {
+                    self.check_statement(statement)?;
+                }
+                if let Some(default) = default {
+                    self.check_statement(default)?;
+                }
+                Ok(())
+            }
+            Statement::ReturnStatement { value, .. } => {
+                let (name, return_type) = self.function;
+                match (return_type, value) {
+                    (None, Some(_)) => {
+                        Err(format!("Void function `{}` can't return a value", name))
+                    }
+                    (Some(_), None) => Err(format!("Function `{}` must return a value", name)),
+                    (Some(return_type), Some(value)) => match self.expression_type(value)? {
+                        Some(value_type) if !compatible(return_type, &value_type) => Err(format!(
+                            "Function `{}` returns {}, not {}",
+                            name, return_type, value_type
+                        )),
+                        _ => Ok(()),
+                    },
+                    (None, None) => Ok(()),
+                }
+            }
+            Statement::VariableDeclarationStatement {
+                name,
+                span,
+                var_type,
+                value,
+                ..
+            } => {
+                if let Some(var_type) = var_type {
+                    self.check_type(var_type)?;
+                }
+                let value_type = match value {
+                    Some(value) => self.expression_type(value)?,
+                    None => None,
+                };
+                let var_type = match (var_type, value) {
+                    (None, None) => Some(Type::I32),
+                    _ => self.declared_type(name, var_type.as_ref(), value_type)?,
+                };
+                self.declare(name, var_type, span.line);
+                Ok(())
+            }
+            Statement::TupleDeclarationStatement {
+                names, span, value, ..
+            } => {
+                let element_types = match self.expression_type(value)? {
+                    Some(Type::Tuple(element_types)) if element_types.len() == names.len() => {
+                        element_types.into_iter().map(Some).collect()
+                    }
+                    Some(t) => {
+                        return Err(format!(
+                            "Expected a tuple of {} values, got {}",
+                            names.len(),
+                            t
+                        ))
+                    }
+                    None => vec![None; names.len()],
+                };
+                for (name, element_type) in names.iter().zip(element_types) {
+                    self.declare(name, element_type, span.line);
+                }
+                Ok(())
+            }
+            Statement::ExpressionStatement { expression } => {
+                self.expression_type(expression)?;
+                Ok(())
+            }
+            Statement::FunctionDefinitionStatement { .. } | Statement::NoOpStatement => Ok(()),
+        }
+    }
+
+    /// Get the type of an expression after checking it, recording its position if an error is
+    /// found in it.
+    ///
+    /// # Arguments
+    /// * `expression` - The expression.
+    fn expression_type(&mut self, expression: &Expression) -> Result<ValueType> {
+        let result = self.expression_type_inner(expression);
+        if result.is_err() {
+            self.record_error_span(expression.span());
+        }
+        result
+    }
+
+    /// Record the position of an error, unless the position of a more specific part of the
+    /// program was already recorded or the position is unknown.
+    ///
+    /// # Arguments
+    /// * `span` - The position of the expression or statement the error was found in.
+    fn record_error_span(&mut self, span: Span) {
+        if self.error_span.is_none() && span.line > 0 {
+            self.error_span = Some(span);
+        }
+    }
+
+    fn expression_type_inner(&mut self, expression: &Expression) -> Result<ValueType> {
+        match expression {
+            Expression::LiteralExpression { value, .. } => Ok(Some(match value {
+                Literal::Integer(_) | Literal::Char(_) => Type::I32,
+                Literal::SizedInteger { bits, .. } => match bits {
+                    8 => Type::I8,
+                    16 => Type::I16,
+                    64 => Type::I64,
+                    _ => Type::I32,
+                },
+                Literal::Str(_) => Type::Str,
+            })),
+            Expression::ParenExpression { expression, .. } => self.expression_type(expression),
+            Expression::SizeOfExpression { target_type, .. } => {
+                self.check_type(target_type)?;
+                Ok(Some(Type::I64))
+            }
+            Expression::VariableReferenceExpression { name, .. } => self.variable_type(name),
+            Expression::ArrayLiteralExpression { elements, .. } => {
+                let mut element_types = Vec::new();
+                for element in elements {
+                    element_types.push(self.expression_type(element)?);
+                }
+                if let Some(Some(first)) = element_types.first() {
+                    for element_type in element_types.iter().skip(1).flatten() {
+                        if !compatible(first, element_type) {
+                            return Err(format!(
+                                "Can't put {} in an array of {}",
+                                element_type, first
+                            ));
+                        }
+                    }
+                }
+                match element_types.first() {
+                    Some(Some(element_type)) => {
+                        Ok(Some(Type::Array(Box::new(element_type.clone()))))
+                    }
+                    Some(None) => Ok(None),
+                    None => Ok(Some(Type::Array(Box::new(Type::I32)))),
+                }
+            }
+            Expression::TupleExpression { elements, .. } => {
+                let mut element_types = Vec::new();
+                for element in elements {
+                    element_types.push(self.expression_type(element)?);
+                }
+                Ok(element_types
+                    .into_iter()
+                    .collect::<Option<Vec<_>>>()
+                    .map(Type::Tuple))
+            }
+            Expression::IndexExpression { array, index, .. } => self.element_type(array, index),
+            Expression::FieldAccessExpression {
+                expression, field, ..
+            } => self.field_type(expression, field),
+            Expression::FunctionCallExpression { name, args, .. } => self.call_type(name, args),
+            Expression::BlockExpression {
+                statements, tail, ..
+            } => {
+                self.scopes.push(BTreeMap::new());
+                for statement in statements {
+                    self.check_statement(statement)?;
+                }
+                let tail_type = self.expression_type(tail)?;
+                self.pop_scope();
+                Ok(tail_type)
+            }
+            Expression::BinaryExpression {
+                op,
+                l_expression,
+                r_expression,
+                ..
+            } if op == "=" => {
+                let var_type = self.assignable_type(l_expression, op)?;
+                let value_type = self.expression_type(r_expression)?;
+                match (var_type, value_type) {
+                    (Some(var_type), Some(value_type)) if !compatible(&var_type, &value_type) => {
+                        Err(format!("Can't assign {} to {}", value_type, var_type))
+                    }
+                    (var_type, _) => Ok(var_type),
+                }
+            }
+            Expression::BinaryExpression {
+                op,
+                l_expression,
+                r_expression,
+                ..
+            } => {
+                let l = self.expression_type(l_expression)?;
+                let r = self.expression_type(r_expression)?;
+                let (l, r) = match (l, r) {
+                    (Some(l), Some(r)) => (l, r),
+                    (l, _) if ["+", "-", "*", "/", "**"].contains(&&op[..]) => return Ok(l),
+                    _ => return Ok(Some(Type::I32)),
+                };
+                let arithmetic = ["+", "-", "*", "/", "**"].contains(&&op[..]);
+                if !compatible(&l, &r) || (arithmetic && !(is_number(&l) && is_number(&r))) {
+                    return Err(format!("Can't apply `{}` to {} and {}", op, l, r));
+                }
+                // Comparisons always result in an i32
+                Ok(Some(if arithmetic { l } else { Type::I32 }))
+            }
+            Expression::UnaryExpression { op, expression, .. } => match &op[..] {
+                "&" => Ok(self
+                    .assignable_type(expression, op)?
+                    .map(|t| Type::Pointer(Box::new(t)))),
+                "*" => self.dereferenced_type(expression),
+                _ => match self.expression_type(expression)? {
+                    Some(t) if !is_number(&t) => Err(format!("Can't apply `{}` to {}", op, t)),
+                    t => Ok(t),
+                },
+            },
+            Expression::CastExpression {
+                expression,
+                target_type,
+                ..
+            } => match self.expression_type(expression)? {
+                Some(t) if !is_number(&t) || !is_number(target_type) => {
+                    Err(format!("Can't cast {} to {}", t, target_type))
+                }
+                None if !is_number(target_type) => Err(format!("Can't cast to {}", target_type)),
+                _ => Ok(Some(target_type.clone())),
+            },
+            Expression::PostfixExpression { op, expression, .. } => {
+                match self.assignable_type(expression, op)? {
+                    Some(t) if !is_integer(&t) => {
+                        Err(format!("`{}` can only be applied to integers", op))
+                    }
+                    t => Ok(t),
+                }
+            }
+        }
+    }
+
+    /// Get the type of a variable, array element, or field that is assigned to.
+    ///
+    /// # Arguments
+    /// * `expression` - The variable reference, index, or field access expression.
+    /// * `op` - The assigning operator.
+    fn assignable_type(&mut self, expression: &Expression, op: &str) -> Result<ValueType> {
+        match expression {
+            Expression::VariableReferenceExpression { name, .. } => {
+                if !self.is_variable(name) && self.program.function(name).is_some() {
+                    return Err(format!("Can't assign to function `{}`", name));
+                }
+                self.variable_type(name)
+            }
+            Expression::IndexExpression { array, index, .. } => self.element_type(array, index),
+            Expression::FieldAccessExpression {
+                expression, field, ..
+            } => self.field_type(expression, field),
+            Expression::UnaryExpression {
+                op: o, expression, ..
+            } if o == "*" => self.dereferenced_type(expression),
+            _ if op == "=" => {
+                Err("Expected variable reference, index, or field on assignment".to_string())
+            }
+            _ => Err(format!(
+                "Expected variable reference, index, or field on `{}`",
+                op
+            )),
+        }
+    }
+
+    /// Get the type of an element of an array after checking the array and index.
+    ///
+    /// # Arguments
+    /// * `array` - The array expression.
+    /// * `index` - The index of the element.
+    fn element_type(&mut self, array: &Expression, index: &Expression) -> Result<ValueType> {
+        let array_type = self.expression_type(array)?;
+        match self.expression_type(index)? {
+            Some(index_type) if !is_integer(&index_type) => {
+                return Err(format!(
+                    "Array index must be an integer, not {}",
+                    index_type
+                ))
+            }
+            _ => (),
+        }
+        match array_type {
+            Some(Type::Array(element_type)) => Ok(Some(*element_type)),
+            Some(Type::Str) => Ok(Some(Type::I8)),
+            Some(t) => Err(format!("Tried to index a non-array value of type {}", t)),
+            None => Ok(None),
+        }
+    }
+
+    /// Get the type of the value a pointer points to after checking the pointer.
+    ///
+    /// # Arguments
+    /// * `pointer` - The pointer expression.
+    fn dereferenced_type(&mut self, pointer: &Expression) -> Result<ValueType> {
+        match self.expression_type(pointer)? {
+            Some(Type::Pointer(element_type)) => Ok(Some(*element_type)),
+            Some(t) => Err(format!("Can't dereference {}", t)),
+            None => Ok(None),
+        }
+    }
+
+    /// Get the type of a field of a struct after checking the struct.
+    ///
+    /// # Arguments
+    /// * `expression` - The struct expression.
+    /// * `field` - The name of the field.
+    fn field_type(&mut self, expression: &Expression, field: &str) -> Result<ValueType> {
+        match self.expression_type(expression)? {
+            Some(Type::Struct(name)) => {
+                let definition = match self.program.structure(&name) {
+                    Some(definition) => definition,
+                    None => return Err(format!("Unknown type `{}`", name)),
+                };
+                match definition.fields.iter().find(|(f, _)| f == field) {
+                    Some((_, field_type)) => Ok(Some(field_type.clone())),
+                    None => Err(format!("Struct `{}` has no field `{}`", name, field)),
+                }
+            }
+            Some(t) => Err(format!("Can't access field `{}` of {}", field, t)),
+            None => Ok(None),
+        }
+    }
+
+    /// Get the type a function call results in after checking its arguments.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the called function.
+    /// * `args` - The arguments of the call.
+    fn call_type(&mut self, name: &str, args: &[Expression]) -> Result<ValueType> {
+        if let Some(definition) = self.program.structure(name) {
+            if args.len() != definition.fields.len() {
+                return Err(format!(
+                    "Struct `{}` has {} fields, got {} values",
+                    name,
+                    definition.fields.len(),
+                    args.len()
+                ));
+            }
+            for (arg, (field, field_type)) in args.iter().zip(&definition.fields) {
+                if let Some(arg_type) = self.expression_type(arg)? {
+                    if !compatible(field_type, &arg_type) {
+                        return Err(format!(
+                            "Field `{}` of `{}` expects {}, got {}",
+                            field, name, field_type, arg_type
+                        ));
+                    }
+                }
+            }
+            return Ok(Some(Type::Struct(name.to_string())));
+        }
+
+        if self.program.is_overloaded(name) && self.program.overload(name, args.len()).is_none() {
+            return Err(format!(
+                "No overload of function `{}` takes {} arguments",
+                name,
+                args.len()
+            ));
+        }
+        let (params, return_type, is_variadic) = match self.program.overload(name, args.len()) {
+            Some(Function::RegularFunction {
+                args, return_type, ..
+            }) => (args, return_type, false),
+            Some(Function::ExternalFunction {
+                args,
+                return_type,
+                is_variadic,
+                ..
+            }) => (args, return_type, *is_variadic),
+            None => {
+                if self.is_variable(name) {
+                    return Err(format!("`{}` isn't a function", name));
+                }
+                if !BUILTIN_FUNCTIONS.iter().any(|(b, _)| *b == name) {
+                    return Err(format!("Function `{}` doesn't exist", name));
+                }
+                // Built-ins check their own arguments during code generation
+                for arg in args {
+                    self.expression_type(arg)?;
+                }
+                return Ok(None);
+            }
+        };
+
+        if is_variadic && args.len() < params.len() {
+            return Err(format!(
+                "Function `{}` expects at least {} arguments, got {}",
+                name,
+                params.len(),
+                args.len()
+            ));
+        }
+        if !is_variadic && args.len() != params.len() {
+            return Err(format!(
+                "Function `{}` expects {} arguments, got {}",
+                name,
+                params.len(),
+                args.len()
+            ));
+        }
+        // Variadic arguments can have any type
+        for arg in &args[params.len()..] {
+            self.expression_type(arg)?;
+        }
+        for (arg, param) in args.iter().zip(params) {
+            if let Some(arg_type) = self.expression_type(arg)? {
+                if !compatible(&param.arg_type, &arg_type) {
+                    return Err(format!(
+                        "Argument `{}` of `{}` expects {}, got {}",
+                        param.name, name, param.arg_type, arg_type
+                    ));
+                }
+            }
+        }
+        // Void results are only allowed when they aren't used, which code generation checks
+        Ok(return_type.clone())
+    }
+}
@@ -0,0 +1,139 @@
+use crate::parser::{Parser, Token};
+use crate::peek_identifier_or_err;
+use crate::Result;
+use log::trace;
+use std::fmt;
+
+/// A yot type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    /// A signed 8-bit integer.
+    I8,
+    /// A signed 16-bit integer.
+    I16,
+    /// A signed 32-bit integer.
+    I32,
+    /// A signed 64-bit integer.
+    I64,
+    /// An unsigned 32-bit integer.
+    U32,
+    /// A 64-bit floating point number.
+    F64,
+    /// A reference to a constant null-terminated string.
+    Str,
+    /// A reference to an array of elements.
+    Array(Box<Type>),
+    /// A pointer to a function, used to pass callbacks to external functions.
+    Function,
+    /// A pointer to a value, such as the address of a variable.
+    Pointer(Box<Type>),
+    /// A fixed number of values, such as multiple values returned from a function.
+    Tuple(Vec<Type>),
+    /// A struct type declared by the program, by name.
+    Struct(String),
+}
+
+impl Type {
+    /// Get a type from its name, `None` if the type is unknown.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the type.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "i8" => Some(Type::I8),
+            "i16" => Some(Type::I16),
+            "i32" => Some(Type::I32),
+            "i64" => Some(Type::I64),
+            "u32" => Some(Type::U32),
+            "f64" => Some(Type::F64),
+            "str" => Some(Type::Str),
+            "fn" => Some(Type::Function),
+            _ => None,
+        }
+    }
+
+    /// Whether or not the type, or the element type of an array or pointer, is an unsigned
+    /// integer.
+    pub fn is_unsigned(&self) -> bool {
+        match self {
+            Type::U32 => true,
+            Type::Array(element_type) | Type::Pointer(element_type) => element_type.is_unsigned(),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::I8 => write!(f, "i8"),
+            Type::I16 => write!(f, "i16"),
+            Type::I32 => write!(f, "i32"),
+            Type::I64 => write!(f, "i64"),
+            Type::U32 => write!(f, "u32"),
+            Type::F64 => write!(f, "f64"),
+            Type::Str => write!(f, "str"),
+            Type::Array(element_type) => write!(f, "[{}]", element_type),
+            Type::Function => write!(f, "fn"),
+            Type::Pointer(element_type) => write!(f, "*{}", element_type),
+            Type::Tuple(element_types) => {
+                let element_types = element_types
+                    .iter()
+                    .map(|t| t.to_string())
+                    .collect::<Vec<_>>();
+                write!(f, "({})", element_types.join(", "))
+            }
+            Type::Struct(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+impl Parser {
+    /// Parses a type.
+    ///
+    /// # Grammar
+    /// * Identifier
+    /// * "[" + Type + "]"
+    /// * "(" + Type + ("," + Type)... + ")"
+    /// * "*" + Type
+    ///
+    /// An identifier that isn't a built-in type names a struct, which is checked to exist along
+    /// with the types of the program.
+    pub fn parse_type(&mut self) -> Result<Type> {
+        trace!("Parsing type");
+        self.nested(|parser| {
+            if parser.next_symbol_is("(") {
+                let mut element_types = vec![parser.parse_type()?];
+                while parser.next_symbol_is(",") {
+                    element_types.push(parser.parse_type()?);
+                }
+                if !parser.next_symbol_is(")") {
+                    return Err("Expected `)` or `,` after tuple element type".to_string());
+                }
+                if element_types.len() < 2 {
+                    return Err("A tuple type must have at least two element types".to_string());
+                }
+                return Ok(Type::Tuple(element_types));
+            }
+            if parser.next_symbol_is("*") {
+                return Ok(Type::Pointer(Box::new(parser.parse_type()?)));
+            }
+            // A pointer to a pointer starts with `**`, which is lexed as one symbol
+            if parser.next_symbol_is("**") {
+                let element_type = Type::Pointer(Box::new(parser.parse_type()?));
+                return Ok(Type::Pointer(Box::new(element_type)));
+            }
+            if parser.next_symbol_is("[") {
+                let element_type = parser.parse_type()?;
+                if !parser.next_symbol_is("]") {
+                    return Err("Expected `]` after array element type".to_string());
+                }
+                return Ok(Type::Array(Box::new(element_type)));
+            }
+
+            let name = peek_identifier_or_err!(parser);
+            parser.tokens.next();
+            Ok(Type::from_name(&name).unwrap_or(Type::Struct(name)))
+        })
+    }
+}
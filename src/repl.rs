@@ -0,0 +1,174 @@
+use crate::generator::{Generator, GeneratorOptions};
+use crate::lexer::Lexer;
+use crate::parser::const_eval::fold_const_calls;
+use crate::parser::optimize::fold_constants;
+use crate::parser::program::{Program, Symbol};
+use crate::parser::typecheck::typecheck_after;
+use crate::parser::Parser;
+use crate::Result;
+use log::{debug, error};
+use std::io::{self, BufRead, Write};
+
+/// An interactive session that compiles and runs each line of input as it is entered.
+///
+/// A line starting with `@`, `$`, or `include` defines functions, global variables, and structs,
+/// which stay available for the rest of the session. Any other line is wrapped in a function that
+/// is compiled just in time and called, as an expression whose value is printed or, if it ends
+/// with `;`, as a statement.
+pub struct Repl {
+    /// The generator whose module holds every definition so far.
+    generator: Generator,
+    /// The definitions entered so far, which later input is checked against.
+    definitions: Program,
+    /// The number of lines that have been evaluated, used to name their functions.
+    evaluations: usize,
+}
+
+impl Repl {
+    /// Create a REPL without any definitions.
+    ///
+    /// # Arguments
+    /// * `options` - Code generation options.
+    pub unsafe fn new(options: GeneratorOptions) -> Self {
+        // Each line is generated separately, so there is no whole program to instrument or debug
+        let options = GeneratorOptions {
+            instrument: false,
+            debug_source: None,
+            ..options
+        };
+        Repl {
+            generator: Generator::with_options(Program::default(), "repl", options),
+            definitions: Program::default(),
+            evaluations: 0,
+        }
+    }
+
+    /// Read lines from stdin until it is closed, printing the value of each expression.
+    ///
+    /// Errors are logged without ending the session.
+    pub unsafe fn run(&mut self) {
+        // The prompt would only clutter output that isn't read by a person
+        let interactive = libc::isatty(libc::STDIN_FILENO) == 1;
+        let stdin = io::stdin();
+        let mut lines = stdin.lock().lines();
+        loop {
+            if interactive {
+                print!("> ");
+                io::stdout().flush().unwrap_or_default();
+            }
+            let line = match lines.next() {
+                Some(Ok(line)) => line,
+                Some(Err(e)) => {
+                    error!("IO: {}", e);
+                    return;
+                }
+                None => return,
+            };
+            match self.eval(&line) {
+                Ok(Some(value)) => println!("{}", value),
+                Ok(None) => (),
+                Err(e) => error!("{}", e),
+            }
+        }
+    }
+
+    /// Compile a line of input and run it if it isn't a definition.
+    ///
+    /// The value of an expression is returned, `None` for definitions and statements. If the line
+    /// has an error, the module is left as it was.
+    ///
+    /// # Arguments
+    /// * `line` - The line of input.
+    pub unsafe fn eval(&mut self, line: &str) -> Result<Option<i32>> {
+        let line = line.trim();
+        if line.is_empty() {
+            return Ok(None);
+        }
+
+        let is_definition =
+            line.starts_with('@') || line.starts_with('$') || line.starts_with("include");
+        let name = format!("__repl_{}", self.evaluations);
+        let source = if is_definition {
+            line.to_string()
+        } else if line.ends_with(';') {
+            format!("@{}[]: void {{ {} }}", name, line)
+        } else {
+            format!("@{}[] -> {};", name, line)
+        };
+        debug!("Evaluating `{}`", source);
+
+        let tokens = Lexer::from_text(&source).collect::<Result<Vec<_>>>()?;
+        let input = Parser::new(tokens.into_iter().peekable()).parse_definitions_only()?;
+        let input = self.check(input)?;
+
+        let generated = self
+            .generator
+            .gen_program(&input)
+            .and_then(|_| self.generator.verify());
+        if let Err(e) = generated {
+            self.generator.remove_definitions(&input);
+            return Err(e);
+        }
+
+        if is_definition {
+            self.definitions.functions.extend(input.functions);
+            self.definitions.globals.extend(input.globals);
+            self.definitions.structs.extend(input.structs);
+            return Ok(None);
+        }
+        self.evaluations += 1;
+        let value = self.generator.jit_call(&name);
+        self.generator.remove_definitions(&input);
+        value
+    }
+
+    /// Type check input along with the previous definitions, returning the input with constants
+    /// folded.
+    ///
+    /// # Arguments
+    /// * `input` - The definitions of a line of input.
+    fn check(&self, input: Program) -> Result<Program> {
+        let defined = self.definitions.symbols();
+        for symbol in input.symbols() {
+            let name = match symbol {
+                Symbol::Function { name, .. } => name,
+                Symbol::Global { name, .. } => name,
+            };
+            let is_defined = defined.iter().any(|s| match s {
+                Symbol::Function { name: n, .. } => *n == name,
+                Symbol::Global { name: n, .. } => *n == name,
+            });
+            if is_defined {
+                return Err(format!("`{}` is already defined", name));
+            }
+        }
+        for definition in &input.structs {
+            if self.definitions.structure(&definition.name).is_some() {
+                return Err(format!("`{}` is already defined", definition.name));
+            }
+        }
+
+        let mut program = Program {
+            functions: self.definitions.functions.clone(),
+            globals: self.definitions.globals.clone(),
+            structs: self.definitions.structs.clone(),
+            entry: None,
+        };
+        program.functions.extend(input.functions);
+        program.globals.extend(input.globals);
+        program.structs.extend(input.structs);
+        // The previous definitions were already warned about
+        typecheck_after(&program, self.definitions.functions.len()).map_err(|(e, _)| e)?;
+        fold_const_calls(&mut program);
+        fold_constants(&mut program);
+
+        Ok(Program {
+            functions: program
+                .functions
+                .split_off(self.definitions.functions.len()),
+            globals: program.globals.split_off(self.definitions.globals.len()),
+            structs: program.structs.split_off(self.definitions.structs.len()),
+            entry: None,
+        })
+    }
+}
@@ -0,0 +1,195 @@
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::Result;
+use log::{debug, info};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// Recompiles a program whenever its file, or a file it includes, changes.
+///
+/// Files are polled for changes to their modification times, so no platform-specific file system
+/// events are needed.
+pub struct Watcher {
+    /// The path to the program file.
+    path: PathBuf,
+    /// The modification time of each watched file when it was last checked, `None` if it couldn't
+    /// be read.
+    files: HashMap<PathBuf, Option<SystemTime>>,
+    /// How often files are checked for changes.
+    interval: Duration,
+    /// How long files must stay unchanged before recompiling, so a burst of writes compiles once.
+    debounce: Duration,
+}
+
+impl Watcher {
+    /// Create a watcher for a program file.
+    ///
+    /// # Arguments
+    /// * `path` - The path to the program file.
+    pub fn new(path: &str) -> Self {
+        let path = PathBuf::from(path);
+        Watcher {
+            // Included files are canonical, so the program file is too
+            path: path.canonicalize().unwrap_or(path),
+            files: HashMap::new(),
+            interval: Duration::from_millis(250),
+            debounce: Duration::from_millis(100),
+        }
+    }
+
+    /// Set how often files are checked and how long they must stay unchanged after a change.
+    ///
+    /// # Arguments
+    /// * `interval` - How often files are checked for changes.
+    /// * `debounce` - How long files must stay unchanged before recompiling.
+    pub fn with_timing(mut self, interval: Duration, debounce: Duration) -> Self {
+        self.interval = interval;
+        self.debounce = debounce;
+        self
+    }
+
+    /// Compile the program, then compile it again after every change until `compile` returns
+    /// false.
+    ///
+    /// # Arguments
+    /// * `compile` - Compiles the program, returning whether or not to keep watching.
+    pub fn run(&mut self, mut compile: impl FnMut() -> bool) {
+        loop {
+            // Included files are found before compiling, so changes during compilation are seen
+            self.update_files();
+            if !compile() {
+                return;
+            }
+            info!("Watching for changes");
+            self.wait_for_change();
+        }
+    }
+
+    /// Watch the program file and the files it includes, keeping the times already checked.
+    ///
+    /// If the program can't be parsed, the files that were already watched are kept, so fixing an
+    /// included file is still seen.
+    fn update_files(&mut self) {
+        let mut paths = included_files(&self.path).unwrap_or_else(|e| {
+            debug!("Unable to find included files: {}", e);
+            self.files.keys().cloned().collect()
+        });
+        paths.push(self.path.clone());
+
+        let mut files = HashMap::new();
+        for path in paths {
+            if files.contains_key(&path) {
+                continue;
+            }
+            let modified = match self.files.remove(&path) {
+                Some(modified) => modified,
+                None => modified(&path),
+            };
+            files.insert(path, modified);
+        }
+        self.files = files;
+    }
+
+    /// Check if any watched file changed since it was last checked.
+    fn changed(&mut self) -> bool {
+        let mut changed = false;
+        for (path, last_modified) in self.files.iter_mut() {
+            let modified = modified(path);
+            if modified != *last_modified {
+                debug!("`{}` changed", path.display());
+                *last_modified = modified;
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// Wait until a watched file changes and then stays unchanged for the debounce time.
+    fn wait_for_change(&mut self) {
+        while !self.changed() {
+            thread::sleep(self.interval);
+        }
+        loop {
+            thread::sleep(self.debounce);
+            if !self.changed() {
+                return;
+            }
+        }
+    }
+}
+
+/// Get the modification time of a file, `None` if it can't be read.
+///
+/// # Arguments
+/// * `path` - The path to the file.
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Parse a program to find the files it includes, including itself.
+///
+/// # Arguments
+/// * `path` - The path to the program file.
+fn included_files(path: &Path) -> Result<Vec<PathBuf>> {
+    let path = path.to_str().ok_or("The program path isn't valid UTF-8")?;
+    let tokens = Lexer::from_file(path)
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>>>()?;
+    let mut parser = Parser::new(tokens.into_iter().peekable()).with_path(path);
+    parser.parse_definitions_only()?;
+    Ok(parser.included_files().to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::Watcher;
+    use std::time::Duration;
+    use std::{env, fs, thread};
+
+    /// Watch a program, writing to `changed_file` once the first compile is done, and return the
+    /// number of compiles.
+    fn count_compiles(name: &str, files: &[(&str, &str)], changed_file: &str) -> usize {
+        let dir = env::temp_dir().join(format!("yotc_watch_{}", name));
+        fs::create_dir_all(&dir).unwrap();
+        for (file, source) in files {
+            fs::write(dir.join(file), source).unwrap();
+        }
+        let path = dir.join(files[0].0);
+        let changed_path = dir.join(changed_file);
+
+        let mut compiles = 0;
+        Watcher::new(path.to_str().unwrap())
+            .with_timing(Duration::from_millis(10), Duration::from_millis(50))
+            .run(|| {
+                compiles += 1;
+                if compiles == 1 {
+                    let changed_path = changed_path.clone();
+                    thread::spawn(move || {
+                        thread::sleep(Duration::from_millis(100));
+                        fs::write(&changed_path, "@main[] -> 1;").unwrap();
+                    });
+                }
+                compiles < 2
+            });
+        compiles
+    }
+
+    #[test]
+    fn recompiles_on_change() {
+        let files = [("main.yot", "@main[] -> 0;")];
+        assert_eq!(count_compiles("main", &files, "main.yot"), 2);
+    }
+
+    #[test]
+    fn recompiles_on_included_change() {
+        let files = [
+            ("main.yot", "include \"lib.yot\"; @main[] -> 0;"),
+            ("lib.yot", "@one[] -> 1;"),
+        ];
+        assert_eq!(count_compiles("include", &files, "lib.yot"), 2);
+    }
+}
@@ -0,0 +1,1261 @@
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+use std::{env, fs};
+
+/// Path to a temporary file unique to a test.
+fn temp_path(name: &str, extension: &str) -> PathBuf {
+    env::temp_dir().join(format!("yotc_{}.{}", name, extension))
+}
+
+/// Write a program to a file and run yotc on it with extra arguments.
+fn yotc(source: &str, name: &str, args: &[&str]) -> Output {
+    let input = temp_path(name, "yot");
+    fs::write(&input, source).unwrap();
+    Command::new(env!("CARGO_BIN_EXE_yotc"))
+        .arg(&input)
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+/// Compile a program into an executable and run it.
+fn compile_and_run(source: &str, name: &str) -> Output {
+    compile_and_run_with(source, name, &[])
+}
+
+/// Compile a program into an executable with extra yotc arguments and run it.
+fn compile_and_run_with(source: &str, name: &str, args: &[&str]) -> Output {
+    Command::new(compile(source, name, args)).output().unwrap()
+}
+
+/// Compile a program into an executable with extra yotc arguments, returning its path.
+fn compile(source: &str, name: &str, args: &[&str]) -> PathBuf {
+    let object_file = temp_path(name, "o");
+    let executable = temp_path(name, "out");
+
+    let mut args = args.to_vec();
+    args.extend(&["-f", "object-file", "-o", object_file.to_str().unwrap()]);
+    let output = yotc(source, name, &args);
+    assert!(output.status.success());
+    let status = Command::new("gcc")
+        .arg(&object_file)
+        .args(["-no-pie", "-o"])
+        .arg(&executable)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    executable
+}
+
+#[test]
+fn print_builtin() {
+    let output = compile_and_run("@main[] { print(42); -> 0; }", "print_builtin");
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "42\n");
+}
+
+#[test]
+fn min_max_abs_builtins() {
+    let output = compile_and_run(
+        "@main[] {
+            @u: u32 = 0xFFFFFFFF;
+            print(min(3, -7), max(3, -7), abs(-7), abs(7));
+            print(min(u, 1), max(u, 1));
+            -> 0;
+        }",
+        "min_max_abs_builtins",
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "-7 3 7 7\n1 4294967295\n"
+    );
+}
+
+#[test]
+fn assert_builtin() {
+    let output = compile_and_run(
+        "@main[] { @x = 2; assert(x == 2); assert(x > 0, \"x is positive\"); -> 0; }",
+        "assert_builtin_pass",
+    );
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+
+    let output = compile_and_run(
+        "@main[] { print(1); assert(1 > 2, \"one is bigger\"); print(2); -> 0; }",
+        "assert_builtin_fail",
+    );
+    assert!(!output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1\n");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "Assertion failed: one is bigger\n"
+    );
+}
+
+#[test]
+fn exponent() {
+    let output = compile_and_run(
+        "@main[] { @a = 2; @b = 3; @c = 2; print(a ** b ** c, -a ** c, a ** -1, 10 ** 0); -> 0; }",
+        "exponent",
+    );
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "512 -4 1 1\n");
+}
+
+#[test]
+fn main_argc_argv() {
+    let executable = compile(
+        "@!puts[s: str]; @main[argc, argv] { puts(argv[argc - 1]); -> argc; }",
+        "main_argc_argv",
+        &[],
+    );
+    let output = Command::new(executable)
+        .args(["first", "second"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "second\n");
+}
+
+#[test]
+fn main_arguments() {
+    let output = compile_and_run(
+        "@!puts[s: str]; @main[argc, argv: [str]] { puts(argv[0]); -> argc; }",
+        "main_arguments",
+    );
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("yotc_main_arguments.out"));
+}
+
+#[test]
+fn deeply_nested_expression() {
+    let source = format!(
+        "@main[] -> {}1{};",
+        "(".repeat(100_000),
+        ")".repeat(100_000)
+    );
+    let output = yotc(&source, "deeply_nested_expression", &["--check"]);
+    // The nesting is an error rather than a stack overflow
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Nesting too deep"));
+
+    let source = format!("@main[] {{ @a: {}i32; -> 0; }}", "* ".repeat(100_000));
+    let output = yotc(&source, "deeply_nested_type", &["--check"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Nesting too deep"));
+}
+
+#[test]
+fn binary_operand_order() {
+    let output = compile_and_run(
+        "@f[] { print(1); -> 10; }
+        @g[] { print(2); -> 3; }
+        @main[] -> f() - g();",
+        "binary_operand_order",
+    );
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1\n2\n");
+    assert_eq!(output.status.code(), Some(7));
+}
+
+#[test]
+fn variadic_printf() {
+    let output = compile_and_run(
+        "@!printf[format: str, ...];
+        @main[] {
+            @small: i8 = -3;
+            printf(\"start|\");
+            printf(\"%d|\", 1);
+            printf(\"%d %s %d|\", 2, \"two\", small);
+            printf(\"%ld %.1f|\", 5_000_000_000i64, 3 as f64);
+            -> 0;
+        }",
+        "variadic_printf",
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "start|1|2 two -3|5000000000 3.0|"
+    );
+
+    // The fixed arguments are still required
+    let output = yotc(
+        "@!printf[format: str, ...]; @main[] -> printf();",
+        "variadic_printf_missing",
+        &[],
+    );
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("Function `printf` expects at least 1 arguments, got 0"));
+}
+
+#[test]
+fn qsort_callback() {
+    let output = compile_and_run(
+        "@!qsort[base: [i32], count: i64, size: i64, compare: fn];
+        @compare[a: [i32], b: [i32]] -> a[0] - b[0];
+        @main[] {
+            @a = [3, 1, 2];
+            qsort(a, 3, 4, compare);
+            print(a[0]);
+            print(a[1]);
+            print(a[2]);
+            -> 0;
+        }",
+        "qsort_callback",
+    );
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1\n2\n3\n");
+}
+
+#[test]
+fn empty_program() {
+    let ir = temp_path("empty_program", "ll");
+    let output = yotc(
+        "",
+        "empty_program",
+        &["-f", "llvm", "-o", ir.to_str().unwrap()],
+    );
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("The program is empty"));
+}
+
+#[test]
+fn empty_functions() {
+    let output = compile_and_run("@main[] {}", "empty_main");
+    assert_eq!(output.status.code(), Some(0));
+
+    let output = compile_and_run("@zero[] {} @main[] -> zero() + 3;", "empty_function");
+    assert_eq!(output.status.code(), Some(3));
+}
+
+#[test]
+fn builtin_shadowing_warning() {
+    let source = "@print[a] -> a + 1; @main[] -> print(6) - 7;";
+    let ir = temp_path("builtin_shadowing_warning", "ll");
+    let ir = ir.to_str().unwrap();
+
+    let output = yotc(
+        source,
+        "builtin_shadowing_warning",
+        &["-f", "llvm", "-o", ir],
+    );
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("Function `print[a]` shadows the built-in `print[values...]`"));
+
+    let output = compile_and_run(source, "builtin_shadowing_warning");
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+
+    let output = yotc(
+        source,
+        "builtin_shadowing_warning",
+        &["-f", "llvm", "-o", ir, "--no-builtins"],
+    );
+    assert!(output.status.success());
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("shadows"));
+}
+
+#[test]
+fn puts_putchar_builtins() {
+    let output = compile_and_run(
+        "@main[] { puts(\"hello\"); putchar('!'); putchar(10); -> 0; }",
+        "puts_putchar_builtins",
+    );
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "hello\n!\n");
+
+    // Declaring the libc function the built-in calls isn't shadowing
+    let output = compile_and_run(
+        "@!puts[s: str]; @main[] { puts(\"hello\"); -> 0; }",
+        "puts_extern",
+    );
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "hello\n");
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("shadows"));
+}
+
+#[test]
+fn stdin_builtins() {
+    let source = "@main[] {
+        @n = read_int();
+        print(n * 2);
+        getchar(); // Eat the newline after the integer
+        @c = getchar();
+        print(c, getchar(), getchar());
+        -> 0;
+    }";
+    let mut program = Command::new(compile(source, "stdin_builtins", &[]))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    program.stdin.take().unwrap().write_all(b"21\nx").unwrap();
+    let output = program.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "42\n120 -1 -1\n");
+}
+
+#[test]
+fn unused_variable_warning() {
+    let output = yotc(
+        "@main[] {\n    @used = 1;\n    @unused = 2;\n    @_ = 3;\n    -> used;\n}",
+        "unused_variable_warning",
+        &["--check"],
+    );
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Unused variable `unused` in function `main` at line 3"));
+    assert!(!stderr.contains("`used`"));
+    assert!(!stderr.contains("`_`"));
+}
+
+#[test]
+fn shadowed_variable_warning() {
+    let source = "@main[] {\n    @x = 1;\n    {\n        @x = 2;\n        x;\n    }\n    -> x;\n}";
+    let output = yotc(source, "shadowed_variable_warning", &["--check"]);
+    assert!(output.status.success());
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("shadows"));
+
+    let output = yotc(
+        source,
+        "shadowed_variable_warning",
+        &["--check", "--warn-shadow"],
+    );
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("Variable `x` shadows `x` from an outer scope in function `main` at line 1"));
+}
+
+#[test]
+fn extern_then_definition() {
+    let output = compile_and_run(
+        "@!triple[n];\n@main[] -> triple(2);\n@triple[n] -> n * 3;",
+        "extern_then_definition",
+    );
+    assert_eq!(output.status.code(), Some(6));
+
+    let output = yotc(
+        "@!triple[n: i64]: i64;\n@main[] -> 0;\n@triple[n] -> n * 3;",
+        "extern_then_definition_mismatch",
+        &[],
+    );
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("Argument `n` of `triple` is i32, but i64 in its external declaration"));
+}
+
+#[test]
+fn wildcard_names() {
+    let source = "@f[_, _] -> 1;
+        @const second[_, b, _] -> b;
+        @main[] {
+            @_ = f(1, 2);
+            @_ = 3;
+            { @_ = 4; }
+            print(second(5, 6, 7));
+            -> f(0, 0) + second(0, 1, 0);
+        }";
+    let output = compile_and_run(source, "wildcard_names");
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "6\n");
+
+    // Variables named `_` are never unused or shadowed
+    let output = yotc(source, "wildcard_names", &["--check", "--warn-shadow"]);
+    assert!(output.status.success());
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("WARN"));
+}
+
+#[test]
+fn function_overloading() {
+    let output = compile_and_run(
+        "@foo[x] -> x;
+        @foo[x, y] -> x + y;
+        @foo[x, y, z: u32]: u32 -> z;
+        @main[] { print(foo(1), foo(2, 3), foo(0, 0, 0xFFFFFFFF)); -> foo(40, 2); }",
+        "function_overloading",
+    );
+    assert_eq!(output.status.code(), Some(42));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1 5 4294967295\n");
+
+    // Calls to const overloads are evaluated with the overload taking that many arguments
+    let output = compile_and_run(
+        "@const foo[x] -> x * 2; @const foo[x, y] -> foo(x) + y; @main[] -> foo(20, 2);",
+        "const_function_overloading",
+    );
+    assert_eq!(output.status.code(), Some(42));
+}
+
+#[test]
+fn prelude() {
+    let source = "@main[] { print(clamp(15, 0, 10), sign(-4), lcm(4, 6)); -> 0; }";
+    let output = compile_and_run(source, "prelude");
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "10 -1 12\n");
+
+    let output = yotc(source, "prelude_disabled", &["--check", "--no-prelude"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Function `clamp` doesn't exist"));
+
+    // A function of the program replaces the prelude function with the same name
+    let output = compile_and_run("@sign[x] -> 7; @main[] -> sign(-4);", "prelude_replaced");
+    assert_eq!(output.status.code(), Some(7));
+
+    // Variables with the name of a prelude function don't use it
+    let output = compile_and_run(
+        "@gcd = 3; @main[] { @sign = 4; -> gcd + sign; }",
+        "prelude_variables",
+    );
+    assert_eq!(output.status.code(), Some(7));
+}
+
+#[test]
+fn instrument_call_counts() {
+    let output = compile_and_run_with(
+        "@f[] -> 0; @main[] { f(); f(); f(); -> 0; }",
+        "instrument_call_counts",
+        &["--instrument"],
+    );
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "f: 3 calls\nmain: 1 calls\n"
+    );
+}
+
+#[test]
+fn if_else_statement() {
+    let output = compile_and_run(
+        "@sign[n] { ?[n < 0] -> -1; : ?[n > 0] -> 1; -> 0; }
+        @offset[n] {
+            @m = n;
+            ?[m < 0] { m = 0; } : { m = m + 10; }
+            -> m;
+        }
+        @main[] {
+            print(sign(-5)); print(sign(0)); print(sign(5));
+            print(offset(-3)); print(offset(3));
+            -> 0;
+        }",
+        "if_else_statement",
+    );
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "-1\n0\n1\n0\n13\n");
+}
+
+#[test]
+fn const_function_runtime_fallback() {
+    let output = compile_and_run(
+        "@const fib[n] { ?[n < 2] -> n; : -> fib(n - 1) + fib(n - 2); }
+        @main[] { @x = 10; print(fib(10)); print(fib(x)); -> 0; }",
+        "const_function_runtime_fallback",
+    );
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "55\n55\n");
+}
+
+#[test]
+fn print_builtin_multiple_values() {
+    let output = compile_and_run(
+        "@main[] { @a: i64 = 2; print(1, a, 3); -> 0; }",
+        "print_builtin_multiple_values",
+    );
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1 2 3\n");
+}
+
+#[test]
+fn debug_info() {
+    let object_file = temp_path("debug_info", "o");
+    let output = yotc(
+        "@add[a, b] -> a + b;\n@main[] {\n    -> add(1, 2);\n}\n",
+        "debug_info",
+        &[
+            "-g",
+            "-f",
+            "object-file",
+            "-o",
+            object_file.to_str().unwrap(),
+        ],
+    );
+    assert!(output.status.success());
+    let object = fs::read(&object_file).unwrap();
+    assert!(object.windows(11).any(|w| w == b".debug_info"));
+}
+
+#[test]
+fn main_return_is_exit_code() {
+    let output = compile_and_run("@main[] { -> 7; }", "main_return_is_exit_code");
+    assert_eq!(output.status.code(), Some(7));
+}
+
+#[test]
+fn missing_linker() {
+    let output = yotc(
+        "@main[] -> 0;",
+        "missing_linker",
+        &[
+            "--linker",
+            "yotc-missing-linker",
+            "-o",
+            temp_path("missing_linker", "out").to_str().unwrap(),
+        ],
+    );
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("Linker `yotc-missing-linker` wasn't found on PATH"));
+}
+
+#[test]
+fn link_error() {
+    let output = yotc(
+        "@!yotc_missing_function[]; @main[] -> yotc_missing_function();",
+        "link_error",
+        &["-o", temp_path("link_error", "out").to_str().unwrap()],
+    );
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("failed with exit status"));
+    assert!(stderr.contains("yotc_missing_function"));
+}
+
+#[test]
+fn link_object_and_library() {
+    let c_source = temp_path("link_object_and_library_c", "c");
+    let c_object = temp_path("link_object_and_library_c", "o");
+    fs::write(&c_source, "int yotc_double(int x) { return x * 2; }").unwrap();
+    let status = Command::new("gcc")
+        .arg("-c")
+        .arg(&c_source)
+        .arg("-o")
+        .arg(&c_object)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let executable = temp_path("link_object_and_library", "out");
+    let output = yotc(
+        "@!yotc_double[x]; @main[] { print(yotc_double(21)); -> 0; }",
+        "link_object_and_library",
+        &[
+            "--link-obj",
+            c_object.to_str().unwrap(),
+            "-lm",
+            "-o",
+            executable.to_str().unwrap(),
+        ],
+    );
+    assert!(output.status.success());
+    let output = Command::new(&executable).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "42\n");
+}
+
+#[test]
+fn object_file_emit_error() {
+    let output = yotc(
+        "@main[] -> 0;",
+        "object_file_emit_error",
+        &[
+            "-f",
+            "object-file",
+            "-o",
+            temp_path("missing_directory/object_file_emit_error", "o")
+                .to_str()
+                .unwrap(),
+        ],
+    );
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("LLVM: "));
+}
+
+#[test]
+fn check() {
+    let output_path = temp_path("check", "out");
+    let _ = fs::remove_file(&output_path);
+    let output = yotc(
+        "@main[] -> 0;",
+        "check",
+        &["--check", "-o", output_path.to_str().unwrap()],
+    );
+    assert!(output.status.success());
+    assert!(!output_path.exists());
+
+    let output = yotc("@main[] -> x;", "check_error", &["--check"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Undefined variable `x`"));
+}
+
+#[test]
+fn stdin_input() {
+    let object_file = temp_path("stdin_input", "o");
+    let executable = temp_path("stdin_input", "out");
+    let mut yotc = Command::new(env!("CARGO_BIN_EXE_yotc"))
+        .args([
+            "-",
+            "-f",
+            "object-file",
+            "-o",
+            object_file.to_str().unwrap(),
+        ])
+        .stdin(Stdio::piped())
+        .spawn()
+        .unwrap();
+    yotc.stdin
+        .take()
+        .unwrap()
+        .write_all(b"@main[] { print(5); -> 0; }")
+        .unwrap();
+    assert!(yotc.wait().unwrap().success());
+
+    let status = Command::new("gcc")
+        .arg(&object_file)
+        .args(["-no-pie", "-o"])
+        .arg(&executable)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let output = Command::new(&executable).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "5\n");
+}
+
+#[test]
+fn string_len_builtin() {
+    let output = compile_and_run(
+        "@main[] { @s: str = \"hello\"; print(len(s)); -> 0; }",
+        "string_len_builtin",
+    );
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "5\n");
+}
+
+#[test]
+fn post_increment() {
+    let output = compile_and_run(
+        "@main[] { @x = 5; @y = x++; print(x, y); y--; -> y; }",
+        "post_increment",
+    );
+    assert_eq!(output.status.code(), Some(4));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "6 5\n");
+}
+
+#[test]
+fn switch_statement() {
+    let output = compile_and_run(
+        "@name[n]: void { %[n] { 1: print(10); 2: { print(20); } -1: print(-10); _: print(0); } }
+        @main[] { name(1); name(2); name(-1); name(7); -> 0; }",
+        "switch_statement",
+    );
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "10\n20\n-10\n0\n");
+}
+
+#[test]
+fn do_while_statement() {
+    let output = compile_and_run(
+        "@main[] {
+            @i = 0;
+            { print(i); i = i + 1; } ~[i < 3];
+            { @once = 10; print(once); } ~[0];
+            -> i;
+        }",
+        "do_while_statement",
+    );
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "0\n1\n2\n10\n");
+    assert_eq!(output.status.code(), Some(3));
+}
+
+#[test]
+fn unsigned_arithmetic() {
+    let output = compile_and_run(
+        "@main[] {
+            @u: u32 = 0xFFFFFFFF;
+            @i = 0xFFFFFFFF;
+            print(u / 2, i / 2);
+            print(u < 1, i < 1);
+            print(u);
+            -> 0;
+        }",
+        "unsigned_arithmetic",
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "2147483647 0\n0 1\n4294967295\n"
+    );
+}
+
+#[test]
+fn i64_addition() {
+    let output = compile_and_run(
+        "@main[] { @a = 2147483647; @b = a + 1i64; @c: i8 = -128i8; print(b, c); -> 0; }",
+        "i64_addition",
+    );
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "2147483648 -128\n");
+}
+
+#[test]
+fn cast_expression() {
+    let output = compile_and_run(
+        "@main[] { @f = 7 as f64; print(300 as i8, f as i32, -1 as u32); -> 0; }",
+        "cast_expression",
+    );
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "44 7 4294967295\n");
+}
+
+#[test]
+fn u32_constant_folding() {
+    let output = compile_and_run(
+        "@g: u32 = 0xF0000000 / 2;
+        @main[] { @y: u32 = 0xF0000000; print(0xF0000000 / 2 == y / 2, g == y / 2); -> 0; }",
+        "u32_constant_folding",
+    );
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1 1\n");
+}
+
+#[test]
+fn f64_arithmetic() {
+    let output = compile_and_run(
+        "@main[] {
+            @x = 3 as f64;
+            @y = x + x;
+            @z = y * x - x / y;
+            print((y - x) as i32, z as i32, (-x) as i32, x < y, y < x);
+            -> 0;
+        }",
+        "f64_arithmetic",
+    );
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "3 17 -3 1 0\n");
+}
+
+#[test]
+fn include() {
+    let dir = env::temp_dir().join("yotc_include");
+    fs::create_dir_all(dir.join("lib")).unwrap();
+    fs::write(
+        dir.join("lib/math.yot"),
+        "include \"io.yot\"; @square[n] -> n * n;",
+    )
+    .unwrap();
+    fs::write(dir.join("lib/io.yot"), "@!putchar[c];").unwrap();
+    let source = "include \"lib/math.yot\"; include \"lib/io.yot\";
+        @main[] { putchar('0' + square(3)); -> 0; }";
+    let output = compile_and_run(source, "include/main");
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "9");
+
+    fs::write(dir.join("lib/io.yot"), "include \"math.yot\";").unwrap();
+    let output = yotc(source, "include/main", &["--check"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Circular include of"));
+}
+
+#[test]
+fn shadowed_variable_restored_after_block() {
+    let output = compile_and_run(
+        "@main[] { @x = 1; { @x = 2; print(x); } print(x); -> x; }",
+        "shadowed_variable_restored_after_block",
+    );
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "2\n1\n");
+}
+
+#[test]
+fn struct_fields() {
+    let output = compile_and_run(
+        "$Point[x, y: i64]
+        $Line[a: Point, b: Point]
+        @length[l: Line]: i64 -> l.b.x - l.a.x + l.b.y - l.a.y;
+        @main[] {
+            @p = Point(3, 4);
+            p.x = p.x + 1;
+            p.y++;
+            @l = Line(Point(0, 0), p);
+            l.b.y = 10;
+            print(p.x, p.y, length(l), Line(p, p).b.x);
+            -> 0;
+        }",
+        "struct_fields",
+    );
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "4 5 14 4\n");
+}
+
+#[test]
+fn position_independent_code() {
+    let source = "@main[] { print(1); -> 1; }";
+    let assembly = |name: &str, args: &[&str]| {
+        let output_path = temp_path(name, "s");
+        let mut args = args.to_vec();
+        args.extend(&["-f", "assembly", "-o", output_path.to_str().unwrap()]);
+        assert!(yotc(source, name, &args).status.success());
+        fs::read_to_string(output_path).unwrap()
+    };
+    // The format string of `print` is addressed relative to the instruction pointer
+    assert!(assembly("position_independent_code_pic", &["--pic"]).contains(".L.str(%rip)"));
+    assert!(
+        !assembly("position_independent_code_static", &["--reloc", "static"])
+            .contains(".L.str(%rip)")
+    );
+
+    // PIC objects can still be linked into executables
+    let output = compile_and_run_with(source, "position_independent_code", &["--reloc", "pic"]);
+    assert_eq!(output.status.code(), Some(1));
+    // Executables linked from PIC objects are PIE, which ELF marks as a shared object
+    if cfg!(target_os = "linux") {
+        let executable = |name: &str, args: &[&str]| {
+            let output_path = temp_path(name, "out");
+            let mut args = args.to_vec();
+            args.extend(&["-f", "executable", "-o", output_path.to_str().unwrap()]);
+            assert!(yotc(source, name, &args).status.success());
+            let elf_type = fs::read(&output_path).unwrap()[16];
+            assert_eq!(
+                Command::new(output_path).output().unwrap().status.code(),
+                Some(1)
+            );
+            elf_type
+        };
+        const ET_EXEC: u8 = 2;
+        const ET_DYN: u8 = 3;
+        assert_eq!(
+            executable("position_independent_executable", &["--pic"]),
+            ET_DYN
+        );
+        assert_eq!(
+            executable("static_executable", &["--reloc", "static"]),
+            ET_EXEC
+        );
+    }
+}
+
+#[test]
+fn no_verify() {
+    // `foo` has no terminator, which fails verification
+    let source = "@foo[] { @a = 1; }\n@main[] { -> 0; }";
+    assert!(!yotc(source, "no_verify_failing", &["-f", "llvm"])
+        .status
+        .success());
+
+    let output_path = temp_path("no_verify", "ll");
+    let output = yotc(
+        source,
+        "no_verify",
+        &[
+            "--no-verify",
+            "-f",
+            "llvm",
+            "-o",
+            output_path.to_str().unwrap(),
+        ],
+    );
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Skipping verification"));
+    assert!(fs::read_to_string(output_path)
+        .unwrap()
+        .contains("define i32 @foo()"));
+}
+
+#[test]
+fn optimized_ir() {
+    let source = "@square[n] -> n * n;\n@main[] -> square(3) + 1;";
+    let ir = |level: &str| {
+        let name = format!("optimized_ir_{}", level);
+        let output_path = temp_path(&name, "ll");
+        let result = yotc(
+            source,
+            &name,
+            &[
+                "-O",
+                level,
+                "-f",
+                "llvm",
+                "-o",
+                output_path.to_str().unwrap(),
+            ],
+        );
+        assert!(result.status.success());
+        fs::read_to_string(output_path).unwrap()
+    };
+    let unoptimized = ir("0");
+    let optimized = ir("3");
+    assert!(unoptimized.contains("call i32 @square"));
+    // The call is inlined and folded into a constant
+    assert!(optimized.contains("ret i32 10"));
+    assert!(!optimized.contains("call i32 @square"));
+    assert!(optimized.lines().count() < unoptimized.lines().count());
+}
+
+#[test]
+fn code_model() {
+    let source = "@main[] { print(1); -> 1; }";
+    let output_path = temp_path("code_model", "s");
+    let result = yotc(
+        source,
+        "code_model",
+        &[
+            "--code-model",
+            "large",
+            "-f",
+            "assembly",
+            "-o",
+            output_path.to_str().unwrap(),
+        ],
+    );
+    assert!(result.status.success());
+    // Addresses can't be assumed to fit in 32 bits
+    assert!(fs::read_to_string(output_path).unwrap().contains("movabsq"));
+
+    let output = compile_and_run_with(source, "code_model", &["--code-model", "large"]);
+    assert_eq!(output.status.code(), Some(1));
+
+    assert!(
+        !yotc(source, "code_model_unknown", &["--code-model", "huge"])
+            .status
+            .success()
+    );
+}
+
+#[test]
+fn entry_point() {
+    let source = "@start[argc, argv] { print(argc); -> 3; }";
+    let output = compile_and_run_with(source, "entry_point", &["--entry", "start"]);
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1\n");
+
+    let output = yotc(source, "entry_point_missing", &["--check"]);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("No main function found"));
+
+    let output = yotc(
+        "@start[] -> 0; @main[] -> 1;",
+        "entry_point_conflict",
+        &["--entry", "start"],
+    );
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("Function `main` conflicts with the entry function `start`"));
+}
+
+#[test]
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+fn freestanding() {
+    // Without libc, the program exits with a Linux x86-64 system call of its own
+    let exit_source = temp_path("freestanding_exit", "c");
+    let exit_object = temp_path("freestanding_exit", "o");
+    fs::write(
+        &exit_source,
+        "void exit_now(int code) {
+            __asm__ volatile(\"syscall\" : : \"a\"(60), \"D\"(code));
+            __builtin_unreachable();
+        }",
+    )
+    .unwrap();
+    let status = Command::new("gcc")
+        .args(["-c", "-ffreestanding", "-fno-pie"])
+        .arg(&exit_source)
+        .arg("-o")
+        .arg(&exit_object)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let source = "@!noreturn exit_now[code]; @start[] { exit_now(min(7, 9)); -> 0; }";
+    let executable = temp_path("freestanding", "out");
+    let output = yotc(
+        source,
+        "freestanding",
+        &[
+            "--freestanding",
+            "--entry",
+            "start",
+            "--link-obj",
+            exit_object.to_str().unwrap(),
+            "-o",
+            executable.to_str().unwrap(),
+        ],
+    );
+    assert!(output.status.success());
+    let output = Command::new(executable).output().unwrap();
+    assert_eq!(output.status.code(), Some(7));
+
+    // libc isn't linked
+    let output = yotc(
+        "@!puts[s: str]; @main[] { puts(\"hello\"); -> 0; }",
+        "freestanding_libc",
+        &["--freestanding"],
+    );
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("undefined reference to `puts'"));
+
+    let output = yotc(
+        "@main[] { print(1); -> 0; }",
+        "freestanding_builtin",
+        &["--freestanding"],
+    );
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("Built-in `print` calls libc, which freestanding programs don't have"));
+}
+
+#[test]
+fn deterministic_ir() {
+    let source = "@total = 0;
+    $Point[x, y]
+    @main[] {
+        @a = 1; @b = 2; @c = 3; @d = 4; @e = 5; @f = 6; @g = 7; @h = 8;
+        { @x = a + b; @y = c + d; @z = e + f; print(x, y, z, g, h); }
+        @p = Point(a, b);
+        puts(\"one\"); puts(\"two\"); puts(\"one\");
+        total = p.x + p.y;
+        -> total;
+    }";
+    let ir = |run: &str| {
+        let output_path = temp_path(&format!("deterministic_ir_{}", run), "ll");
+        let output = yotc(
+            source,
+            "deterministic_ir",
+            &[
+                "-g",
+                "-O0",
+                "-f",
+                "llvm",
+                "-o",
+                output_path.to_str().unwrap(),
+            ],
+        );
+        assert!(output.status.success());
+        fs::read(output_path).unwrap()
+    };
+    assert_eq!(ir("first"), ir("second"));
+}
+
+#[test]
+fn output_to_stdout() {
+    let source = "@main[] { print(1); -> 0; }";
+    let output = yotc(source, "output_to_stdout", &["-f", "llvm", "-o", "-"]);
+    assert!(output.status.success());
+    let ir = String::from_utf8(output.stdout).unwrap();
+    assert!(ir.contains("define i32 @main()"));
+
+    // The printed IR is complete, so it can be piped into LLVM tools if they're installed
+    if let Ok(mut assembler) = Command::new("llvm-as")
+        .args(["-o", "/dev/null"])
+        .stdin(Stdio::piped())
+        .spawn()
+    {
+        assembler
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(ir.as_bytes())
+            .unwrap();
+        assert!(assembler.wait().unwrap().success());
+    }
+
+    let output = yotc(source, "output_to_stdout", &["-f", "assembly", "-o", "-"]);
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout).unwrap().contains("main:"));
+
+    // Binary formats can't be written to stdout
+    let output = yotc(
+        source,
+        "output_to_stdout",
+        &["-f", "object-file", "-o", "-"],
+    );
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("stdout"));
+}
+
+#[test]
+fn shared_library() {
+    let output_path = temp_path("shared_library", "so");
+    let result = yotc(
+        "@scale = 3;\n@triple[n] -> n * scale;",
+        "shared_library",
+        &["-f", "shared", "-o", output_path.to_str().unwrap()],
+    );
+    assert!(result.status.success());
+
+    let path = std::ffi::CString::new(output_path.to_str().unwrap()).unwrap();
+    unsafe {
+        let library = libc::dlopen(path.as_ptr(), libc::RTLD_NOW);
+        assert!(!library.is_null());
+        let symbol = libc::dlsym(library, "triple\0".as_ptr() as *const libc::c_char);
+        assert!(!symbol.is_null());
+        let triple: extern "C" fn(i32) -> i32 = std::mem::transmute(symbol);
+        assert_eq!(triple(14), 42);
+        libc::dlclose(library);
+    }
+}
+
+#[test]
+fn emit_all() {
+    let output = temp_path("emit_all", "out");
+    for extension in &["ll", "s", "o", "out"] {
+        let _ = fs::remove_file(temp_path("emit_all", extension));
+    }
+    let result = yotc(
+        "@main[] { -> 0; }",
+        "emit_all",
+        &["--emit-all", "-f", "llvm", "-o", output.to_str().unwrap()],
+    );
+    assert!(result.status.success());
+    assert!(temp_path("emit_all", "ll").exists());
+    assert!(temp_path("emit_all", "s").exists());
+    assert!(temp_path("emit_all", "o").exists());
+    assert!(output.exists());
+}
+
+#[test]
+fn repl() {
+    let mut yotc = Command::new(env!("CARGO_BIN_EXE_yotc"))
+        .arg("--repl")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    yotc.stdin
+        .take()
+        .unwrap()
+        .write_all(
+            b"@square[x] -> x * x;\nsquare(7) + 1\nundefined(1)\nprint(square(3));\n2 * 21\n",
+        )
+        .unwrap();
+    let output = yotc.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "50\n9\n42\n");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("undefined"));
+}
+
+#[test]
+fn time_passes() {
+    let ir = temp_path("time_passes", "ll");
+    let output = yotc(
+        "@main[] -> 0;",
+        "time_passes",
+        &["--time-passes", "-f", "llvm", "-o", ir.to_str().unwrap()],
+    );
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut lines = stdout.lines();
+    assert_eq!(lines.next(), Some("***PASS TIMES***"));
+    let passes = lines
+        .map(|line| line.split("  ").next().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        passes,
+        vec![
+            "Lexing",
+            "Parsing",
+            "Type Checking",
+            "Constant Folding",
+            "Code Generation",
+            "Verification",
+            "Optimization",
+            "Emission"
+        ]
+    );
+}
+
+#[test]
+fn stats() {
+    let source = "@!abs[x];\n\n@main[] {\n    @a = 1;\n    ?[a] { a++; }\n    -> a;\n}\n";
+    let output = yotc(source, "stats", &["--stats", "--check"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stats = stdout
+        .lines()
+        .skip_while(|line| *line != "***STATS***")
+        .skip(1)
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        stats,
+        vec!["Tokens 29", "Functions 1", "Statements 4", "Lines 7"]
+    );
+}
+
+#[test]
+fn multiple_parse_errors() {
+    let output = yotc(
+        "@double[n m] -> n * 2;\n@main[] {\n    @a = 5 + ;\n    -> a;\n}\n@unused = 1;",
+        "multiple_parse_errors",
+        &["--message-format", "json"],
+    );
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let errors = stderr
+        .lines()
+        .filter(|line| line.contains("\"error\""))
+        .collect::<Vec<_>>();
+    assert_eq!(errors.len(), 2);
+    assert!(errors[0].contains("\"line\":1") && errors[0].contains("after function `double`"));
+    assert!(errors[1].contains("\"line\":3") && errors[1].contains("Unable to parse expression"));
+}
+
+#[test]
+fn error_caret() {
+    let output = yotc(
+        "@main[] {\n    @a = 5 + ;\n    -> a;\n}",
+        "error_caret",
+        &["--color", "always"],
+    );
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("\x1b["));
+
+    // Remove the ANSI escape codes
+    let mut plain = String::new();
+    let mut chars = stderr.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            chars.by_ref().find(|&c| c == 'm');
+        } else {
+            plain.push(c);
+        }
+    }
+    let lines = plain.lines().collect::<Vec<_>>();
+    assert_eq!(lines[0], "error: Parsing: Unable to parse expression");
+    assert!(lines[1].ends_with("yotc_error_caret.yot:2:14"));
+    assert_eq!(lines[3], "2 |     @a = 5 + ;");
+    // The caret is under the `;` that ends the expression too early
+    assert_eq!(lines[4].find('^'), lines[3].find(';'));
+
+    let output = yotc(
+        "@main[] { @a = 5 + ; }",
+        "error_caret_never",
+        &["--color", "never"],
+    );
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("\x1b["));
+}
+
+#[test]
+fn json_messages() {
+    let output = yotc(
+        "@main[] {\n    -> 0 #;\n}",
+        "json_messages_lexing",
+        &["--message-format", "json", "--check"],
+    );
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let diagnostic: serde_json::Value = serde_json::from_str(stderr.trim()).unwrap();
+    assert!(diagnostic["file"]
+        .as_str()
+        .unwrap()
+        .ends_with("yotc_json_messages_lexing.yot"));
+    assert_eq!(diagnostic["line"], 2);
+    assert_eq!(diagnostic["col"], 10);
+    assert_eq!(diagnostic["severity"], "error");
+    assert!(diagnostic["message"]
+        .as_str()
+        .unwrap()
+        .starts_with("Lexing: Unknown token"));
+
+    // Type errors are at the expression they were found in
+    let output = yotc(
+        "@main[] -> x;",
+        "json_messages_type_checking",
+        &["--message-format", "json", "--check"],
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let diagnostic: serde_json::Value = serde_json::from_str(stderr.trim()).unwrap();
+    assert_eq!(diagnostic["line"], 1);
+    assert_eq!(diagnostic["col"], 12);
+    assert_eq!(
+        diagnostic["message"],
+        "Type Checking: Undefined variable `x` in function `main` at line 1"
+    );
+}
@@ -0,0 +1,40 @@
+extern crate yotc;
+
+/// Get the resident memory of the process in bytes.
+#[cfg(target_os = "linux")]
+fn resident_memory() -> usize {
+    let statm = std::fs::read_to_string("/proc/self/statm").unwrap();
+    let pages: usize = statm.split_whitespace().nth(1).unwrap().parse().unwrap();
+    pages * unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize
+}
+
+/// Check that emitting an object file many times doesn't leak memory.
+#[cfg(target_os = "linux")]
+fn main() {
+    let output = std::env::temp_dir().join("yotc_repeated_object_file_emission.o");
+    let compile = || unsafe {
+        let generator = yotc::compile_str("@main[] -> 0;", "repeated_emission").unwrap();
+        generator
+            .generate_object_file(2, output.to_str().unwrap())
+            .unwrap();
+    };
+
+    // The first compiles initialize LLVM's global state
+    for _ in 0..10 {
+        compile();
+    }
+    let before = resident_memory();
+    for _ in 0..200 {
+        compile();
+    }
+    let growth = resident_memory().saturating_sub(before);
+    // Leaking a target machine on every compile grows memory by hundreds of kilobytes each
+    assert!(growth < 32 * 1024 * 1024, "memory grew by {} bytes", growth);
+    println!(
+        "repeated object file emission grew memory by {} bytes",
+        growth
+    );
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {}
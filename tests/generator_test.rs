@@ -0,0 +1,598 @@
+extern crate yotc;
+
+use std::{env, fs};
+use yotc::generator::Generator;
+use yotc::lexer::Lexer;
+use yotc::parser::const_eval::fold_const_calls;
+use yotc::parser::program::Program;
+use yotc::parser::Parser;
+
+/// Lex and parse a program.
+fn parse(source: &str) -> Program {
+    let tokens = Lexer::from_text(source)
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    Parser::new(tokens.into_iter().peekable())
+        .parse_program()
+        .unwrap()
+}
+
+/// Generate and verify a program, returning the LLVM IR.
+fn generate_ir(source: &str, name: &str) -> String {
+    generate_program_ir(parse(source), name)
+}
+
+/// Generate and verify a parsed program, returning the LLVM IR.
+fn generate_program_ir(program: Program, name: &str) -> String {
+    let output = env::temp_dir().join(format!("yotc_{}.ll", name));
+    unsafe {
+        let generator = Generator::new(program, name);
+        generator.generate().unwrap();
+        generator.verify().unwrap();
+        generator.generate_ir(output.to_str().unwrap()).unwrap();
+    }
+    fs::read_to_string(output).unwrap()
+}
+
+#[test]
+fn f64_variable() {
+    let ir = generate_ir("@main[] { @x: f64; @y: f64 = x; -> 0; }", "f64_variable");
+    assert!(ir.contains("alloca double"));
+    assert!(ir.contains("load double"));
+    assert!(ir.contains("store double"));
+}
+
+#[test]
+fn array_literal() {
+    let ir = generate_ir("@main[] { @a = [1, 2, 3]; -> 0; }", "array_literal");
+    assert!(ir.contains("alloca i32, i32 3"));
+    assert!(ir.contains("alloca i32*"));
+}
+
+#[test]
+fn typed_array_literal() {
+    let ir = generate_ir(
+        "@main[] { @a: [i64] = [1, 2]; -> 0; }",
+        "typed_array_literal",
+    );
+    assert!(ir.contains("alloca i64, i32 2"));
+    assert!(ir.contains("store i64 1, i64*"));
+}
+
+#[test]
+fn array_literal_in_loop() {
+    let ir = generate_ir(
+        "@main[] { @i = 0; { @a = [i, i + 1]; i = a[1]; } ~ [i < 10]; -> 0; }",
+        "array_literal_in_loop",
+    );
+    // The array is allocated once in the entry block, not on every iteration
+    let entry_block = ir.split("loop:").next().unwrap();
+    assert!(entry_block.contains("alloca i32, i32 2"));
+}
+
+#[test]
+fn array_index_read() {
+    let ir = generate_ir("@main[] { @a = [1, 2, 3]; -> a[1]; }", "array_index_read");
+    assert!(ir.contains("getelementptr i32, i32*"));
+    assert!(ir.contains("load i32, i32*"));
+}
+
+#[test]
+fn array_index_write() {
+    let ir = generate_ir(
+        "@main[] { @a = [1, 2, 3]; a[0] = 5; -> a[0]; }",
+        "array_index_write",
+    );
+    assert!(ir.contains("store i32 5, i32*"));
+}
+
+#[test]
+fn integer_literal_widths() {
+    let ir = generate_ir(
+        "@main[] { @a: i8 = 5; @b: i16 = 5; @c: i32 = 5; @d: i64 = 5; -> 0; }",
+        "integer_literal_widths",
+    );
+    assert!(ir.contains("store i8 5, i8*"));
+    assert!(ir.contains("store i16 5, i16*"));
+    assert!(ir.contains("store i32 5, i32*"));
+    assert!(ir.contains("store i64 5, i64*"));
+    assert!(!ir.contains("sext"));
+}
+
+#[test]
+fn integer_literal_assignment_width() {
+    let ir = generate_ir(
+        "@main[] { @a: i64; a = (1 + 2) * 3; -> 0; }",
+        "integer_literal_assignment_width",
+    );
+    assert!(ir.contains("store i64 9, i64*"));
+}
+
+#[test]
+fn integer_literal_too_wide() {
+    let program = parse("@main[] { @a: i8 = 300; -> 0; }");
+    unsafe {
+        let generator = Generator::new(program, "integer_literal_too_wide");
+        assert_eq!(
+            generator.generate(),
+            Err("Integer literal 300 doesn't fit in an i8".to_string())
+        );
+    }
+}
+
+#[test]
+fn integer_literal_minimum() {
+    let ir = generate_ir(
+        "@main[] { @a: i8 = -128; -> -2147483648; }",
+        "integer_literal_minimum",
+    );
+    assert!(ir.contains("store i8 -128, i8*"));
+    assert!(ir.contains("ret i32 -2147483648"));
+}
+
+#[test]
+fn division_by_zero() {
+    for (i, source) in ["10 / 0", "10 / (5 - 5)"].iter().enumerate() {
+        let program = parse(&format!("@main[] {{ -> {}; }}", source));
+        unsafe {
+            let generator = Generator::new(program, &format!("division_by_zero_{}", i));
+            assert_eq!(generator.generate(), Err("division by zero".to_string()));
+        }
+    }
+}
+
+#[test]
+fn verification_error_names_function() {
+    let program = parse("@foo[] { @a = 1; } @main[] { -> 0; }");
+    unsafe {
+        let generator = Generator::new(program, "verification_error_names_function");
+        let error = generator.generate().unwrap_err();
+        assert!(error.starts_with("Verification failed in 'foo': "));
+    }
+}
+
+#[test]
+fn void_function_bare_return() {
+    let ir = generate_ir(
+        "@log[a]: void { ?[a < 0] ->; print(a); } @main[] { log(1); -> 0; }",
+        "void_function_bare_return",
+    );
+    assert!(ir.contains("define void @log(i32 %a)"));
+    assert_eq!(ir.matches("ret void").count(), 2);
+    assert!(ir.contains("call void @log(i32 1)"));
+}
+
+#[test]
+fn bare_return_in_i32_function() {
+    let program = parse("@main[] { ->; }");
+    unsafe {
+        let generator = Generator::new(program, "bare_return_in_i32_function");
+        assert_eq!(
+            generator.generate(),
+            Err("Function `main` must return a value".to_string())
+        );
+    }
+}
+
+#[test]
+fn string_literals_share_global() {
+    let ir = generate_ir(
+        "@!puts[s: [i8]]; @greet[] -> puts(\"hi\"); @main[] { puts(\"hi\"); -> greet(); }",
+        "string_literals_share_global",
+    );
+    assert_eq!(ir.matches("c\"hi\\00\"").count(), 1);
+}
+
+#[test]
+fn block_expression() {
+    let ir = generate_ir("@main[] { -> { @x = 5; x + 1 }; }", "block_expression");
+    assert!(ir.contains("store i32 5, i32*"));
+    assert!(ir.contains("add i32 %"));
+
+    let program = parse("@main[] { @y = { @x = 5; x }; -> x; }");
+    unsafe {
+        let generator = Generator::new(program, "block_expression_scope");
+        assert_eq!(
+            generator.generate(),
+            Err("Unresolved variable reference `x`".to_string())
+        );
+    }
+}
+
+#[test]
+fn block_variable_shadowing() {
+    let ir = generate_ir(
+        "@main[] { @x = 1; { @x: i64 = 2; x = x + 1; } -> x; }",
+        "block_variable_shadowing",
+    );
+    assert!(ir.contains("store i64 2, i64*"));
+    assert!(ir.contains("add i64 %"));
+    assert!(ir.contains("load i32, i32*"));
+
+    let program = parse("@main[] { @x = 1; @x = 2; -> x; }");
+    unsafe {
+        let generator = Generator::new(program, "block_variable_redeclaration");
+        assert_eq!(
+            generator.generate(),
+            Err("Variable `x` already exists".to_string())
+        );
+    }
+}
+
+#[test]
+fn global_variable() {
+    let ir = generate_ir(
+        "@count: i64 = 2 * 5; @increment[] { count = count + 1; -> 0; } \
+         @main[] { increment(); @x: i64 = count; -> 0; }",
+        "global_variable",
+    );
+    assert!(ir.contains("@count = global i64 10"));
+    assert!(ir.contains("load i64, i64* @count"));
+    assert!(ir.contains("store i64 %"));
+}
+
+#[test]
+fn global_variable_shadowed_by_local() {
+    let ir = generate_ir(
+        "@x = 1; @main[] { @x = 2; -> x; }",
+        "global_variable_shadowed_by_local",
+    );
+    assert!(!ir.contains("load i32, i32* @x"));
+}
+
+#[test]
+fn object_file_emit_error() {
+    let program = parse("@main[] -> 0;");
+    let output = env::temp_dir().join("yotc_missing_directory/object_file_emit_error.o");
+    unsafe {
+        let generator = Generator::new(program, "object_file_emit_error");
+        generator.generate().unwrap();
+        let error = generator
+            .generate_object_file(0, output.to_str().unwrap())
+            .unwrap_err();
+        assert!(error.contains("No such file or directory"));
+    }
+}
+
+#[test]
+fn emit_object_and_assembly_files() {
+    let program = parse("@main[] -> 0;");
+    let object_file = env::temp_dir().join("yotc_emit_object_and_assembly_files.o");
+    let assembly_file = env::temp_dir().join("yotc_emit_object_and_assembly_files.s");
+    unsafe {
+        let generator = Generator::new(program, "emit_object_and_assembly_files");
+        generator.generate().unwrap();
+        generator
+            .generate_object_file(2, object_file.to_str().unwrap())
+            .unwrap();
+        generator
+            .generate_assembly_file(2, assembly_file.to_str().unwrap())
+            .unwrap();
+    }
+    assert!(object_file.exists());
+    assert!(fs::read_to_string(assembly_file).unwrap().contains("main:"));
+}
+
+#[test]
+fn min_max_abs_builtins() {
+    let ir = generate_ir(
+        "@main[] { @a = 3; @b: u32 = 4; @c: f64 = a as f64; -> min(a, 5) + max(b, 1) + abs(a) + abs(c) as i32; }",
+        "min_max_abs_builtins",
+    );
+    assert!(ir.contains("icmp slt i32 %"));
+    assert!(ir.contains("icmp ult i32 %"));
+    assert!(ir.contains("fcmp olt double %"));
+    assert!(ir.contains("fneg double %"));
+    assert!(ir.contains("select i1 %"));
+
+    let program = parse("@main[] -> min(1);");
+    let error = unsafe { Generator::new(program, "min_arity").generate() };
+    assert_eq!(
+        error,
+        Err("Built-in `min` expects 2 arguments, got 1".to_string())
+    );
+}
+
+#[test]
+fn exponent() {
+    let ir = generate_ir(
+        "@main[] { @a = 2; @b = 3; @f: f64 = 1; -> a ** b ** a + (f ** f) as i32; }",
+        "exponent",
+    );
+    assert!(ir.contains("phi i32 [ 1, %"));
+    assert!(ir.contains("mul i32 %"));
+    assert!(ir.contains("call double @llvm.pow.f64(double %"));
+}
+
+#[test]
+fn empty_function() {
+    let ir = generate_ir(
+        "@none[]: void {} @wide[]: i64 {} @main[] {}",
+        "empty_function",
+    );
+    assert!(ir.contains("ret void"));
+    assert!(ir.contains("ret i64 0"));
+    assert!(ir.contains("ret i32 0"));
+}
+
+#[test]
+fn size_of() {
+    let ir = generate_ir(
+        "@int[]: i64 -> sizeof(i32);
+        @pair[]: i64 -> sizeof((i8, i64));
+        @array[]: i64 -> sizeof([i32]);
+        @main[] -> sizeof(i16);",
+        "size_of",
+    );
+    assert!(ir.contains("ret i64 4"));
+    assert!(ir.contains("ret i64 16"));
+    // Arrays are references
+    assert!(ir.contains("ret i64 8"));
+    assert!(ir.contains("ret i32 2"));
+}
+
+#[test]
+fn string_variable() {
+    let ir = generate_ir(
+        "@!puts[s: str]; @greet[s: str]: str { puts(s); -> s; } \
+         @main[] { @s: str = \"hi\"; @t = greet(s); -> len(t); }",
+        "string_variable",
+    );
+    assert!(ir.contains("alloca i8*"));
+    assert!(ir.contains("define i8* @greet(i8* %s)"));
+    assert!(ir.contains("call i32 @puts(i8* %"));
+    assert!(ir.contains("call i64 @strlen(i8* %"));
+}
+
+#[test]
+fn post_increment_requires_variable() {
+    let program = parse("@main[] { -> 5++; }");
+    unsafe {
+        let generator = Generator::new(program, "post_increment_requires_variable");
+        assert_eq!(
+            generator.generate(),
+            Err("Expected variable reference, index, or field on `++`".to_string())
+        );
+    }
+}
+
+#[test]
+fn const_variable_assignment() {
+    let ir = generate_ir("@main[] { @const x = 5; -> x + 1; }", "const_variable");
+    assert!(ir.contains("store i32 5, i32*"));
+
+    for (i, source) in ["x = 6", "x++", "@y = { x = 6; x }"].iter().enumerate() {
+        let program = parse(&format!("@main[] {{ @const x = 5; {}; -> x; }}", source));
+        unsafe {
+            let generator = Generator::new(program, &format!("const_variable_assignment_{}", i));
+            assert_eq!(
+                generator.generate(),
+                Err("cannot assign to const 'x'".to_string())
+            );
+        }
+    }
+}
+
+#[test]
+fn tuple_return_and_destructure() {
+    let ir = generate_ir(
+        "@divmod[a, b]: (i32, i32) -> (a / b, a - a / b * b);
+        @wide[]: (i8, i64) -> (1, 2);
+        @main[] { @q, @r = divmod(7, 2); @_, @w = wide(); -> q + r; }",
+        "tuple_return_and_destructure",
+    );
+    assert!(ir.contains("define { i32, i32 } @divmod(i32 %a, i32 %b)"));
+    assert!(ir.contains("insertvalue { i32, i32 }"));
+    assert!(ir.contains("ret { i8, i64 } { i8 1, i64 2 }"));
+    assert!(ir.contains("extractvalue { i32, i32 } %"));
+    assert!(ir.contains("alloca i64"));
+
+    let program = parse("@pair[]: (i32, i32) -> (1, 2); @main[] { @a, @b, @c = pair(); -> a; }");
+    unsafe {
+        let generator = Generator::new(program, "tuple_destructure_count");
+        assert_eq!(
+            generator.generate(),
+            Err("Expected a tuple of 3 values".to_string())
+        );
+    }
+}
+
+#[test]
+fn mutual_recursion() {
+    let ir = generate_ir(
+        "@main[] -> even(10);
+        @even[n] -> (n == 0) + odd(n - 1);
+        @odd[n] -> (n != 0) * even(n - 1);",
+        "mutual_recursion",
+    );
+    assert!(ir.contains("call i32 @odd(i32"));
+    assert!(ir.contains("call i32 @even(i32"));
+}
+
+#[test]
+fn char_literal_comparison() {
+    let ir = generate_ir(
+        "@is_a[c] -> c == 'a'; @main[] -> is_a('b');",
+        "char_literal_comparison",
+    );
+    assert!(ir.contains("icmp eq i32 %1, 97"));
+    assert!(ir.contains("call i32 @is_a(i32 98)"));
+}
+
+#[test]
+fn if_statement() {
+    let ir = generate_ir(
+        "@sign[n] { ?[n < 0] -> -1; : ?[n > 0] -> 1; -> 0; } @main[] -> sign(5);",
+        "if_statement",
+    );
+    assert!(ir.contains("br i1"));
+}
+
+#[test]
+fn switch_statement() {
+    let ir = generate_ir(
+        "@dispatch[n] { %[n] { 1: -> 10; 2: -> 20; 3: -> 30; _: -> 0; } }
+        @main[] -> dispatch(2);",
+        "switch_statement",
+    );
+    assert!(ir.contains("switch i32 %"));
+    assert!(ir.contains("i32 1, label %case"));
+    assert!(ir.contains("i32 2, label %case"));
+    assert!(ir.contains("i32 3, label %case"));
+}
+
+#[test]
+fn do_while_statement() {
+    let ir = generate_ir(
+        "@main[] { @n = 0; { @step = 1; n = n + step; } ~[n < 0]; -> n; }",
+        "do_while_statement",
+    );
+    // The body is entered unconditionally and the condition branches back to it
+    assert!(ir.contains("br label %loop"));
+    assert!(ir.contains("label %loop, label %merge"));
+    let entry = ir.split("loop:").next().unwrap();
+    assert_eq!(entry.matches("alloca i32").count(), 2);
+}
+
+#[test]
+fn unsigned_arithmetic() {
+    let ir = generate_ir(
+        "@half[n: u32]: u32 -> n / 2;
+        @main[] { @x: u32 = 0xFFFFFFFF; @y = half(x); @z = 0xFFFFFFFF; -> (y < 1) + (z < 1) + z / 2; }",
+        "unsigned_arithmetic",
+    );
+    assert!(ir.contains("udiv i32 %"));
+    assert!(ir.contains("icmp ult i32 %"));
+    assert!(ir.contains("icmp slt i32 %"));
+    assert!(ir.contains("sdiv i32 %"));
+}
+
+#[test]
+fn mixed_width_arithmetic() {
+    let ir = generate_ir(
+        "@main[] { @a = 2147483647; @b = a + 1i64; @c: i64 = a; -> b > c; }",
+        "mixed_width_arithmetic",
+    );
+    assert!(ir.contains("sext i32 %"));
+    assert!(ir.contains("add i64 %"));
+    assert!(ir.contains("icmp sgt i64 %"));
+}
+
+#[test]
+fn cast_expression() {
+    let ir = generate_ir(
+        "@main[] { @x = 7; @f = x as f64; @u = 200 as u32; -> f as i32 + (x as i64 + u as f64 as i64) as i32; }",
+        "cast_expression",
+    );
+    assert!(ir.contains("sitofp i32 %"));
+    assert!(ir.contains("fptosi double %"));
+    assert!(ir.contains("uitofp i32 %"));
+    assert!(ir.contains("sext i32 %"));
+}
+
+#[test]
+fn nested_function() {
+    let ir = generate_ir(
+        "@main[] { @square[n] -> n * n; -> square(3) + square(4); }",
+        "nested_function",
+    );
+    assert!(ir.contains("define i32 @main.square(i32"));
+    assert!(ir.contains("call i32 @main.square(i32 3)"));
+}
+
+#[test]
+fn const_function_folding() {
+    let mut program = parse(
+        "@const fib[n] { ?[n < 2] -> n; : -> fib(n - 1) + fib(n - 2); }
+        @main[] { @x = 10; -> fib(10) + fib(x); }",
+    );
+    fold_const_calls(&mut program);
+    let ir = generate_program_ir(program, "const_function_folding");
+    assert!(ir.contains("define i32 @fib(i32 %n)"));
+    assert!(ir.contains("add i32 55, %"));
+    assert!(!ir.contains("call i32 @fib(i32 10)"));
+}
+
+#[test]
+fn struct_field_access() {
+    let ir = generate_ir(
+        "$Point[x, y: i64] @at[x]: Point -> Point(x, 0);
+        @main[] { @p = at(1); p.y = p.x + 3; -> p.x + at(2).x; }",
+        "struct_field_access",
+    );
+    assert!(ir.contains("%Point = type { i32, i64 }"));
+    assert!(ir.contains("insertvalue %Point"));
+    assert!(ir.contains("getelementptr inbounds %Point, %Point* %"));
+    assert!(ir.contains("store i64"));
+    assert!(ir.contains("extractvalue %Point"));
+}
+
+#[test]
+fn pointer_round_trip() {
+    let ir = generate_ir(
+        "@set[p: *i64, v: i64]: void { *p = v; }
+        @main[] { @x: i64 = 1; @p = &x; set(p, 2); -> (*p) as i32; }",
+        "pointer_round_trip",
+    );
+    assert!(ir.contains("define void @set(i64* %p, i64 %v)"));
+    assert!(ir.contains("store i64 %3, i64* %2"));
+    assert!(ir.contains("store i64* %1, i64** %0"));
+    assert!(ir.contains("call void @set(i64* %2, i64 2)"));
+}
+
+#[test]
+fn address_of_requires_variable() {
+    let program = parse("@main[] { @p = &5; -> 0; }");
+    unsafe {
+        let generator = Generator::new(program, "address_of_requires_variable");
+        assert_eq!(
+            generator.generate(),
+            Err("Expected variable reference, index, or field on `&`".to_string())
+        );
+    }
+}
+
+#[test]
+fn function_attributes() {
+    let ir = generate_ir(
+        "@!noreturn exit[code];
+        @inline square[n] -> n * n;
+        @noinline cube[n] -> n * n * n;
+        @main[n] {
+            ?[n > 1] { exit(1); }
+            -> square(n) + cube(n);
+        }",
+        "function_attributes",
+    );
+    assert!(ir.contains("declare i32 @exit(i32) #0"));
+    assert!(ir.contains("define i32 @square(i32 %n) #1"));
+    assert!(ir.contains("define i32 @cube(i32 %n) #2"));
+    assert!(ir.contains("attributes #0 = { noreturn }"));
+    assert!(ir.contains("attributes #1 = { alwaysinline }"));
+    assert!(ir.contains("attributes #2 = { noinline }"));
+    // The block can't continue after a call that doesn't return
+    assert!(ir.contains("call i32 @exit(i32 1)\n  unreachable"));
+}
+
+#[test]
+fn compile_str() {
+    let generator = yotc::compile_str("@main[] -> clamp(5, 0, 3);", "compile_str").unwrap();
+    let ir = unsafe { generator.ir_string() };
+    // The prelude is added the same way as by the compiler
+    assert!(ir.contains("define i32 @clamp("));
+
+    assert_eq!(
+        yotc::compile_str("@main[] -> 'a", "compile_str_lexing").map(|_| ()),
+        Err("Lexing: Unterminated character literal".to_string())
+    );
+    assert!(yotc::compile_str("@main[] -> 1", "compile_str_parsing")
+        .map(|_| ())
+        .unwrap_err()
+        .starts_with("Parsing: "));
+    assert!(
+        yotc::compile_str("@main[] -> x;", "compile_str_type_checking")
+            .map(|_| ())
+            .unwrap_err()
+            .starts_with("Type Checking: Undefined variable `x`")
+    );
+}
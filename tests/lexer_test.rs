@@ -1,3 +1,362 @@
 extern crate yotc;
 
-use yotc::lexer::Lexer;
\ No newline at end of file
+use std::io::Cursor;
+use yotc::lexer::tokens::{Literal, Span, Token};
+use yotc::lexer::Lexer;
+
+#[test]
+fn lex_variable_declaration() {
+    let tokens = Lexer::from_text("@a = 5;")
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(
+        tokens,
+        vec![
+            Token::Symbol("@".to_string()),
+            Token::Identifier("a".to_string()),
+            Token::Symbol("=".to_string()),
+            Token::Literal(Literal::Integer(5)),
+            Token::Symbol(";".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn lex_integer_literal_boundary() {
+    let tokens = Lexer::from_text("2147483647")
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(tokens, vec![Token::Literal(Literal::Integer(2147483647))]);
+}
+
+#[test]
+fn lex_integer_literal_overflow() {
+    let tokens = Lexer::from_text("2147483648").collect::<Result<Vec<_>, _>>();
+    assert_eq!(
+        tokens,
+        Err("Integer literal 2147483648 exceeds i32 range".to_string())
+    );
+}
+
+#[test]
+fn lex_negated_integer_literal_minimum() {
+    let tokens = Lexer::from_text("-> -2147483648")
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(
+        tokens,
+        vec![
+            Token::Symbol("->".to_string()),
+            Token::Symbol("-".to_string()),
+            Token::Literal(Literal::Integer(i32::MIN)),
+        ]
+    );
+
+    // A binary minus doesn't negate the literal
+    let tokens = Lexer::from_text("a -2147483648").collect::<Result<Vec<_>, _>>();
+    assert_eq!(
+        tokens,
+        Err("Integer literal 2147483648 exceeds i32 range".to_string())
+    );
+}
+
+#[test]
+fn lex_char_literals() {
+    let tokens = Lexer::from_text(r"'a' '\n' '\'' '\\'")
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(
+        tokens,
+        vec![
+            Token::Literal(Literal::Char('a')),
+            Token::Literal(Literal::Char('\n')),
+            Token::Literal(Literal::Char('\'')),
+            Token::Literal(Literal::Char('\\')),
+        ]
+    );
+}
+
+#[test]
+fn lex_empty_char_literal() {
+    let tokens = Lexer::from_text("''").collect::<Result<Vec<_>, _>>();
+    assert_eq!(tokens, Err("Empty character literal".to_string()));
+}
+
+#[test]
+fn lex_multi_char_literal() {
+    let tokens = Lexer::from_text("'ab'").collect::<Result<Vec<_>, _>>();
+    assert_eq!(
+        tokens,
+        Err("Character literal 'ab' contains more than one character".to_string())
+    );
+}
+
+#[test]
+fn lex_radix_integer_literals() {
+    let tokens = Lexer::from_text("0xFF 0o17 0b1010 0 10")
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(
+        tokens,
+        vec![
+            Token::Literal(Literal::Integer(255)),
+            Token::Literal(Literal::Integer(15)),
+            Token::Literal(Literal::Integer(10)),
+            Token::Literal(Literal::Integer(0)),
+            Token::Literal(Literal::Integer(10)),
+        ]
+    );
+}
+
+#[test]
+fn lex_radix_integer_literal_u32_range() {
+    let tokens = Lexer::from_text("0xFFFFFFFF 0x8000_0000")
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(
+        tokens,
+        vec![
+            Token::Literal(Literal::Integer(-1)),
+            Token::Literal(Literal::Integer(i32::MIN)),
+        ]
+    );
+
+    let tokens = Lexer::from_text("0x1_0000_0000").collect::<Result<Vec<_>, _>>();
+    assert_eq!(
+        tokens,
+        Err("Integer literal 0x1_0000_0000 exceeds u32 range".to_string())
+    );
+}
+
+#[test]
+fn lex_sized_integer_literals() {
+    let tokens = Lexer::from_text("5i64 0xFFi8 -> -128i8 3_000_000_000i64")
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(
+        tokens,
+        vec![
+            Token::Literal(Literal::SizedInteger { value: 5, bits: 64 }),
+            Token::Literal(Literal::SizedInteger { value: -1, bits: 8 }),
+            Token::Symbol("->".to_string()),
+            Token::Symbol("-".to_string()),
+            Token::Literal(Literal::SizedInteger {
+                value: -128,
+                bits: 8
+            }),
+            Token::Literal(Literal::SizedInteger {
+                value: 3_000_000_000,
+                bits: 64
+            }),
+        ]
+    );
+
+    let tokens = Lexer::from_text("128i8").collect::<Result<Vec<_>, _>>();
+    assert_eq!(
+        tokens,
+        Err("Integer literal 128i8 exceeds i8 range".to_string())
+    );
+    let tokens = Lexer::from_text("5i7").collect::<Result<Vec<_>, _>>();
+    assert_eq!(
+        tokens,
+        Err("Invalid suffix `i7` on integer literal 5i7".to_string())
+    );
+}
+
+#[test]
+fn lex_invalid_radix_digit() {
+    let tokens = Lexer::from_text("0b12").collect::<Result<Vec<_>, _>>();
+    assert_eq!(
+        tokens,
+        Err("Invalid digit `2` in binary integer literal 0b12".to_string())
+    );
+}
+
+#[test]
+fn lex_digit_separators() {
+    let tokens = Lexer::from_text("1_000 0xFF_FF 0b1_0")
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(
+        tokens,
+        vec![
+            Token::Literal(Literal::Integer(1000)),
+            Token::Literal(Literal::Integer(65535)),
+            Token::Literal(Literal::Integer(2)),
+        ]
+    );
+}
+
+#[test]
+fn lex_misplaced_digit_separators() {
+    for literal in &["1__0", "5_", "0x_F"] {
+        let tokens = Lexer::from_text(literal).collect::<Result<Vec<_>, _>>();
+        assert_eq!(
+            tokens,
+            Err(format!(
+                "Integer literal {} has a misplaced `_` separator",
+                literal
+            ))
+        );
+    }
+}
+
+#[test]
+fn lex_from_reader() {
+    let source = "@main[] {\n    @a = 'x'; // comment\n    -> a + 0x10;\n}\n";
+    let streamed = Lexer::from_reader(Cursor::new(source.as_bytes().to_vec()))
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    let text = Lexer::from_text(source)
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(streamed, text);
+}
+
+#[test]
+fn lex_unknown_token_position() {
+    let tokens = Lexer::from_text("@main[] {\n    #\n}").collect::<Result<Vec<_>, _>>();
+    assert_eq!(
+        tokens,
+        Err("Unknown token: # at line 2, column 5".to_string())
+    );
+
+    let tokens = Lexer::from_text("@main[] { # }").collect::<Result<Vec<_>, _>>();
+    assert_eq!(
+        tokens,
+        Err("Unknown token: # at line 1, column 11".to_string())
+    );
+}
+
+#[test]
+fn lex_tab_width_position() {
+    let error = |source: &str, tab_width| {
+        Lexer::from_text(source)
+            .with_tab_width(tab_width)
+            .collect::<Result<Vec<_>, _>>()
+    };
+    assert_eq!(
+        error("@main[] {\n\t#\n}", 1),
+        Err("Unknown token: # at line 2, column 2".to_string())
+    );
+    assert_eq!(
+        error("@main[] {\n\t#\n}", 4),
+        Err("Unknown token: # at line 2, column 5".to_string())
+    );
+    // A tab after other characters only advances to the next tab stop
+    assert_eq!(
+        error("@main[] {\n  \t\t#\n}", 8),
+        Err("Unknown token: # at line 2, column 17".to_string())
+    );
+}
+
+#[test]
+fn lex_unterminated_string() {
+    let tokens = Lexer::from_text("@s: str = \"abc").collect::<Result<Vec<_>, _>>();
+    assert_eq!(
+        tokens,
+        Err("Unterminated string literal at line 1, column 11".to_string())
+    );
+
+    let tokens = Lexer::from_text("\"abc\"").collect::<Result<Vec<_>, _>>();
+    assert_eq!(
+        tokens,
+        Ok(vec![Token::Literal(Literal::Str("abc".to_string()))])
+    );
+}
+
+#[test]
+fn lex_multiline_string() {
+    let source = "@s: str = \"\"\"\nfirst \"line\"\n    second \\\n    line\"\"\";\n@";
+    let mut lexer = Lexer::from_text(source);
+    let tokens = lexer
+        .by_ref()
+        .take(6)
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(
+        tokens[5],
+        Token::Literal(Literal::Str("first \"line\"\n    second line".to_string()))
+    );
+    assert_eq!(lexer.next(), Some(Ok(Token::Symbol(";".to_string()))));
+    assert_eq!(lexer.next(), Some(Ok(Token::Symbol("@".to_string()))));
+    assert_eq!(lexer.span(), Span { line: 5, column: 1 });
+
+    let tokens = Lexer::from_text("\"\" \"\"\"\"\"\"").collect::<Result<Vec<_>, _>>();
+    assert_eq!(
+        tokens,
+        Ok(vec![
+            Token::Literal(Literal::Str(String::new())),
+            Token::Literal(Literal::Str(String::new())),
+        ])
+    );
+
+    let tokens = Lexer::from_text("@s: str = \"\"\"abc\"\"").collect::<Result<Vec<_>, _>>();
+    assert_eq!(
+        tokens,
+        Err("Unterminated string literal at line 1, column 11".to_string())
+    );
+}
+
+#[test]
+fn lex_comments() {
+    let source = "@a = 5; // five\n// end";
+    let tokens = Lexer::from_text(source)
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert!(!tokens.iter().any(|t| matches!(t, Token::Comment(_))));
+
+    let mut lexer = Lexer::from_text(source).with_comments();
+    let mut comments = Vec::new();
+    while let Some(token) = lexer.next() {
+        if let Token::Comment(text) = token.unwrap() {
+            comments.push((text, lexer.span()));
+        }
+    }
+    assert_eq!(
+        comments,
+        vec![
+            (" five".to_string(), Span { line: 1, column: 9 }),
+            (" end".to_string(), Span { line: 2, column: 1 }),
+        ]
+    );
+}
+
+#[test]
+fn display_tokens() {
+    let display = |token: Token| token.to_string();
+    assert_eq!(display(Token::Identifier("x".to_string())), "x");
+    assert_eq!(display(Token::Symbol("->".to_string())), "->");
+    assert_eq!(display(Token::Comment(" note".to_string())), "// note");
+    assert_eq!(display(Token::Literal(Literal::Integer(-5))), "-5");
+    assert_eq!(
+        display(Token::Literal(Literal::SizedInteger { value: 7, bits: 64 })),
+        "7i64"
+    );
+    assert_eq!(display(Token::Literal(Literal::Char('a'))), "'a'");
+    assert_eq!(display(Token::Literal(Literal::Char('\''))), "'\\''");
+    assert_eq!(display(Token::Literal(Literal::Char('\n'))), "'\\n'");
+    assert_eq!(
+        display(Token::Literal(Literal::Str("say hi\n".to_string()))),
+        "\"say hi\n\""
+    );
+
+    // Displayed tokens lex back into the same tokens
+    let source = "@s: str = \"a b\"; @c = '\\''; @n = 3i8; @m = -2 + 0x10; // end";
+    let tokens = Lexer::from_text(source)
+        .with_comments()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    let displayed = tokens
+        .iter()
+        .map(|t| t.to_string())
+        .collect::<Vec<_>>()
+        .join(" ");
+    assert_eq!(
+        Lexer::from_text(&displayed)
+            .with_comments()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap(),
+        tokens
+    );
+}
@@ -0,0 +1,799 @@
+extern crate yotc;
+
+use yotc::lexer::tokens::{Literal, Span};
+use yotc::lexer::Lexer;
+use yotc::parser::expression::Expression;
+use yotc::parser::function::{Function, FunctionAttribute};
+use yotc::parser::program::Program;
+use yotc::parser::statement::Statement;
+use yotc::parser::typecheck::typecheck;
+use yotc::parser::types::Type;
+use yotc::parser::Parser;
+
+/// Lex and parse a single function.
+fn parse_function(source: &str) -> Function {
+    let tokens = Lexer::from_text(source)
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    Parser::new(tokens.into_iter().peekable())
+        .parse_function()
+        .unwrap()
+}
+
+/// Lex and parse a single expression.
+fn parse_expression(source: &str) -> Expression {
+    let tokens = Lexer::from_text(source)
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    Parser::new(tokens.into_iter().peekable())
+        .parse_expression()
+        .unwrap()
+}
+
+/// Lex and parse a program with token positions.
+fn parse_program_with_spans(source: &str) -> Program {
+    let mut lexer = Lexer::from_text(source);
+    let mut tokens = Vec::new();
+    let mut spans = Vec::new();
+    while let Some(token) = lexer.next() {
+        tokens.push(token.unwrap());
+        spans.push(lexer.span());
+    }
+    Parser::with_spans(tokens, spans).parse_program().unwrap()
+}
+
+/// Lex, parse, and type check a program with token positions, returning the error message.
+fn typecheck_program(source: &str) -> Result<(), String> {
+    typecheck(&parse_program_with_spans(source)).map_err(|(e, _)| e)
+}
+
+#[test]
+fn parse_void_function_bare_return() {
+    match parse_function("@log[a]: void { print(a); ->; }") {
+        Function::RegularFunction {
+            return_type,
+            statement,
+            ..
+        } => {
+            assert_eq!(return_type, None);
+            match *statement {
+                Statement::CompoundStatement { statements } => assert_eq!(
+                    statements.last(),
+                    Some(&Statement::ReturnStatement {
+                        value: None,
+                        span: Span::default()
+                    })
+                ),
+                s => panic!("Expected compound statement, got {:?}", s),
+            }
+        }
+        f => panic!("Expected regular function, got {:?}", f),
+    }
+}
+
+#[test]
+fn parse_skips_comments() {
+    let tokens = Lexer::from_text("@main[] { // start\n -> 0; }")
+        .with_comments()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    let program = Parser::new(tokens.into_iter().peekable())
+        .parse_program()
+        .unwrap();
+    assert!(program.function("main").is_some());
+}
+
+#[test]
+fn parse_const_variable() {
+    match parse_function("@main[] { @const x: i64 = 5; @const = 1; -> 0; }") {
+        Function::RegularFunction { statement, .. } => match *statement {
+            Statement::CompoundStatement { statements } => {
+                assert!(matches!(
+                    &statements[0],
+                    Statement::VariableDeclarationStatement { name, is_const: true, .. } if name == "x"
+                ));
+                assert!(matches!(
+                    &statements[1],
+                    Statement::VariableDeclarationStatement { name, is_const: false, .. } if name == "const"
+                ));
+            }
+            s => panic!("Expected compound statement, got {:?}", s),
+        },
+        f => panic!("Expected regular function, got {:?}", f),
+    }
+}
+
+#[test]
+fn parse_const_global() {
+    let parse = |source: &str| {
+        let tokens = Lexer::from_text(source)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        Parser::new(tokens.into_iter().peekable())
+            .parse_program()
+            .map(|_| ())
+    };
+    let error = Err("Const declarations are only allowed inside functions".to_string());
+    assert_eq!(parse("@const k = 3; @main[] -> k;"), error);
+    assert_eq!(parse("@const k: i64 = 3; @main[] -> 0;"), error);
+    assert_eq!(parse("@const k[] -> 3; @main[] -> k();"), Ok(()));
+}
+
+#[test]
+fn parse_unary_minus_precedence() {
+    let int = |i| {
+        Box::new(Expression::LiteralExpression {
+            value: Literal::Integer(i),
+            span: Span::default(),
+        })
+    };
+    let binary = |op: &str, l_expression, r_expression| Expression::BinaryExpression {
+        op: op.to_string(),
+        l_expression,
+        r_expression,
+        span: Span::default(),
+    };
+    let negate = |expression| Expression::UnaryExpression {
+        op: "-".to_string(),
+        expression: Box::new(expression),
+        span: Span::default(),
+    };
+
+    assert_eq!(parse_expression("-2 * 3"), binary("*", int(-2), int(3)));
+    assert_eq!(parse_expression("-2 + 3"), binary("+", int(-2), int(3)));
+    assert_eq!(
+        parse_expression("-(2 + 3)"),
+        negate(Expression::ParenExpression {
+            expression: Box::new(binary("+", int(2), int(3))),
+            span: Span::default()
+        })
+    );
+    assert_eq!(parse_expression("- -5"), negate(*int(-5)));
+    assert_eq!(
+        parse_expression("-x * 3"),
+        binary(
+            "*",
+            Box::new(negate(Expression::VariableReferenceExpression {
+                name: "x".to_string(),
+                span: Span::default()
+            })),
+            int(3)
+        )
+    );
+}
+
+#[test]
+fn parse_exponent_right_associative() {
+    let int = |i| {
+        Box::new(Expression::LiteralExpression {
+            value: Literal::Integer(i),
+            span: Span::default(),
+        })
+    };
+    let power = |l_expression, r_expression| Expression::BinaryExpression {
+        op: "**".to_string(),
+        l_expression,
+        r_expression,
+        span: Span::default(),
+    };
+
+    assert_eq!(
+        parse_expression("2 ** 3 ** 2"),
+        power(int(2), Box::new(power(int(3), int(2))))
+    );
+    assert_eq!(
+        parse_expression("2 ** 3 * 4"),
+        Expression::BinaryExpression {
+            op: "*".to_string(),
+            l_expression: Box::new(power(int(2), int(3))),
+            r_expression: int(4),
+            span: Span::default(),
+        }
+    );
+    assert_eq!(
+        parse_expression("-2 ** 2"),
+        Expression::UnaryExpression {
+            op: "-".to_string(),
+            expression: Box::new(power(int(2), int(2))),
+            span: Span::default(),
+        }
+    );
+}
+
+#[test]
+fn parse_function_attributes() {
+    match parse_function("@const noinline noreturn fail[]: void {}") {
+        Function::RegularFunction {
+            name,
+            is_const,
+            attributes,
+            ..
+        } => {
+            assert_eq!(name, "fail");
+            assert!(is_const);
+            assert_eq!(
+                attributes,
+                vec![FunctionAttribute::NoInline, FunctionAttribute::NoReturn]
+            );
+        }
+        f => panic!("Expected regular function, got {:?}", f),
+    }
+    // An attribute keyword is only an attribute before the function name
+    match parse_function("@inline[inline] -> inline;") {
+        Function::RegularFunction {
+            name, attributes, ..
+        } => {
+            assert_eq!(name, "inline");
+            assert!(attributes.is_empty());
+        }
+        f => panic!("Expected regular function, got {:?}", f),
+    }
+
+    let parse = |source| {
+        let tokens = Lexer::from_text(source)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        Parser::new(tokens.into_iter().peekable())
+            .parse_function()
+            .map(|_| ())
+    };
+    assert_eq!(
+        parse("@inline noinline f[] -> 1;"),
+        Err("Function `f` can't be both `inline` and `noinline`".to_string())
+    );
+    assert_eq!(
+        parse("@inline inline f[] -> 1;"),
+        Err("Duplicate attribute `inline`".to_string())
+    );
+    assert_eq!(
+        parse("@!inline f[];"),
+        Err("External function `f` can't be `inline`".to_string())
+    );
+}
+
+#[test]
+fn parse_nesting_limit() {
+    let parse = |source: &str| {
+        let tokens = Lexer::from_text(source)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        Parser::new(tokens.into_iter().peekable())
+            .with_max_depth(10)
+            .parse_statement()
+            .map(|_| ())
+    };
+    let error = Err("Nesting too deep, more than 10 levels".to_string());
+
+    assert_eq!(
+        parse(&format!("{}1{};", "(".repeat(8), ")".repeat(8))),
+        Ok(())
+    );
+    assert_eq!(
+        parse(&format!("{}1{};", "(".repeat(10), ")".repeat(10))),
+        error
+    );
+    assert_eq!(
+        parse(&format!("{}{}", "{".repeat(11), "}".repeat(11))),
+        error
+    );
+    assert_eq!(parse(&format!("2{};", " ** 2".repeat(11))), error);
+    assert_eq!(
+        parse(&format!("@a: {}i32{};", "[".repeat(11), "]".repeat(11))),
+        error
+    );
+}
+
+#[test]
+fn parse_chained_comparison() {
+    let parse = |source| {
+        let tokens = Lexer::from_text(source)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        Parser::new(tokens.into_iter().peekable())
+            .parse_expression()
+            .map(|_| ())
+    };
+
+    assert_eq!(
+        parse("1 < 2 < 3"),
+        Err(
+            "Chained comparison `<` then `<` isn't allowed, put one of the comparisons in parentheses"
+                .to_string()
+        )
+    );
+    assert_eq!(
+        parse("a == b + 1 != c"),
+        Err(
+            "Chained comparison `==` then `!=` isn't allowed, put one of the comparisons in parentheses"
+                .to_string()
+        )
+    );
+    assert_eq!(parse("(1 < 2) < 3"), Ok(()));
+    assert_eq!(parse("x = a < b"), Ok(()));
+}
+
+#[test]
+fn parse_size_of() {
+    assert_eq!(
+        parse_expression("sizeof([i64])"),
+        Expression::SizeOfExpression {
+            target_type: Type::Array(Box::new(Type::I64)),
+            span: Span::default()
+        }
+    );
+    // `sizeof` is only special when it is followed by `(`
+    assert_eq!(
+        parse_expression("sizeof"),
+        Expression::VariableReferenceExpression {
+            name: "sizeof".to_string(),
+            span: Span::default()
+        }
+    );
+}
+
+#[test]
+fn parse_if_else() {
+    let parse = |source| {
+        let tokens = Lexer::from_text(source)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        Parser::new(tokens.into_iter().peekable()).parse_function()
+    };
+
+    assert_eq!(
+        parse("@main[] { : -> 0; }").map(|_| ()),
+        Err("Unexpected `:`, else without a matching if".to_string())
+    );
+    assert_eq!(
+        parse("@main[n] { ?[n] -> 1; -> 2; : -> 0; }").map(|_| ()),
+        Err("Unexpected `:`, else without a matching if".to_string())
+    );
+
+    // The statement after an if without an else isn't a part of the if
+    match parse("@main[n] { ?[n] -> 1; -> 2; }").unwrap() {
+        Function::RegularFunction { statement, .. } => match *statement {
+            Statement::CompoundStatement { statements } => {
+                assert_eq!(statements.len(), 2);
+                match &statements[0] {
+                    Statement::IfStatement { else_statement, .. } => {
+                        assert!(else_statement.is_none())
+                    }
+                    s => panic!("Expected if statement, got {:?}", s),
+                }
+                assert!(matches!(statements[1], Statement::ReturnStatement { .. }));
+            }
+            s => panic!("Expected compound statement, got {:?}", s),
+        },
+        f => panic!("Expected regular function, got {:?}", f),
+    }
+
+    // An else if is an if as the statement of an else
+    match parse("@main[n] { ?[n == 1] -> 1; : ?[n == 2] -> 2; : -> 3; }").unwrap() {
+        Function::RegularFunction { statement, .. } => match *statement {
+            Statement::CompoundStatement { statements } => match &statements[..] {
+                [Statement::IfStatement {
+                    else_statement: Some(else_statement),
+                    ..
+                }] => match &**else_statement {
+                    Statement::IfStatement { else_statement, .. } => {
+                        assert!(else_statement.is_some())
+                    }
+                    s => panic!("Expected if statement, got {:?}", s),
+                },
+                s => panic!("Expected one if else statement, got {:?}", s),
+            },
+            s => panic!("Expected compound statement, got {:?}", s),
+        },
+        f => panic!("Expected regular function, got {:?}", f),
+    }
+}
+
+#[test]
+fn parse_switch_statement() {
+    match parse_function("@main[n] { %[n] { 1: -> 10; -2: { -> 20; } _: -> 0; } }") {
+        Function::RegularFunction { statement, .. } => match *statement {
+            Statement::CompoundStatement { statements } => match &statements[0] {
+                Statement::SwitchStatement { cases, default, .. } => {
+                    assert_eq!(
+                        cases.iter().map(|(case, _)| *case).collect::<Vec<_>>(),
+                        vec![1, -2]
+                    );
+                    assert!(default.is_some());
+                }
+                s => panic!("Expected switch statement, got {:?}", s),
+            },
+            s => panic!("Expected compound statement, got {:?}", s),
+        },
+        f => panic!("Expected regular function, got {:?}", f),
+    }
+
+    let tokens = Lexer::from_text("@main[n] { %[n] { 1: ; 1: ; } -> 0; }")
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(
+        Parser::new(tokens.into_iter().peekable())
+            .parse_function()
+            .map(|_| ()),
+        Err("Duplicate case `1` in switch statement".to_string())
+    );
+}
+
+#[test]
+fn typecheck_wildcards() {
+    assert_eq!(
+        typecheck_program(
+            "@f[_, _] -> 1; @g[_: i64, x, _: str] -> x; @main[] { @_ = f(1, 2); @_: i64 = 3i64; -> g(1i64, 2, \"s\"); }"
+        ),
+        Ok(())
+    );
+    assert_eq!(
+        typecheck_program("@f[_, b] -> _; @main[] -> f(1, 2);"),
+        Err("Undefined variable `_` in function `f` at line 1".to_string())
+    );
+}
+
+#[test]
+fn parse_extern_then_definition() {
+    let parse = |source: &str| {
+        let tokens = Lexer::from_text(source)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        Parser::new(tokens.into_iter().peekable()).parse_program()
+    };
+
+    let program = parse("@!noreturn stop[c];\n@main[] -> 0;\n@stop[code] { -> 0; }").unwrap();
+    assert_eq!(program.functions.len(), 2);
+    match program.function("stop") {
+        Some(Function::RegularFunction { attributes, .. }) => {
+            assert_eq!(attributes, &vec![FunctionAttribute::NoReturn])
+        }
+        f => panic!("Expected a regular function, got {:?}", f),
+    }
+
+    assert_eq!(
+        parse("@!foo[x, y];\n@main[] -> 0;\n@foo[x] -> x;").map(|_| ()),
+        Err("Function `foo` takes 1 arguments, but its external declaration takes 2".to_string())
+    );
+    assert_eq!(
+        parse("@!foo[x: i64];\n@main[] -> 0;\n@foo[x] -> x;").map(|_| ()),
+        Err("Argument `x` of `foo` is i32, but i64 in its external declaration".to_string())
+    );
+    assert_eq!(
+        parse("@!foo[x];\n@main[] -> 0;\n@foo[x]: void {}").map(|_| ()),
+        Err("Function `foo` returns void, but its external declaration returns i32".to_string())
+    );
+    assert_eq!(
+        parse("@!foo[x, ...];\n@main[] -> 0;\n@foo[x] -> x;").map(|_| ()),
+        Err("Function `foo` isn't variadic, but its external declaration is".to_string())
+    );
+}
+
+#[test]
+fn parse_recovery() {
+    let tokens = Lexer::from_text("@f[] { @a = (1; { -> a; } } @g[] -> 1; $S[x y] @h[] -> ];")
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    let mut parser = Parser::new(tokens.into_iter().peekable());
+    assert!(parser.parse_program().is_err());
+    let errors = parser
+        .errors()
+        .iter()
+        .map(|(e, _)| e.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        errors,
+        vec![
+            "Expected `)` after expression",
+            "Expected `]` or `,` after field in struct `S`",
+            "Unable to parse expression",
+        ]
+    );
+}
+
+#[test]
+fn parse_nested_function() {
+    let parse = |source: &str| {
+        let tokens = Lexer::from_text(source)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        Parser::new(tokens.into_iter().peekable()).parse_program()
+    };
+
+    let program = parse("@main[] { @x = 2; @double[n] -> n * 2; -> double(x); }").unwrap();
+    assert!(program.function("double").is_none());
+    assert!(program.function("main.double").is_some());
+    typecheck(&program).unwrap();
+
+    assert_eq!(
+        parse("@main[] { @x = 2; @add_x[n] -> n + x; -> add_x(1); }").map(|_| ()),
+        Err(
+            "Nested function `add_x` can't use `x` of `main`, variables can't be captured"
+                .to_string()
+        )
+    );
+}
+
+#[test]
+fn parse_struct() {
+    let tokens = Lexer::from_text("$Point[x, y: i64] @main[] { @p = Point(1, 2); -> p.x; }")
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    let program = Parser::new(tokens.into_iter().peekable())
+        .parse_program()
+        .unwrap();
+    let point = program.structure("Point").unwrap();
+    assert_eq!(
+        point.fields,
+        vec![("x".to_string(), Type::I32), ("y".to_string(), Type::I64)]
+    );
+
+    assert_eq!(
+        parse_expression("p.x = a.b.c"),
+        Expression::BinaryExpression {
+            op: "=".to_string(),
+            l_expression: Box::new(Expression::FieldAccessExpression {
+                expression: Box::new(Expression::VariableReferenceExpression {
+                    name: "p".to_string(),
+                    span: Span::default()
+                }),
+                field: "x".to_string(),
+                span: Span::default(),
+            }),
+            r_expression: Box::new(Expression::FieldAccessExpression {
+                expression: Box::new(Expression::FieldAccessExpression {
+                    expression: Box::new(Expression::VariableReferenceExpression {
+                        name: "a".to_string(),
+                        span: Span::default()
+                    }),
+                    field: "b".to_string(),
+                    span: Span::default(),
+                }),
+                field: "c".to_string(),
+                span: Span::default(),
+            }),
+            span: Span::default(),
+        }
+    );
+}
+
+#[test]
+fn typecheck_struct() {
+    assert_eq!(
+        typecheck_program(
+            "$Point[x, y: i64] @origin[]: Point -> Point(0, 0);
+            @main[] { @p: Point = origin(); p.y = p.x + 1; -> p.x; }"
+        ),
+        Ok(())
+    );
+    assert_eq!(
+        typecheck_program("$Point[x, y] @main[] { @p = Point(1, 2); -> p.z; }"),
+        Err("Struct `Point` has no field `z` in function `main` at line 1".to_string())
+    );
+    assert_eq!(
+        typecheck_program("$Point[x, y] @main[] -> Point(1, \"2\").x;"),
+        Err("Field `y` of `Point` expects i32, got str in function `main` at line 1".to_string())
+    );
+    assert_eq!(
+        typecheck_program("$Node[value, next: Node] @main[] -> 0;"),
+        Err("Field `next` makes the struct contain itself in struct `Node`".to_string())
+    );
+    assert_eq!(
+        typecheck_program("@main[] { @p: Pointt; -> 0; }"),
+        Err("Unknown type `Pointt` in function `main` at line 1".to_string())
+    );
+}
+
+#[test]
+fn parse_pointer_operators() {
+    let reference = |name: &str| {
+        Box::new(Expression::VariableReferenceExpression {
+            name: name.to_string(),
+            span: Span::default(),
+        })
+    };
+    let deref = |expression| Expression::UnaryExpression {
+        op: "*".to_string(),
+        expression: Box::new(expression),
+        span: Span::default(),
+    };
+    assert_eq!(
+        parse_expression("*p = **q * 2"),
+        Expression::BinaryExpression {
+            op: "=".to_string(),
+            l_expression: Box::new(deref(*reference("p"))),
+            r_expression: Box::new(Expression::BinaryExpression {
+                op: "*".to_string(),
+                l_expression: Box::new(deref(deref(*reference("q")))),
+                r_expression: Box::new(Expression::LiteralExpression {
+                    value: Literal::Integer(2),
+                    span: Span::default()
+                }),
+                span: Span::default(),
+            }),
+            span: Span::default(),
+        }
+    );
+    assert_eq!(
+        parse_expression("&a[0]"),
+        Expression::UnaryExpression {
+            op: "&".to_string(),
+            expression: Box::new(Expression::IndexExpression {
+                array: reference("a"),
+                index: Box::new(Expression::LiteralExpression {
+                    value: Literal::Integer(0),
+                    span: Span::default()
+                }),
+                span: Span::default(),
+            }),
+            span: Span::default(),
+        }
+    );
+}
+
+#[test]
+fn typecheck_pointers() {
+    assert_eq!(
+        typecheck_program(
+            "@swap[a: *i32, b: *i32]: void { @t = *a; *a = *b; *b = t; }
+            @main[] { @x = 1; @y = 2; @p: **i32 = &(&x); swap(&x, &y); -> x; }"
+        ),
+        Err(
+            "Expected variable reference, index, or field on `&` in function `main` at line 2"
+                .to_string()
+        )
+    );
+    assert_eq!(
+        typecheck_program(
+            "@swap[a: *i32, b: *i32]: void { @t = *a; *a = *b; *b = t; }
+            @main[] { @x = 1; @y = 2; @p = &x; @pp: **i32 = &p; swap(*pp, &y); -> x; }"
+        ),
+        Ok(())
+    );
+    assert_eq!(
+        typecheck_program("@main[] { @x = 1; @p: *i64 = &x; -> *x; }"),
+        Err("Can't assign *i32 to `p` of type *i64 in function `main` at line 1".to_string())
+    );
+    assert_eq!(
+        typecheck_program("@main[] { @x = 1; -> *x; }"),
+        Err("Can't dereference i32 in function `main` at line 1".to_string())
+    );
+}
+
+#[test]
+fn typecheck_valid_program() {
+    assert_eq!(
+        typecheck_program(
+            "@!puts[s: str]; @count: i64 = 1;
+            @greet[s: str, n: i64]: void { puts(s); count = count + n; }
+            @main[] { @a = [1, 2]; a[0]++; greet(\"hi\", a[1]); print(len(\"hi\")); -> a[0]; }"
+        ),
+        Ok(())
+    );
+}
+
+#[test]
+fn typecheck_cast() {
+    assert_eq!(
+        typecheck_program("@main[] { @f = 7 as f64; @i: i8 = f as i8; -> i as i32 + 1; }"),
+        Ok(())
+    );
+    assert_eq!(
+        typecheck_program("@main[] -> \"7\" as i32;"),
+        Err("Can't cast str to i32 in function `main` at line 1".to_string())
+    );
+}
+
+#[test]
+fn typecheck_main_signature() {
+    assert_eq!(typecheck_program("@main[] { -> 0; }"), Ok(()));
+    assert_eq!(
+        typecheck_program("@main[argc, argv] -> argc + len(argv[0]);"),
+        Ok(())
+    );
+    assert_eq!(
+        typecheck_program("@main[x] { -> x; }"),
+        Err(
+            "`main` must have no arguments or `[argc, argv]`, not `[x: i32]` in function `main` \
+             at line 1"
+                .to_string()
+        )
+    );
+    assert_eq!(
+        typecheck_program("@main[]: void {}"),
+        Err(
+            "`main` must return an i32 exit code, not void in function `main` at line 1"
+                .to_string()
+        )
+    );
+}
+
+#[test]
+fn typecheck_undefined_variable() {
+    let program = parse_program_with_spans("@main[] {\n    -> 0;\n}\n@foo[] {\n    -> x + 1;\n}");
+    assert_eq!(
+        typecheck(&program),
+        Err((
+            "Undefined variable `x` in function `foo` at line 5".to_string(),
+            Span { line: 5, column: 8 }
+        ))
+    );
+}
+
+#[test]
+fn typecheck_arity_mismatch() {
+    let program = parse_program_with_spans("@sum[a, b] -> a + b; @main[] -> sum(1);");
+    assert_eq!(
+        typecheck(&program),
+        Err((
+            "Function `sum` expects 2 arguments, got 1 in function `main` at line 1".to_string(),
+            Span {
+                line: 1,
+                column: 33
+            }
+        ))
+    );
+}
+
+#[test]
+fn typecheck_overloads() {
+    let overloads = "@foo[x] -> x; @foo[x, y] -> x + y;";
+    assert_eq!(
+        typecheck_program(&format!("{} @main[] -> foo(1) + foo(2, 3);", overloads)),
+        Ok(())
+    );
+    assert_eq!(
+        typecheck_program(&format!("{} @main[] -> foo(1, 2, 3);", overloads)),
+        Err(
+            "No overload of function `foo` takes 3 arguments in function `main` at line 1"
+                .to_string()
+        )
+    );
+    assert_eq!(
+        typecheck_program(&format!("{} @main[] {{ @f = foo; -> 0; }}", overloads)),
+        Err(
+            "Overloaded function `foo` can't be referenced without calling it in function `main` at line 1"
+                .to_string()
+        )
+    );
+    assert_eq!(
+        typecheck_program("@foo[x] -> x; @foo[y] -> y; @main[] -> 0;"),
+        Err(
+            "Function `foo` is defined more than once with 1 argument, overloads must have \
+             different numbers of arguments"
+                .to_string()
+        )
+    );
+    assert_eq!(
+        typecheck_program("@foo[x: i32, y] -> x; @foo[s: str, y] -> 0; @main[] -> 0;"),
+        Err(
+            "Function `foo` is defined more than once with 2 arguments, overloads must have \
+             different numbers of arguments"
+                .to_string()
+        )
+    );
+    assert_eq!(
+        typecheck_program("@!puts[s: str]; @puts[] -> 0; @puts[a, b] -> 0; @main[] -> 0;"),
+        Err("External function `puts` can't be overloaded".to_string())
+    );
+    assert_eq!(
+        typecheck_program("@main[] -> 0; @main[argc, argv] -> 0;"),
+        Err("The entry function `main` can't be overloaded".to_string())
+    );
+}
+
+#[test]
+fn typecheck_type_mismatch() {
+    assert_eq!(
+        typecheck_program("@main[] { @a = 1; a = \"hi\"; -> a; }"),
+        Err("Can't assign str to i32 in function `main` at line 1".to_string())
+    );
+    assert_eq!(
+        typecheck_program("@main[] { @a = 1; -> a(2); }"),
+        Err("`a` isn't a function in function `main` at line 1".to_string())
+    );
+    assert_eq!(
+        typecheck_program("@main[] { @a = [1, \"s\"]; -> 0; }"),
+        Err("Can't put str in an array of i32 in function `main` at line 1".to_string())
+    );
+    assert!(typecheck_program("@main[] { @a = [1, 'c', 2i64]; -> 0; }").is_ok());
+}